@@ -0,0 +1,172 @@
+use crate::{
+    error::Result,
+    fitness,
+    primitives::{ArcBytes, Index, Value},
+    query::{ActivityQuery, FingerprintQuery, UserQuery},
+    resource::index::{ContentHash, DeviceFile, ParentActivity, TopLevelActivity},
+    Database,
+};
+use tf_models::{
+    activity::{
+        BestEffort, Details, Device, Elevation, File, FileId, Histograms, Lap, Load, Pause,
+        PowerCurve, Record, RecordRange, Session, Swim,
+    },
+    user::{PersonalRecords, User},
+};
+
+/// The record of an activity, which for a sport of a multisport activity is
+/// sliced from its parent, see [`tf_models::Activity::children`].
+pub fn record(db: &Database, query: &ActivityQuery) -> Result<Option<Record>> {
+    let root = db.root::<User>()?;
+
+    if let Some(record) = root.traverse::<Record>()?.get(query)? {
+        return Ok(Some(record));
+    }
+
+    let range = match root.traverse::<RecordRange>()?.get(query)? {
+        Some(range) => range,
+        None => return Ok(None),
+    };
+
+    let parent = match root.traverse::<ParentActivity>()?.key(query)? {
+        Some(parent) => parent,
+        None => return Ok(None),
+    };
+
+    Ok(root
+        .traverse::<Record>()?
+        .get(&parent)?
+        .map(|x| x.slice(range.range())))
+}
+
+/// Like `record`, but without decoding the record if it is stored as it is.
+pub fn record_raw(db: &Database, query: &ActivityQuery) -> Result<Option<ArcBytes<'static>>> {
+    if let Some(buffer) = db.root::<Record>()?.get_raw(query)? {
+        return Ok(Some(buffer));
+    }
+
+    record(db, query)?
+        .map(|x| Ok(ArcBytes::from(x.as_bytes()?)))
+        .transpose()
+}
+
+/// Removes an activity and everything stored for it, including its sports,
+/// and takes it out of the fitness and personal
+/// records of the user. Returns whether there was such an activity.
+///
+/// Must be called with the lock of the user held, see `lock::user`.
+pub fn remove(db: &Database, query: &ActivityQuery) -> Result<bool> {
+    let root = db.root::<User>()?;
+
+    // Taken away while the load and session it is worked out from are still
+    // there
+    fitness::count_stress(db, query, -1.)?;
+
+    remove_children(db, query)?;
+    remove_best_efforts(db, query)?;
+
+    // Otherwise the same file could not be uploaded again, or would be found
+    // as the activity that takes the id next
+    remove_fingerprints(&root.traverse::<ContentHash>()?, query)?;
+    remove_fingerprints(&root.traverse::<DeviceFile>()?, query)?;
+
+    let session = root.traverse::<Session>()?.remove(query)?;
+    let record = root.traverse::<Record>()?.remove(query)?.map(drop);
+    let range = root.traverse::<RecordRange>()?.remove(query)?.map(drop);
+    let lap = root.traverse::<Vec<Lap>>()?.remove(query)?;
+    root.traverse::<File>()?.remove(query)?;
+    root.traverse::<FileId>()?.remove(query)?;
+    root.traverse::<Swim>()?.remove(query)?;
+    root.traverse::<Vec<Device>>()?.remove(query)?;
+    root.traverse::<Vec<Pause>>()?.remove(query)?;
+    root.traverse::<Details>()?.remove(query)?;
+    root.traverse::<Elevation>()?.remove(query)?;
+    root.traverse::<PowerCurve>()?.remove(query)?;
+    root.traverse::<Histograms>()?.remove(query)?;
+    root.traverse::<Load>()?.remove(query)?;
+    root.traverse::<ParentActivity>()?.remove(query)?;
+    root.traverse::<TopLevelActivity>()?.remove(query)?;
+
+    Ok(session.is_some() && record.or(range).is_some() && lap.is_some())
+}
+
+/// Removes the fingerprints that find an activity. They are looked up by
+/// the session of the activity, so this is done before it is removed.
+fn remove_fingerprints<T>(
+    index: &Index<FingerprintQuery, T, ActivityQuery, Session>,
+    query: &ActivityQuery,
+) -> Result<()> {
+    let total_count = index.join(query, 0, 0, false)?.total_count;
+
+    for fingerprint in index.join(query, 0, total_count, false)? {
+        index.remove(&fingerprint)?;
+    }
+
+    Ok(())
+}
+
+/// Removes the sports of an activity, see [`tf_models::Activity::children`].
+///
+/// Must be called with the lock of the user held, see `lock::user`.
+pub fn remove_children(db: &Database, parent: &ActivityQuery) -> Result<()> {
+    let root = db.root::<User>()?;
+    let index = root.traverse::<ParentActivity>()?;
+
+    let total_count = index.join(parent, 0, 0, false)?.total_count;
+
+    for child in index.join(parent, 0, total_count, false)? {
+        root.traverse::<Session>()?.remove(&child)?;
+        root.traverse::<Record>()?.remove(&child)?;
+        root.traverse::<RecordRange>()?.remove(&child)?;
+        root.traverse::<Vec<Lap>>()?.remove(&child)?;
+        root.traverse::<Vec<Pause>>()?.remove(&child)?;
+        root.traverse::<Swim>()?.remove(&child)?;
+        root.traverse::<Elevation>()?.remove(&child)?;
+        root.traverse::<PowerCurve>()?.remove(&child)?;
+        remove_best_efforts(db, &child)?;
+        root.traverse::<Load>()?.remove(&child)?;
+        root.traverse::<Histograms>()?.remove(&child)?;
+        index.remove(&child)?;
+    }
+
+    Ok(())
+}
+
+/// Removes the best efforts of an activity. If any of them were personal
+/// records, the records are made again from the efforts of the other
+/// activities, as those may have been records in their place.
+///
+/// Must be called with the lock of the user held, see `lock::user`.
+pub fn remove_best_efforts(db: &Database, query: &ActivityQuery) -> Result<()> {
+    let root = db.root::<User>()?;
+    let efforts = root.traverse::<Vec<BestEffort>>()?;
+    let collection = root.traverse::<PersonalRecords>()?;
+    let user = UserQuery {
+        user_id: query.user_id,
+    };
+
+    efforts.remove(query)?;
+
+    let mut records = collection.get(&user)?.unwrap_or_default();
+
+    if !records.remove(query.id) {
+        return Ok(());
+    }
+
+    let sessions = root.traverse::<Session>()?;
+    records = PersonalRecords::default();
+
+    let total_count = efforts.keys(&user, 0, 0, false)?.total_count;
+
+    for query in efforts.keys(&user, 0, total_count, false)? {
+        if let Some(session) = sessions.get(&query)? {
+            let efforts = efforts.get(&query)?.unwrap_or_default();
+
+            records.insert(query.id, session.start_time, &efforts);
+        }
+    }
+
+    collection.insert(&user, &records)?;
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use crate::{
+    error::Result,
+    query::{ActivityQuery, UserQuery},
+    resource::index::ParentActivity,
+    Database,
+};
+use tf_models::{
+    activity::{Load, Session},
+    user::{Fitness, Thresholds, User},
+};
+
+/// Adds the training stress of a stored activity to the fitness of the
+/// user, or takes it away with a `sign` of -1. Nothing is done for the
+/// sports of a multisport activity, see [`tf_models::Activity::children`].
+///
+/// Must be called with the lock of the user held, see `lock::user`.
+pub fn count_stress(db: &Database, query: &ActivityQuery, sign: f64) -> Result<()> {
+    let root = db.root::<User>()?;
+    let user = UserQuery {
+        user_id: query.user_id,
+    };
+
+    if root.traverse::<ParentActivity>()?.contains_key(query)? {
+        return Ok(());
+    }
+
+    let (load, session) = match (
+        root.traverse::<Load>()?.get(query)?,
+        root.traverse::<Session>()?.get(query)?,
+    ) {
+        (Some(load), Some(session)) => (load, session),
+        _ => return Ok(()),
+    };
+
+    let thresholds = root.traverse::<Thresholds>()?.get(&user)?;
+
+    if let Some((date, stress)) = thresholds.and_then(|x| x.stress(&load, &session)) {
+        let collection = root.traverse::<Fitness>()?;
+        let mut fitness = collection.get(&user)?.unwrap_or_default();
+
+        fitness.add(date, sign * stress);
+
+        collection.insert(&user, &fitness)?;
+    }
+
+    Ok(())
+}
+
+/// Works out the fitness of a user again from every activity, as the stress
+/// of each depends on the thresholds of its day.
+///
+/// Must be called with the lock of the user held, see `lock::user`.
+pub fn rebuild(db: &Database, user: &UserQuery) -> Result<()> {
+    let root = db.root::<User>()?;
+    let loads = root.traverse::<Load>()?;
+    let sessions = root.traverse::<Session>()?;
+    let parents = root.traverse::<ParentActivity>()?;
+    let thresholds = root
+        .traverse::<Thresholds>()?
+        .get(user)?
+        .unwrap_or_default();
+
+    let total_count = loads.keys(user, 0, 0, false)?.total_count;
+
+    let mut stress = Vec::new();
+
+    for query in loads.keys(user, 0, total_count, false)? {
+        if parents.contains_key(&query)? {
+            continue;
+        }
+
+        if let (Some(load), Some(session)) = (loads.get(&query)?, sessions.get(&query)?) {
+            stress.extend(thresholds.stress(&load, &session));
+        }
+    }
+
+    let collection = root.traverse::<Fitness>()?;
+    let mut fitness = collection.get(user)?.unwrap_or_default();
+
+    fitness.rebuild(stress);
+
+    collection.insert(user, &fitness)
+}
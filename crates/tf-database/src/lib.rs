@@ -1,4 +1,7 @@
+pub mod activity;
 pub mod error;
+pub mod fitness;
+pub mod lock;
 pub mod primitives;
 pub mod query;
 pub mod resource;
@@ -25,6 +28,12 @@ impl Database {
         self.db.compact()
     }
 
+    /// Brings a database written by an older version up to date.
+    pub fn migrate(&self) -> Result<()> {
+        self.db.migrate_activity_ids()?;
+        self.db.migrate_top_level_activities()
+    }
+
     pub fn root<R>(&self) -> Result<Root<'_, R, primitives::Tree<R::Key, R>>>
     where
         R: Resource,
@@ -0,0 +1,29 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tf_models::{query::UserQuery, UserId};
+
+// A lock for each user that has written anything
+static LOCKS: Mutex<Option<HashMap<UserId, Arc<Mutex<()>>>>> = Mutex::new(None);
+
+/// Runs `f` while holding the lock of the user. Taken by everything that
+/// reads, changes and writes back data of a user that is derived from their
+/// activities, such as the fitness and personal records, so that concurrent
+/// uploads, deletions and threshold changes do not overwrite each other.
+/// Writes of different users do not wait for each other.
+///
+/// The lock is not reentrant, so `f` must not take it again.
+pub fn user<T>(user: &UserQuery, f: impl FnOnce() -> T) -> T {
+    let lock = LOCKS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .entry(user.user_id)
+        .or_default()
+        .clone();
+
+    let _guard = lock.lock().unwrap();
+
+    f()
+}
@@ -8,12 +8,21 @@ pub use self::{
     value::Value,
 };
 
-use crate::{error::Result, Resource};
-use nebari::tree::Root;
+use crate::{
+    error::Result,
+    resource::index::{ParentActivity, TopLevelActivity},
+    Resource,
+};
+use nebari::tree::{Root, ScanEvaluation};
 pub use nebari::ArcBytes;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use tf_models::{activity::Session, ActivityId, UserId};
 
 pub type Inner = nebari::Roots<nebari::io::fs::StdFile>;
 
+// Holds a key for each migration that has run
+const MIGRATION_TREE: &str = "migration";
+
 #[derive(Clone, Copy, Debug)]
 struct LZ4Vault;
 
@@ -86,6 +95,160 @@ impl Database {
         Ok(())
     }
 
+    /// Rewrites keys of activities stored with the old twelve character ids,
+    /// which only held the start time to the minute in the time zone of the
+    /// server. The new ids are made from the stored session like those of
+    /// new activities, taking the next sequence number if one is in use.
+    /// Foreign keys held by indexes are rewritten to match. Runs once, after
+    /// which it is marked as done.
+    pub fn migrate_activity_ids(&self) -> Result<()> {
+        const LEGACY_LENGTH: usize = UserId::LENGTH + ActivityId::LENGTH - 4;
+        const MARKER: &[u8] = b"activity_ids";
+
+        let is_legacy = |key: &[u8]| {
+            key.len() == LEGACY_LENGTH && key[UserId::LENGTH..].iter().all(u8::is_ascii_digit)
+        };
+
+        let migrations = self
+            .inner
+            .tree(nebari::tree::Unversioned::tree(MIGRATION_TREE))?;
+
+        if migrations.get(MARKER)?.is_some() {
+            return Ok(());
+        }
+
+        let mut trees = Vec::new();
+
+        for name in self.inner.tree_names()? {
+            let is_index = name.ends_with("_index");
+            let tree = self.inner.tree(nebari::tree::Unversioned::tree(name))?;
+            let mut legacy = Vec::new();
+            let mut foreign = Vec::new();
+
+            tree.scan::<crate::error::Error, _, _, _, _>(
+                &(..),
+                true,
+                |_, _, _| ScanEvaluation::ReadData,
+                |key, _| {
+                    if is_legacy(key) {
+                        legacy.push(key.to_vec());
+                    }
+
+                    if is_index {
+                        ScanEvaluation::ReadData
+                    } else {
+                        ScanEvaluation::Skip
+                    }
+                },
+                |key, _, value| {
+                    if is_legacy(&value) {
+                        foreign.push(key.to_vec());
+                    }
+
+                    Ok(())
+                },
+            )?;
+
+            trees.push((tree, legacy, foreign));
+        }
+
+        let keys = self.migrated_activity_keys(
+            trees
+                .iter()
+                .flat_map(|(_, legacy, _)| legacy)
+                .collect::<BTreeSet<_>>(),
+        )?;
+
+        for (tree, legacy, foreign) in trees {
+            for key in foreign {
+                if let Some(value) = tree.get(&key)? {
+                    if let Some(new_value) = keys.get(value.as_slice()) {
+                        tree.set(key, new_value.clone())?;
+                    }
+                }
+            }
+
+            for key in legacy {
+                if let (Some(new_key), Some(value)) = (keys.get(&key), tree.get(&key)?) {
+                    tree.set(new_key.clone(), value)?;
+                    tree.remove(&key)?;
+                }
+            }
+        }
+
+        migrations.set(MARKER, b"done".as_slice())?;
+
+        Ok(())
+    }
+
+    /// The new key for each legacy activity key, in the order of the legacy
+    /// keys so that activities started within the same minute keep their
+    /// order. Without a session the seconds are not known and set to zero.
+    fn migrated_activity_keys(
+        &self,
+        legacy: BTreeSet<&Vec<u8>>,
+    ) -> Result<HashMap<Vec<u8>, Vec<u8>>> {
+        let sessions = self.open_resource::<Session>()?;
+        let mut keys = HashMap::new();
+        let mut taken = HashSet::new();
+
+        for key in legacy {
+            let (user_id, legacy_id) = key.split_at(UserId::LENGTH);
+
+            let mut id = sessions
+                .as_ref()
+                .get(key)?
+                .and_then(|x| Session::from_bytes(&x).ok())
+                .and_then(|x| ActivityId::from_start_time(&x.local_start_time()).ok())
+                .or_else(|| ActivityId::from_bytes(&[legacy_id, b"0000"].concat()).ok());
+
+            // Left as it is if all sequence numbers are in use
+            while let Some(candidate) = id {
+                let new_key = [user_id, candidate.as_bytes().as_slice()].concat();
+
+                if !taken.contains(&new_key) && sessions.as_ref().get(&new_key)?.is_none() {
+                    taken.insert(new_key.clone());
+                    keys.insert(key.clone(), new_key);
+                    break;
+                }
+
+                id = candidate.next_sequence();
+            }
+        }
+
+        Ok(keys)
+    }
+
+    /// Fills the index of top level activities for activities stored before
+    /// there was one. Runs once, after which it is marked as done.
+    pub fn migrate_top_level_activities(&self) -> Result<()> {
+        const MARKER: &[u8] = b"top_level_activities";
+
+        let migrations = self
+            .inner
+            .tree(nebari::tree::Unversioned::tree(MIGRATION_TREE))?;
+
+        if migrations.get(MARKER)?.is_some() {
+            return Ok(());
+        }
+
+        let sessions = self.open_resource::<Session>()?;
+        let parents = self.open_index::<ParentActivity, Session>()?;
+        let index = self.open_index::<TopLevelActivity, Session>()?;
+
+        let total_count = sessions.iter(0, 0, false)?.total_count;
+
+        for query in sessions.iter(0, total_count, false)? {
+            if !parents.contains_key(&query)? {
+                index.insert(&query, &query)?;
+            }
+        }
+
+        migrations.set(MARKER, b"done".as_slice())?;
+
+        Ok(())
+    }
+
     pub fn open_resource<R>(&self) -> Result<Tree<R::Key, R>>
     where
         R: Resource,
@@ -1,6 +1,6 @@
 use crate::{error::Result, primitives::Key};
-pub use tf_models::query::{ActivityQuery, ClientQuery, GearQuery, UserQuery};
-use tf_models::{ActivityId, ClientId, GearId, UserId};
+pub use tf_models::query::{ActivityQuery, ClientQuery, FingerprintQuery, GearQuery, UserQuery};
+use tf_models::{ActivityId, ClientId, GearId, InvalidLengthError, UserId};
 
 impl Key for ActivityQuery {
     fn as_key(&self) -> Vec<u8> {
@@ -48,6 +48,31 @@ impl Key for ClientQuery {
     }
 }
 
+impl Key for FingerprintQuery {
+    fn as_key(&self) -> Vec<u8> {
+        [
+            self.user_id.as_bytes().as_slice(),
+            self.fingerprint.as_slice(),
+        ]
+        .concat()
+    }
+
+    fn as_prefix(&self) -> [u8; UserId::LENGTH] {
+        self.user_id.as_bytes()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (prefix, suffix) = bytes.split_at(UserId::LENGTH);
+
+        Ok(Self {
+            user_id: UserId::from_bytes(prefix)?,
+            fingerprint: suffix
+                .try_into()
+                .map_err(|_| InvalidLengthError::new(32, suffix.len()))?,
+        })
+    }
+}
+
 impl Key for GearQuery {
     fn as_key(&self) -> Vec<u8> {
         [
@@ -1,7 +1,10 @@
 use super::Resource;
 use crate::{primitives::Relation, Traverse};
 use tf_models::{
-    activity::{Lap, Record, Session},
+    activity::{
+        BestEffort, Details, Device, Elevation, File, FileId, Histograms, Lap, Load, Pause,
+        PowerCurve, Record, RecordRange, Session, Swim,
+    },
     gear::Gear,
     query::{ActivityQuery, GearQuery, UserQuery},
     user::User,
@@ -19,12 +22,84 @@ impl Resource for Record {
     type Key = ActivityQuery;
 }
 
+impl Resource for RecordRange {
+    const NAME: &'static str = "record_range";
+
+    type Key = ActivityQuery;
+}
+
 impl Resource for Vec<Lap> {
     const NAME: &'static str = "lap";
 
     type Key = ActivityQuery;
 }
 
+impl Resource for File {
+    const NAME: &'static str = "file";
+
+    type Key = ActivityQuery;
+}
+
+impl Resource for FileId {
+    const NAME: &'static str = "file_id";
+
+    type Key = ActivityQuery;
+}
+
+impl Resource for Vec<Device> {
+    const NAME: &'static str = "device";
+
+    type Key = ActivityQuery;
+}
+
+impl Resource for Vec<Pause> {
+    const NAME: &'static str = "pause";
+
+    type Key = ActivityQuery;
+}
+
+impl Resource for Details {
+    const NAME: &'static str = "details";
+
+    type Key = ActivityQuery;
+}
+
+impl Resource for Elevation {
+    const NAME: &'static str = "elevation";
+
+    type Key = ActivityQuery;
+}
+
+impl Resource for PowerCurve {
+    const NAME: &'static str = "power_curve";
+
+    type Key = ActivityQuery;
+}
+
+impl Resource for Histograms {
+    const NAME: &'static str = "histograms";
+
+    type Key = ActivityQuery;
+}
+
+impl Resource for Load {
+    const NAME: &'static str = "load";
+
+    type Key = ActivityQuery;
+}
+
+impl Resource for Vec<BestEffort> {
+    const NAME: &'static str = "best_effort";
+
+    type Key = ActivityQuery;
+}
+
+impl Resource for Swim {
+    const NAME: &'static str = "swim";
+
+    type Key = ActivityQuery;
+}
+
 impl Traverse<Gear> for Session {
     type Collection = Relation<ActivityQuery, Session, GearQuery, Gear>;
 }
@@ -0,0 +1,12 @@
+use crate::{query::FingerprintQuery, resource::Resource};
+use serde::{Deserialize, Serialize};
+
+/// Finds an activity by the SHA-256 hash of its uploaded file.
+#[derive(Serialize, Deserialize)]
+pub struct ContentHash;
+
+impl Resource for ContentHash {
+    const NAME: &'static str = "content_hash";
+
+    type Key = FingerprintQuery;
+}
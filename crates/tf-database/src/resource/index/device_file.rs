@@ -0,0 +1,13 @@
+use crate::{query::FingerprintQuery, resource::Resource};
+use serde::{Deserialize, Serialize};
+
+/// Finds an activity by a hash of the `FileId` of its file, which stays the
+/// same when a device exports one recording several times.
+#[derive(Serialize, Deserialize)]
+pub struct DeviceFile;
+
+impl Resource for DeviceFile {
+    const NAME: &'static str = "device_file";
+
+    type Key = FingerprintQuery;
+}
@@ -1,3 +1,11 @@
+mod content_hash;
 mod default_gear;
+mod device_file;
+mod parent_activity;
+mod top_level_activity;
 
+pub use content_hash::ContentHash;
 pub use default_gear::DefaultGear;
+pub use device_file::DeviceFile;
+pub use parent_activity::ParentActivity;
+pub use top_level_activity::TopLevelActivity;
@@ -0,0 +1,12 @@
+use crate::{query::ActivityQuery, resource::Resource};
+use serde::{Deserialize, Serialize};
+
+/// Links each sport to its parent, see [`tf_models::Activity::children`].
+#[derive(Serialize, Deserialize)]
+pub struct ParentActivity;
+
+impl Resource for ParentActivity {
+    const NAME: &'static str = "parent_activity";
+
+    type Key = ActivityQuery;
+}
@@ -0,0 +1,13 @@
+use crate::{query::ActivityQuery, resource::Resource};
+use serde::{Deserialize, Serialize};
+
+/// Lists the activities that are not a sport of another, so they can be
+/// paged through without skipping any.
+#[derive(Serialize, Deserialize)]
+pub struct TopLevelActivity;
+
+impl Resource for TopLevelActivity {
+    const NAME: &'static str = "top_level_activity";
+
+    type Key = ActivityQuery;
+}
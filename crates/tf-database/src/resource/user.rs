@@ -1,13 +1,19 @@
-use super::{index::DefaultGear, Resource};
+use super::{
+    index::{ContentHash, DefaultGear, DeviceFile, ParentActivity, TopLevelActivity},
+    Resource,
+};
 use crate::{
     primitives::{Index, Relation, Tree},
     Traverse,
 };
 use tf_models::{
-    activity::{Lap, Record, Session},
+    activity::{
+        BestEffort, Details, Device, Elevation, File, FileId, Histograms, Lap, Load, Pause,
+        PowerCurve, Record, RecordRange, Session, Swim,
+    },
     gear::Gear,
-    query::{ActivityQuery, GearQuery, UserQuery},
-    user::{User, Zones},
+    query::{ActivityQuery, FingerprintQuery, GearQuery, UserQuery},
+    user::{Fitness, PersonalRecords, Thresholds, User, Zones},
 };
 
 impl Resource for User {
@@ -22,6 +28,24 @@ impl Resource for Zones {
     type Key = UserQuery;
 }
 
+impl Resource for Thresholds {
+    const NAME: &'static str = "thresholds";
+
+    type Key = UserQuery;
+}
+
+impl Resource for Fitness {
+    const NAME: &'static str = "fitness";
+
+    type Key = UserQuery;
+}
+
+impl Resource for PersonalRecords {
+    const NAME: &'static str = "personal_records";
+
+    type Key = UserQuery;
+}
+
 impl Traverse<Session> for User {
     type Collection = Relation<ActivityQuery, Session, UserQuery, User>;
 }
@@ -30,10 +54,74 @@ impl Traverse<Record> for User {
     type Collection = Relation<ActivityQuery, Record, UserQuery, User>;
 }
 
+impl Traverse<RecordRange> for User {
+    type Collection = Relation<ActivityQuery, RecordRange, UserQuery, User>;
+}
+
 impl Traverse<Vec<Lap>> for User {
     type Collection = Relation<ActivityQuery, Vec<Lap>, UserQuery, User>;
 }
 
+impl Traverse<Vec<Device>> for User {
+    type Collection = Relation<ActivityQuery, Vec<Device>, UserQuery, User>;
+}
+
+impl Traverse<Vec<Pause>> for User {
+    type Collection = Relation<ActivityQuery, Vec<Pause>, UserQuery, User>;
+}
+
+impl Traverse<Details> for User {
+    type Collection = Relation<ActivityQuery, Details, UserQuery, User>;
+}
+
+impl Traverse<Elevation> for User {
+    type Collection = Relation<ActivityQuery, Elevation, UserQuery, User>;
+}
+
+impl Traverse<PowerCurve> for User {
+    type Collection = Relation<ActivityQuery, PowerCurve, UserQuery, User>;
+}
+
+impl Traverse<Histograms> for User {
+    type Collection = Relation<ActivityQuery, Histograms, UserQuery, User>;
+}
+
+impl Traverse<Load> for User {
+    type Collection = Relation<ActivityQuery, Load, UserQuery, User>;
+}
+
+impl Traverse<Vec<BestEffort>> for User {
+    type Collection = Relation<ActivityQuery, Vec<BestEffort>, UserQuery, User>;
+}
+
+impl Traverse<Swim> for User {
+    type Collection = Relation<ActivityQuery, Swim, UserQuery, User>;
+}
+
+impl Traverse<File> for User {
+    type Collection = Relation<ActivityQuery, File, UserQuery, User>;
+}
+
+impl Traverse<FileId> for User {
+    type Collection = Relation<ActivityQuery, FileId, UserQuery, User>;
+}
+
+impl Traverse<ContentHash> for User {
+    type Collection = Index<FingerprintQuery, ContentHash, ActivityQuery, Session>;
+}
+
+impl Traverse<DeviceFile> for User {
+    type Collection = Index<FingerprintQuery, DeviceFile, ActivityQuery, Session>;
+}
+
+impl Traverse<ParentActivity> for User {
+    type Collection = Index<ActivityQuery, ParentActivity, ActivityQuery, Session>;
+}
+
+impl Traverse<TopLevelActivity> for User {
+    type Collection = Index<ActivityQuery, TopLevelActivity, ActivityQuery, Session>;
+}
+
 impl Traverse<Gear> for User {
     type Collection = Relation<GearQuery, Gear, UserQuery, User>;
 }
@@ -45,3 +133,15 @@ impl Traverse<DefaultGear> for User {
 impl Traverse<Zones> for User {
     type Collection = Tree<UserQuery, Zones>;
 }
+
+impl Traverse<Thresholds> for User {
+    type Collection = Tree<UserQuery, Thresholds>;
+}
+
+impl Traverse<Fitness> for User {
+    type Collection = Tree<UserQuery, Fitness>;
+}
+
+impl Traverse<PersonalRecords> for User {
+    type Collection = Tree<UserQuery, PersonalRecords>;
+}
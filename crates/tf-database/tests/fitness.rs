@@ -0,0 +1,88 @@
+use std::thread;
+use tf_database::{
+    fitness, lock,
+    query::{ActivityQuery, UserQuery},
+    resource::index::ParentActivity,
+    Database,
+};
+use tf_models::{
+    activity::{Load, Session},
+    user::{Fitness, Threshold, Thresholds, User},
+    UserId,
+};
+
+#[test]
+fn count_stress_of_concurrent_writes() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Database::open(dir.path()).unwrap();
+
+    let user = UserQuery {
+        user_id: UserId::new(),
+    };
+    let root = db.root::<User>().unwrap();
+    root.insert(&user, &User::default()).unwrap();
+
+    let mut thresholds = Thresholds::default();
+    thresholds.set(Threshold {
+        effective_from: "1970-01-01".parse().unwrap(),
+        ftp: None,
+        threshold_pace: None,
+        lthr: Some(170),
+    });
+    root.traverse::<Thresholds>()
+        .unwrap()
+        .insert(&user, &thresholds)
+        .unwrap();
+
+    let session = Session::default();
+    let load = Load {
+        duration: std::time::Duration::from_secs(3600).into(),
+        heartrate_avg: Some(150),
+        ..Default::default()
+    };
+
+    let activities = (0..16)
+        .map(|i| ActivityQuery {
+            user_id: user.user_id,
+            id: format!("1970010100{i:02}0000").parse().unwrap(),
+        })
+        .collect::<Vec<_>>();
+
+    for activity in &activities {
+        root.traverse::<Session>()
+            .unwrap()
+            .insert(activity, &session, &user)
+            .unwrap();
+        root.traverse::<Load>()
+            .unwrap()
+            .insert(activity, &load, &user)
+            .unwrap();
+    }
+
+    // The last is a sport of the first, which is counted in its parent
+    root.traverse::<ParentActivity>()
+        .unwrap()
+        .insert(&activities[15], &activities[0])
+        .unwrap();
+
+    let threads = activities
+        .iter()
+        .map(|activity| {
+            let db = db.clone();
+            let activity = *activity;
+
+            thread::spawn(move || {
+                lock::user(&user, || fitness::count_stress(&db, &activity, 1.)).unwrap()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let (date, stress) = thresholds.stress(&load, &session).unwrap();
+    let fitness = root.traverse::<Fitness>().unwrap().get(&user).unwrap();
+
+    assert!((fitness.unwrap().day(date).stress - 15. * stress).abs() < 1e-9);
+}
@@ -0,0 +1,288 @@
+use serde::Serialize;
+use tf_database::{
+    error::Result,
+    primitives::{Database, Key, Value},
+    query::{ActivityQuery, UserQuery},
+    resource::index::{ParentActivity, TopLevelActivity},
+};
+use tf_models::{
+    activity::{Record, Session},
+    types::DateTime,
+    user::{User, ZoneUnit, Zones},
+    ActivityId, Sport, UserId,
+};
+
+#[test]
+fn migrate_legacy_activity_ids() -> Result<()> {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::open(dir.path())?;
+
+    let user_id = UserId::from_bytes(nanoid::nanoid!().as_bytes()).unwrap();
+    let legacy_key = [user_id.as_bytes().as_slice(), b"202201311545"].concat();
+
+    let session = Session {
+        start_time: "2022-01-31T15:45:00Z".parse().unwrap(),
+        ..Default::default()
+    };
+
+    let sessions = db.open_resource::<Session>()?;
+    sessions
+        .as_ref()
+        .set(legacy_key.clone(), session.as_bytes()?)?;
+
+    db.migrate_activity_ids()?;
+
+    let query = ActivityQuery {
+        user_id,
+        id: ActivityId::from_bytes(b"2022013115450000").unwrap(),
+    };
+
+    assert!(sessions.contains_key(&query)?);
+    assert!(sessions.as_ref().get(&legacy_key)?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn migrate_legacy_next_to_new_activity_ids() -> Result<()> {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = tf_database::Database::open(dir.path())?;
+
+    let user = UserQuery {
+        user_id: UserId::new(),
+    };
+    let root = db.root::<User>()?;
+    root.insert(&user, &User::default())?;
+
+    // Stored by a server an hour behind the time zone of the activity
+    let legacy_key = [user.user_id.as_bytes().as_slice(), b"202201311445"].concat();
+    let legacy = Session {
+        start_time: "2022-01-31T14:45:30Z".parse().unwrap(),
+        utc_offset: Some(3600),
+        ..Default::default()
+    };
+
+    let query = |id: &str| ActivityQuery {
+        user_id: user.user_id,
+        id: id.parse().unwrap(),
+    };
+
+    // A new activity that started in the same second
+    let new = query("2022013115453000");
+    root.traverse::<Session>()?.insert(&new, &legacy, &user)?;
+
+    // Stored with the legacy key, and linked to the user by it
+    let sessions = root.traverse::<Session>()?;
+    let records = root.traverse::<Record>()?;
+    sessions
+        .local
+        .as_ref()
+        .set(legacy_key.clone(), legacy.as_bytes()?)?;
+    records
+        .local
+        .as_ref()
+        .set(legacy_key.clone(), Record::default().as_bytes()?)?;
+    sessions
+        .index
+        .index
+        .as_ref()
+        .set(legacy_key.clone(), user.as_key())?;
+    records
+        .index
+        .index
+        .as_ref()
+        .set(legacy_key.clone(), user.as_key())?;
+
+    // A sport of the legacy activity, linked to it by its legacy key
+    let child = query("2022013115460000");
+    sessions.insert(&child, &Session::default(), &user)?;
+    root.traverse::<ParentActivity>()?
+        .index
+        .as_ref()
+        .set(child.as_key(), legacy_key.clone())?;
+
+    db.migrate()?;
+
+    let migrated = query("2022013115453001");
+
+    assert!(sessions.contains_key(&new)?);
+    assert!(sessions.contains_key(&migrated)?);
+    assert!(sessions.local.as_ref().get(&legacy_key)?.is_none());
+    assert!(records.contains_key(&migrated)?);
+
+    let parent = root.traverse::<ParentActivity>()?.key(&child)?;
+    assert_eq!(parent.map(|x| x.id), Some(migrated.id));
+
+    let top_level = root
+        .traverse::<TopLevelActivity>()?
+        .keys(&user, 0, 10, false)?
+        .map(|x| x.id)
+        .collect::<Vec<_>>();
+
+    assert_eq!(top_level, [new.id, migrated.id]);
+
+    Ok(())
+}
+
+#[test]
+fn migrate_activity_ids_once() -> Result<()> {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::open(dir.path())?;
+
+    db.migrate_activity_ids()?;
+
+    let user_id = UserId::from_bytes(nanoid::nanoid!().as_bytes()).unwrap();
+    let legacy_key = [user_id.as_bytes().as_slice(), b"202201311545"].concat();
+
+    let sessions = db.open_resource::<Session>()?;
+    sessions
+        .as_ref()
+        .set(legacy_key.clone(), Session::default().as_bytes()?)?;
+
+    // Keys are no longer scanned once the migration has run
+    db.migrate_activity_ids()?;
+
+    assert!(sessions.as_ref().get(&legacy_key)?.is_some());
+
+    Ok(())
+}
+
+#[test]
+fn index_top_level_activities() -> Result<()> {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = tf_database::Database::open(dir.path())?;
+
+    let user = UserQuery {
+        user_id: UserId::new(),
+    };
+    let root = db.root::<User>()?;
+    root.insert(&user, &User::default())?;
+
+    let [parent, child] = ["2022013115450000", "2022013115460000"].map(|id| ActivityQuery {
+        user_id: user.user_id,
+        id: id.parse().unwrap(),
+    });
+
+    for query in [&parent, &child] {
+        root.traverse::<Session>()?
+            .insert(query, &Session::default(), &user)?;
+    }
+    root.traverse::<ParentActivity>()?.insert(&child, &parent)?;
+
+    db.migrate()?;
+
+    let top_level = root
+        .traverse::<TopLevelActivity>()?
+        .keys(&user, 0, 10, false)?
+        .map(|x| x.id)
+        .collect::<Vec<_>>();
+
+    assert_eq!(top_level, [parent.id]);
+
+    Ok(())
+}
+
+#[test]
+fn read_legacy_zones() -> Result<()> {
+    #[derive(Serialize)]
+    struct LegacyZones {
+        z1: f32,
+        z2: f32,
+        z3: f32,
+        z4: f32,
+        z5: f32,
+    }
+
+    let legacy = LegacyZones {
+        z1: 0.5,
+        z2: 0.625,
+        z3: 0.75,
+        z4: 0.875,
+        z5: 0.9375,
+    };
+
+    let zones = Zones::from_bytes(&legacy.as_bytes()?)?;
+
+    assert_eq!(zones.heartrate.len(), 1);
+    assert_eq!(zones.heartrate[0].bounds, [0.5, 0.625, 0.75, 0.875, 0.9375]);
+    assert!(zones.heartrate[0].unit == ZoneUnit::Fraction);
+    assert_eq!(zones.power.len(), 1);
+
+    let zones = Zones::from_bytes(&zones.as_bytes()?)?;
+
+    assert_eq!(zones.heartrate[0].bounds, [0.5, 0.625, 0.75, 0.875, 0.9375]);
+
+    Ok(())
+}
+
+#[test]
+fn read_legacy_records() -> Result<()> {
+    #[derive(Serialize)]
+    struct LegacyRecord {
+        cadence: Vec<Option<f64>>,
+        distance: Vec<Option<f64>>,
+        altitude: Vec<Option<f64>>,
+        speed: Vec<Option<f64>>,
+        heartrate: Vec<Option<u8>>,
+        power: Vec<Option<u16>>,
+        lat: Vec<Option<f64>>,
+        lon: Vec<Option<f64>>,
+        timestamp: Vec<Option<DateTime>>,
+        duration: Vec<std::time::Duration>,
+    }
+
+    let legacy = LegacyRecord {
+        cadence: vec![None],
+        distance: vec![Some(10.)],
+        altitude: vec![None],
+        speed: vec![None],
+        heartrate: vec![Some(140)],
+        power: vec![Some(200)],
+        lat: vec![None],
+        lon: vec![None],
+        timestamp: vec![None],
+        duration: vec![std::time::Duration::ZERO],
+    };
+
+    let record = Record::from_bytes(&legacy.as_bytes()?)?;
+
+    assert_eq!(record.heartrate, [Some(140)]);
+    assert_eq!(record.duration.len(), 1);
+    assert!(record.timer_time.is_empty());
+    assert!(record.temperature.is_empty());
+    assert!(record.left_right_balance.is_empty());
+    assert!(record.ground_contact_time.is_empty());
+    assert!(record.respiration_rate.is_empty());
+    assert!(record.developer_fields.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn read_legacy_sessions() -> Result<()> {
+    #[derive(Serialize)]
+    struct LegacySession {
+        heartrate_avg: Option<u8>,
+        sport: Sport,
+        duration: std::time::Duration,
+        duration_active: std::time::Duration,
+        start_time: DateTime,
+    }
+
+    let legacy = LegacySession {
+        heartrate_avg: Some(140),
+        sport: Sport::Running,
+        duration: std::time::Duration::from_secs(60),
+        duration_active: std::time::Duration::from_secs(60),
+        start_time: DateTime::default(),
+    };
+
+    let session = Session::from_bytes(&legacy.as_bytes()?)?;
+
+    assert_eq!(session.heartrate_avg, Some(140));
+    assert_eq!(session.temperature_avg, None);
+    assert_eq!(session.respiration_rate_avg, None);
+    assert_eq!(session.training_effect, None);
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use crate::{private::Local, Event, FollowerEvent, Handler};
+use crate::{private::Local, Event, FollowerEvent, Handler, ImportEvent};
 use dashmap::DashMap;
 use std::sync::Arc;
 use tokio::sync::watch::{self, error::SendError, Receiver, Sender};
@@ -8,6 +8,7 @@ pub type Topic<T> = Arc<DashMap<<T as Event>::Key, Arc<Sender<<T as Event>::Valu
 #[derive(Clone, Default)]
 pub struct Broker {
     pub follower_event: Topic<FollowerEvent>,
+    pub import_event: Topic<ImportEvent>,
 }
 
 impl Broker {
@@ -1,5 +1,5 @@
 use std::hash::Hash;
-use tf_models::UserId;
+use tf_models::{import::ImportProgress, UserId};
 
 mod broker;
 
@@ -38,3 +38,16 @@ impl Handler<FollowerEvent> for Broker {
         self.follower_event.clone()
     }
 }
+
+pub struct ImportEvent;
+
+impl Event for ImportEvent {
+    type Key = UserId;
+    type Value = ImportProgress;
+}
+
+impl Handler<ImportEvent> for Broker {
+    fn handle<L: private::IsLocal>(&self) -> Topic<ImportEvent> {
+        self.import_event.clone()
+    }
+}
@@ -1,9 +1,9 @@
 use crate::{guard::OAuthGuard, query};
 use tf_database::{error::Error, Database};
 use tf_models::{
-    activity::{Lap, Record, Session},
+    activity::Session,
     gear::Gear,
-    query::{ActivityQuery, GearQuery},
+    query::{ActivityQuery, GearQuery, UserQuery},
     user::User,
     ActivityId, GearId, UserId,
 };
@@ -108,19 +108,13 @@ impl ActivityRoot {
             id: activity,
         };
 
-        tokio::task::spawn_blocking(move || {
-            let root = db.root::<User>()?;
-
-            let session = root.traverse::<Session>()?.remove(&activity)?;
-            let record = root.traverse::<Record>()?.remove(&activity)?;
-            let lap = root.traverse::<Vec<Lap>>()?.remove(&activity)?;
+        let user = UserQuery { user_id: user };
 
-            Ok(session
-                .and(record)
-                .and(lap)
-                .is_some()
-                .then_some(DeleteActivityPayload { id: activity.id }))
+        let removed = tokio::task::spawn_blocking(move || {
+            tf_database::lock::user(&user, || tf_database::activity::remove(&db, &activity))
         })
-        .await?
+        .await??;
+
+        Ok(removed.then_some(DeleteActivityPayload { id: activity.id }))
     }
 }
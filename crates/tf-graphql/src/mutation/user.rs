@@ -1,10 +1,11 @@
 use crate::{guard::OAuthGuard, query};
 use async_graphql::{Context, Object, Result, SimpleObject};
+use chrono::NaiveDate;
 use oxide_auth::primitives::grant::Grant;
-use tf_database::{error::Error, resource::index::DefaultGear, Database};
+use tf_database::{error::Error, fitness, resource::index::DefaultGear, Database};
 use tf_models::{
     query::{GearQuery, UserQuery},
-    user::{User, Zones},
+    user::{Fitness, Threshold, Thresholds, User, ZoneKind, ZoneSet, Zones},
     GearId, UserId,
 };
 use tf_scopes::{self as scopes, Write};
@@ -32,6 +33,26 @@ struct SetZonesPayload {
     user: query::user::UserRoot,
 }
 
+#[derive(SimpleObject)]
+struct RemoveZonesPayload {
+    user: query::user::UserRoot,
+}
+
+#[derive(SimpleObject)]
+struct SetThresholdPayload {
+    user: query::user::UserRoot,
+}
+
+#[derive(SimpleObject)]
+struct RemoveThresholdPayload {
+    user: query::user::UserRoot,
+}
+
+#[derive(SimpleObject)]
+struct SetFitnessTimeConstantsPayload {
+    user: query::user::UserRoot,
+}
+
 #[Object(name = "UserMutation")]
 impl UserRoot {
     #[graphql(guard = "OAuthGuard::new(Write(scopes::User))")]
@@ -118,16 +139,19 @@ impl UserRoot {
         &self,
         ctx: &Context<'_>,
         user: UserId,
-        input: Zones,
+        input: ZoneSet,
     ) -> Result<SetZonesPayload> {
         let db = ctx.data_unchecked::<Database>().clone();
 
         let user = UserQuery { user_id: user };
 
         tokio::task::spawn_blocking(move || {
-            db.root::<User>()?
-                .traverse::<Zones>()?
-                .insert(&user, &input)?;
+            let collection = db.root::<User>()?.traverse::<Zones>()?;
+
+            let mut zones = collection.get(&user)?.unwrap_or_default();
+            zones.set(input);
+
+            collection.insert(&user, &zones)?;
 
             Ok::<_, Error>(())
         })
@@ -137,4 +161,127 @@ impl UserRoot {
             user: query::user::UserRoot { query: user },
         })
     }
+
+    #[graphql(guard = "OAuthGuard::new(Write(scopes::User))")]
+    async fn remove_zones(
+        &self,
+        ctx: &Context<'_>,
+        user: UserId,
+        kind: ZoneKind,
+        effective_from: NaiveDate,
+    ) -> Result<RemoveZonesPayload> {
+        let db = ctx.data_unchecked::<Database>().clone();
+
+        let user = UserQuery { user_id: user };
+
+        tokio::task::spawn_blocking(move || {
+            let collection = db.root::<User>()?.traverse::<Zones>()?;
+
+            let mut zones = collection.get(&user)?.unwrap_or_default();
+
+            if zones.remove(kind, effective_from) {
+                collection.insert(&user, &zones)?;
+            }
+
+            Ok::<_, Error>(())
+        })
+        .await??;
+
+        Ok(RemoveZonesPayload {
+            user: query::user::UserRoot { query: user },
+        })
+    }
+
+    #[graphql(guard = "OAuthGuard::new(Write(scopes::User))")]
+    async fn set_threshold(
+        &self,
+        ctx: &Context<'_>,
+        user: UserId,
+        input: Threshold,
+    ) -> Result<SetThresholdPayload> {
+        let db = ctx.data_unchecked::<Database>().clone();
+
+        let user = UserQuery { user_id: user };
+
+        tokio::task::spawn_blocking(move || {
+            tf_database::lock::user(&user, || {
+                let collection = db.root::<User>()?.traverse::<Thresholds>()?;
+
+                let mut thresholds = collection.get(&user)?.unwrap_or_default();
+                thresholds.set(input);
+
+                collection.insert(&user, &thresholds)?;
+
+                fitness::rebuild(&db, &user)
+            })
+        })
+        .await??;
+
+        Ok(SetThresholdPayload {
+            user: query::user::UserRoot { query: user },
+        })
+    }
+
+    #[graphql(guard = "OAuthGuard::new(Write(scopes::User))")]
+    async fn remove_threshold(
+        &self,
+        ctx: &Context<'_>,
+        user: UserId,
+        effective_from: NaiveDate,
+    ) -> Result<RemoveThresholdPayload> {
+        let db = ctx.data_unchecked::<Database>().clone();
+
+        let user = UserQuery { user_id: user };
+
+        tokio::task::spawn_blocking(move || {
+            tf_database::lock::user(&user, || {
+                let collection = db.root::<User>()?.traverse::<Thresholds>()?;
+
+                let mut thresholds = collection.get(&user)?.unwrap_or_default();
+
+                if thresholds.remove(effective_from) {
+                    collection.insert(&user, &thresholds)?;
+                    fitness::rebuild(&db, &user)?;
+                }
+
+                Ok::<_, Error>(())
+            })
+        })
+        .await??;
+
+        Ok(RemoveThresholdPayload {
+            user: query::user::UserRoot { query: user },
+        })
+    }
+
+    #[graphql(guard = "OAuthGuard::new(Write(scopes::User))")]
+    async fn set_fitness_time_constants(
+        &self,
+        ctx: &Context<'_>,
+        user: UserId,
+        #[graphql(default = 42)] ctl_days: u16,
+        #[graphql(default = 7)] atl_days: u16,
+    ) -> Result<SetFitnessTimeConstantsPayload> {
+        let db = ctx.data_unchecked::<Database>().clone();
+
+        let user = UserQuery { user_id: user };
+
+        tokio::task::spawn_blocking(move || {
+            tf_database::lock::user(&user, || {
+                let collection = db.root::<User>()?.traverse::<Fitness>()?;
+
+                let mut fitness = collection.get(&user)?.unwrap_or_default();
+                fitness.set_time_constants(ctl_days.max(1), atl_days.max(1));
+
+                collection.insert(&user, &fitness)?;
+
+                Ok::<_, Error>(())
+            })
+        })
+        .await??;
+
+        Ok(SetFitnessTimeConstantsPayload {
+            user: query::user::UserRoot { query: user },
+        })
+    }
 }
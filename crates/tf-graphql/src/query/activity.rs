@@ -1,11 +1,19 @@
-use async_graphql::{Context, Object, Result};
+use async_graphql::{Context, Object, Result, SimpleObject};
 
 use super::{GearRoot, OAuthGuard, UserRoot};
-use tf_database::{query::ActivityQuery, Database};
+use tf_database::{
+    query::{ActivityQuery, UserQuery},
+    resource::index::{ParentActivity, TopLevelActivity},
+    Database,
+};
 use tf_models::{
-    activity::{Lap, Record, Session},
+    activity::{
+        BestEffort, Details, Device, Elevation, FileId, Histograms, Lap, Load, Pause, PowerCurve,
+        Session, StressSource, Swim,
+    },
     gear::Gear,
-    user::User,
+    types::{Duration, Power},
+    user::{Thresholds, User, ZoneKind, Zones},
     ActivityId,
 };
 use tf_scopes::{self as scopes, Read};
@@ -17,6 +25,41 @@ pub struct ActivityRoot {
     pub query: ActivityQuery,
 }
 
+#[derive(SimpleObject)]
+struct TrainingLoad {
+    normalized_power: Option<Power>,
+    intensity_factor: Option<f64>,
+    tss: Option<f64>,
+    tss_source: Option<StressSource>,
+    trimp: Option<f64>,
+}
+
+#[derive(SimpleObject)]
+pub(super) struct ZoneTimes {
+    below: Duration,
+    zones: Vec<Duration>,
+}
+
+impl From<Vec<f64>> for ZoneTimes {
+    fn from(seconds: Vec<f64>) -> Self {
+        let mut durations = seconds
+            .into_iter()
+            .map(|x| std::time::Duration::from_secs_f64(x.max(0.)).into());
+
+        Self {
+            below: durations.next().unwrap_or_default(),
+            zones: durations.collect(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct TimeInZones {
+    bounds: Vec<f64>,
+    activity: ZoneTimes,
+    laps: Vec<ZoneTimes>,
+}
+
 #[Object(name = "Activity")]
 impl ActivityRoot {
     async fn id(&self) -> &ActivityId {
@@ -28,10 +71,13 @@ impl ActivityRoot {
         let query = self.query;
 
         tokio::task::spawn_blocking(move || {
-            Ok(db
-                .root::<Session>()?
-                .prev(&query)?
-                .map(|query| Self { query }))
+            let prev = db
+                .root::<User>()?
+                .traverse::<TopLevelActivity>()?
+                .index
+                .prev(&query)?;
+
+            Ok(prev.map(|query| Self { query }))
         })
         .await?
     }
@@ -41,10 +87,13 @@ impl ActivityRoot {
         let query = self.query;
 
         tokio::task::spawn_blocking(move || {
-            Ok(db
-                .root::<Session>()?
-                .next(&query)?
-                .map(|query| Self { query }))
+            let next = db
+                .root::<User>()?
+                .traverse::<TopLevelActivity>()?
+                .index
+                .next(&query)?;
+
+            Ok(next.map(|query| Self { query }))
         })
         .await?
     }
@@ -61,11 +110,13 @@ impl ActivityRoot {
         let query = self.query;
 
         let buffer = tokio::task::spawn_blocking(move || {
-            Ok::<_, tf_database::error::Error>(db.root::<Record>()?.get_raw(&query)?.unwrap())
+            Ok::<_, tf_database::error::Error>(
+                tf_database::activity::record_raw(&db, &query)?.unwrap(),
+            )
         })
         .await??;
 
-        Ok(RecordRoot { buffer })
+        Ok(RecordRoot { query, buffer })
     }
 
     async fn lap(&self, ctx: &Context<'_>) -> Result<Vec<Lap>> {
@@ -76,6 +127,180 @@ impl ActivityRoot {
             .await?
     }
 
+    async fn pauses(&self, ctx: &Context<'_>) -> Result<Vec<Pause>> {
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+
+        tokio::task::spawn_blocking(move || {
+            Ok(db.root::<Vec<Pause>>()?.get(&query)?.unwrap_or_default())
+        })
+        .await?
+    }
+
+    async fn swim(&self, ctx: &Context<'_>) -> Result<Option<Swim>> {
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+
+        tokio::task::spawn_blocking(move || Ok(db.root::<Swim>()?.get(&query)?)).await?
+    }
+
+    async fn details(&self, ctx: &Context<'_>) -> Result<Option<Details>> {
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+
+        tokio::task::spawn_blocking(move || Ok(db.root::<Details>()?.get(&query)?)).await?
+    }
+
+    async fn elevation(&self, ctx: &Context<'_>) -> Result<Option<Elevation>> {
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+
+        tokio::task::spawn_blocking(move || Ok(db.root::<Elevation>()?.get(&query)?)).await?
+    }
+
+    async fn power_curve(&self, ctx: &Context<'_>) -> Result<Option<PowerCurve>> {
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+
+        tokio::task::spawn_blocking(move || Ok(db.root::<PowerCurve>()?.get(&query)?)).await?
+    }
+
+    async fn best_efforts(&self, ctx: &Context<'_>) -> Result<Vec<BestEffort>> {
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+
+        tokio::task::spawn_blocking(move || {
+            Ok(db
+                .root::<Vec<BestEffort>>()?
+                .get(&query)?
+                .unwrap_or_default())
+        })
+        .await?
+    }
+
+    async fn training_load(&self, ctx: &Context<'_>) -> Result<Option<TrainingLoad>> {
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+
+        tokio::task::spawn_blocking(move || {
+            let load = match db.root::<Load>()?.get(&query)? {
+                Some(load) => load,
+                None => return Ok(None),
+            };
+
+            let thresholds = db
+                .root::<Thresholds>()?
+                .get(&UserQuery {
+                    user_id: query.user_id,
+                })?
+                .unwrap_or_default();
+
+            let threshold = db
+                .root::<Session>()?
+                .get(&query)?
+                .and_then(|x| thresholds.at(x.local_start_time().date_naive()).copied());
+
+            let intensity = threshold.and_then(|x| load.intensity(&x));
+            let stress = threshold.and_then(|x| load.stress(&x));
+
+            Ok(Some(TrainingLoad {
+                normalized_power: load.normalized_power,
+                intensity_factor: intensity.map(|(intensity, _)| intensity),
+                tss: stress.map(|(stress, _)| stress),
+                tss_source: stress.map(|(_, source)| source),
+                trimp: load.trimp,
+            }))
+        })
+        .await?
+    }
+
+    async fn time_in_zones(
+        &self,
+        ctx: &Context<'_>,
+        kind: ZoneKind,
+    ) -> Result<Option<TimeInZones>> {
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+        let user = UserQuery {
+            user_id: query.user_id,
+        };
+
+        tokio::task::spawn_blocking(move || {
+            let (histograms, session) = match (
+                db.root::<Histograms>()?.get(&query)?,
+                db.root::<Session>()?.get(&query)?,
+            ) {
+                (Some(histograms), Some(session)) => (histograms, session),
+                _ => return Ok(None),
+            };
+
+            let bounds = db.root::<Zones>()?.get(&user)?.unwrap_or_default().bounds(
+                kind,
+                session.local_start_time().date_naive(),
+                &db.root::<User>()?.get(&user)?.unwrap_or_default(),
+                &db.root::<Thresholds>()?.get(&user)?.unwrap_or_default(),
+            );
+
+            Ok(bounds.map(|bounds| TimeInZones {
+                activity: histograms.activity.time_in_zones(kind, &bounds).into(),
+                laps: histograms
+                    .lap
+                    .iter()
+                    .map(|x| x.time_in_zones(kind, &bounds).into())
+                    .collect(),
+                bounds,
+            }))
+        })
+        .await?
+    }
+
+    async fn devices(&self, ctx: &Context<'_>) -> Result<Vec<Device>> {
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+
+        tokio::task::spawn_blocking(move || {
+            Ok(db.root::<Vec<Device>>()?.get(&query)?.unwrap_or_default())
+        })
+        .await?
+    }
+
+    async fn parent(&self, ctx: &Context<'_>) -> Result<Option<Self>> {
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+
+        tokio::task::spawn_blocking(move || {
+            Ok(db
+                .root::<User>()?
+                .traverse::<ParentActivity>()?
+                .key(&query)?
+                .map(|query| Self { query }))
+        })
+        .await?
+    }
+
+    async fn children(&self, ctx: &Context<'_>) -> Result<Vec<Self>> {
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+
+        tokio::task::spawn_blocking(move || {
+            let index = db.root::<User>()?.traverse::<ParentActivity>()?;
+            let total_count = index.join(&query, 0, 0, false)?.total_count;
+
+            Ok(index
+                .join(&query, 0, total_count, false)?
+                .map(|query| Self { query })
+                .collect())
+        })
+        .await?
+    }
+
+    async fn file_id(&self, ctx: &Context<'_>) -> Result<Option<FileId>> {
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+
+        tokio::task::spawn_blocking(move || Ok(db.root::<FileId>()?.get(&query)?)).await?
+    }
+
     #[graphql(guard = "OAuthGuard::new(Read(scopes::Gear))")]
     async fn gear(&self, ctx: &Context<'_>) -> Result<Option<GearRoot>> {
         let db = ctx.data_unchecked::<Database>().clone();
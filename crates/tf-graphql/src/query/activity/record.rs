@@ -1,15 +1,26 @@
-use async_graphql::{Object, Result};
-use tf_database::primitives::ArcBytes;
-use tf_models::types::{AngularVelocity, DateTime, Duration, LengthF64, Power, Velocity};
+use async_graphql::{Context, Enum, Object, Result};
+use tf_database::{primitives::ArcBytes, query::ActivityQuery, Database};
+use tf_models::{
+    activity::{DeveloperField, Elevation},
+    types::{AngularVelocity, DateTime, Duration, LengthF64, Power, Velocity},
+};
+
+#[derive(Enum, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AltitudeSource {
+    #[default]
+    Recorded,
+    ElevationModel,
+}
 
 pub struct RecordRoot {
+    pub(super) query: ActivityQuery,
     pub(super) buffer: ArcBytes<'static>,
 }
 
 impl RecordRoot {
     async fn inner<T>(&self, field: &'static str) -> Result<T>
     where
-        for<'a> T: Send + serde::Deserialize<'a> + 'static,
+        for<'a> T: Default + Send + serde::Deserialize<'a> + 'static,
     {
         let (send, recv) = tokio::sync::oneshot::channel();
         let buffer = self.buffer.clone();
@@ -19,7 +30,13 @@ impl RecordRoot {
                 .unwrap()
                 .as_map()
                 .idx(field);
-            let res = T::deserialize(reader).unwrap();
+
+            // Channels added later are missing in records stored before them
+            let res = if reader.flexbuffer_type() == flexbuffers::FlexBufferType::Null {
+                T::default()
+            } else {
+                T::deserialize(reader).unwrap()
+            };
 
             let _ = send.send(res);
         });
@@ -38,8 +55,27 @@ impl RecordRoot {
         self.inner("distance").await
     }
 
-    async fn altitude(&self) -> Result<Vec<Option<LengthF64>>> {
-        self.inner("altitude").await
+    async fn altitude(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] source: AltitudeSource,
+    ) -> Result<Vec<Option<LengthF64>>> {
+        match source {
+            AltitudeSource::Recorded => self.inner("altitude").await,
+            AltitudeSource::ElevationModel => {
+                let db = ctx.data_unchecked::<Database>().clone();
+                let query = self.query;
+
+                tokio::task::spawn_blocking(move || {
+                    Ok(db
+                        .root::<Elevation>()?
+                        .get(&query)?
+                        .map(|x| x.altitude)
+                        .unwrap_or_default())
+                })
+                .await?
+            }
+        }
     }
 
     async fn speed(&self) -> Result<Vec<Option<Velocity>>> {
@@ -69,4 +105,45 @@ impl RecordRoot {
     async fn duration(&self) -> Result<Vec<Duration>> {
         self.inner("duration").await
     }
+
+    async fn timer_time(&self) -> Result<Vec<Duration>> {
+        self.inner("timer_time").await
+    }
+
+    async fn temperature(&self) -> Result<Vec<Option<i8>>> {
+        self.inner("temperature").await
+    }
+
+    async fn left_right_balance(&self) -> Result<Vec<Option<f64>>> {
+        self.inner("left_right_balance").await
+    }
+
+    async fn vertical_oscillation(&self) -> Result<Vec<Option<LengthF64>>> {
+        self.inner("vertical_oscillation").await
+    }
+
+    async fn ground_contact_time(&self) -> Result<Vec<Option<Duration>>> {
+        self.inner("ground_contact_time").await
+    }
+
+    async fn stance_time_balance(&self) -> Result<Vec<Option<f64>>> {
+        self.inner("stance_time_balance").await
+    }
+
+    async fn step_length(&self) -> Result<Vec<Option<LengthF64>>> {
+        self.inner("step_length").await
+    }
+
+    async fn respiration_rate(&self) -> Result<Vec<Option<f64>>> {
+        self.inner("respiration_rate").await
+    }
+
+    async fn developer_fields(&self, name: Option<String>) -> Result<Vec<DeveloperField>> {
+        let developer_fields: Vec<DeveloperField> = self.inner("developer_fields").await?;
+
+        Ok(developer_fields
+            .into_iter()
+            .filter(|x| name.is_none() || name.as_deref() == Some(x.name.as_str()))
+            .collect())
+    }
 }
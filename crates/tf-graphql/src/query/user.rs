@@ -1,16 +1,18 @@
-use super::{ActivityRoot, GearRoot, OAuthGuard};
+use super::{activity::ZoneTimes, ActivityRoot, GearRoot, OAuthGuard};
 use crate::connection::{Connection, PageInfo};
-use async_graphql::{Context, Object, Result};
+use async_graphql::{Context, Enum, InputObject, Object, Result, SimpleObject};
+use chrono::{Datelike, NaiveDate};
 use tf_database::{
     error::Error,
     query::{ActivityQuery, GearQuery, UserQuery},
-    resource::index::DefaultGear,
+    resource::index::{DefaultGear, TopLevelActivity},
     Database,
 };
 use tf_models::{
-    activity::Session,
+    activity::{Device, FileId, Histograms, PowerCurve, Session},
     gear::Gear,
-    user::{User, Zones},
+    types::{DateTime, Duration, LengthF64, Power},
+    user::{Fitness, PersonalRecords, Threshold, Thresholds, User, ZoneKind, Zones},
     ActivityId, GearId, UserId,
 };
 use tf_scopes::{self as scopes, Read};
@@ -19,6 +21,84 @@ pub struct UserRoot {
     pub query: UserQuery,
 }
 
+#[derive(SimpleObject)]
+struct DuplicateActivity {
+    activity: ActivityRoot,
+    duplicate: ActivityRoot,
+}
+
+#[derive(SimpleObject)]
+struct DeviceUsage {
+    device: Device,
+    first_used: DateTime,
+    last_used: DateTime,
+    activities: usize,
+}
+
+#[derive(SimpleObject)]
+struct PowerRecord {
+    duration: Duration,
+    power: Power,
+    activity: ActivityRoot,
+    start: Duration,
+}
+
+#[derive(SimpleObject)]
+struct PersonalRecord {
+    distance: LengthF64,
+    duration: Duration,
+    activity: ActivityRoot,
+    start_time: DateTime,
+    start: Duration,
+}
+
+#[derive(InputObject)]
+struct PlannedLoad {
+    date: NaiveDate,
+    tss: f64,
+}
+
+#[derive(SimpleObject)]
+struct FitnessDay {
+    date: NaiveDate,
+    tss: f64,
+    planned_tss: f64,
+    ctl: f64,
+    atl: f64,
+    tsb: f64,
+}
+
+#[derive(SimpleObject)]
+struct FitnessSeries {
+    ctl_days: u16,
+    atl_days: u16,
+    days: Vec<FitnessDay>,
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+enum Period {
+    Week,
+    Month,
+}
+
+impl Period {
+    fn start(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Week => {
+                date - chrono::Duration::days(date.weekday().num_days_from_monday().into())
+            }
+            Self::Month => date.with_day(1).unwrap(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct PeriodTimeInZones {
+    start: NaiveDate,
+    activities: usize,
+    times: ZoneTimes,
+}
+
 #[Object(name = "User")]
 impl UserRoot {
     async fn id(&self) -> &UserId {
@@ -40,6 +120,20 @@ impl UserRoot {
         tokio::task::spawn_blocking(move || Ok(db.root()?.get(&query)?.unwrap_or_default())).await?
     }
 
+    async fn thresholds(&self, ctx: &Context<'_>) -> Result<Vec<Threshold>> {
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+
+        tokio::task::spawn_blocking(move || {
+            Ok(db
+                .root::<Thresholds>()?
+                .get(&query)?
+                .unwrap_or_default()
+                .history)
+        })
+        .await?
+    }
+
     #[graphql(guard = "OAuthGuard::new(Read(scopes::Activity))")]
     async fn activity(
         &self,
@@ -74,12 +168,12 @@ impl UserRoot {
         let query = self.query;
 
         let (edges, total_count) = tokio::task::spawn_blocking(move || {
-            let collection = db.root::<User>()?.traverse::<Session>()?;
+            let collection = db.root::<User>()?.traverse::<TopLevelActivity>()?;
 
             let iter = collection.keys(&query, skip, take, reverse)?;
-            let total_count = iter.total_count;
 
-            let edges = iter.map(|query| ActivityRoot { query }).collect();
+            let total_count = iter.total_count;
+            let edges = iter.map(|query| ActivityRoot { query }).collect::<Vec<_>>();
 
             Ok::<_, Error>((edges, total_count))
         })
@@ -95,6 +189,354 @@ impl UserRoot {
         })
     }
 
+    #[graphql(guard = "OAuthGuard::new(Read(scopes::Activity))")]
+    async fn duplicate_activities(&self, ctx: &Context<'_>) -> Result<Vec<DuplicateActivity>> {
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+
+        tokio::task::spawn_blocking(move || {
+            let root = db.root::<User>()?;
+            let sessions = root.traverse::<Session>()?;
+            let file_ids = root.traverse::<FileId>()?;
+            let top_level = root.traverse::<TopLevelActivity>()?;
+
+            let total_count = top_level.keys(&query, 0, 0, false)?.total_count;
+
+            let mut activities = Vec::with_capacity(total_count);
+
+            for query in top_level.keys(&query, 0, total_count, false)? {
+                if let Some(session) = sessions.get(&query)? {
+                    let start = session.start_time.timestamp_millis();
+                    let end = start + session.duration.as_millis() as i64;
+                    let file_id = file_ids.get(&query)?.unwrap_or_default();
+
+                    activities.push((start, end, file_id, query));
+                }
+            }
+
+            activities.sort_by_key(|(start, ..)| *start);
+
+            let mut duplicates = Vec::new();
+
+            for (i, (_, end, file_id, query)) in activities.iter().enumerate() {
+                for (_, _, other_file_id, other) in activities[i + 1..]
+                    .iter()
+                    .take_while(|(start, ..)| start < end)
+                {
+                    if !file_id.same_device(other_file_id) {
+                        duplicates.push(DuplicateActivity {
+                            activity: ActivityRoot { query: *query },
+                            duplicate: ActivityRoot { query: *other },
+                        });
+                    }
+                }
+            }
+
+            Ok(duplicates)
+        })
+        .await?
+    }
+
+    #[graphql(guard = "OAuthGuard::new(Read(scopes::Activity))")]
+    async fn devices(&self, ctx: &Context<'_>) -> Result<Vec<DeviceUsage>> {
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+
+        tokio::task::spawn_blocking(move || {
+            let root = db.root::<User>()?;
+            let sessions = root.traverse::<Session>()?;
+            let collection = root.traverse::<Vec<Device>>()?;
+
+            let total_count = collection.keys(&query, 0, 0, false)?.total_count;
+
+            let mut usages: Vec<DeviceUsage> = Vec::new();
+
+            for query in collection.keys(&query, 0, total_count, false)? {
+                let start_time = match sessions.get(&query)? {
+                    Some(session) => session.start_time,
+                    None => continue,
+                };
+
+                for device in collection.get(&query)?.unwrap_or_default() {
+                    match usages.iter_mut().find(|x| x.device.same_device(&device)) {
+                        Some(usage) => {
+                            if start_time > usage.last_used {
+                                usage.device = device;
+                                usage.last_used = start_time;
+                            }
+                            usage.first_used = usage.first_used.min(start_time);
+                            usage.activities += 1;
+                        }
+                        // Without a serial number, devices can not be told apart
+                        None if device.serial_number.is_some() => usages.push(DeviceUsage {
+                            device,
+                            first_used: start_time,
+                            last_used: start_time,
+                            activities: 1,
+                        }),
+                        None => (),
+                    }
+                }
+            }
+
+            usages.sort_by(|a, b| b.last_used.cmp(&a.last_used));
+
+            Ok(usages)
+        })
+        .await?
+    }
+
+    #[graphql(guard = "OAuthGuard::new(Read(scopes::Activity))")]
+    async fn power_curve(
+        &self,
+        ctx: &Context<'_>,
+        from: Option<DateTime>,
+        to: Option<DateTime>,
+    ) -> Result<Vec<PowerRecord>> {
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+
+        tokio::task::spawn_blocking(move || {
+            let root = db.root::<User>()?;
+            let sessions = root.traverse::<Session>()?;
+            let curves = root.traverse::<PowerCurve>()?;
+            let top_level = root.traverse::<TopLevelActivity>()?;
+
+            let total_count = top_level.keys(&query, 0, 0, false)?.total_count;
+
+            let mut records: Vec<PowerRecord> = Vec::new();
+
+            for query in top_level.keys(&query, 0, total_count, false)? {
+                let start_time = match sessions.get(&query)? {
+                    Some(session) => session.start_time,
+                    None => continue,
+                };
+
+                if from.filter(|x| start_time < *x).is_some()
+                    || to.filter(|x| start_time >= *x).is_some()
+                {
+                    continue;
+                }
+
+                for best in curves.get(&query)?.unwrap_or_default().bests {
+                    let record = PowerRecord {
+                        duration: best.duration,
+                        power: best.power,
+                        activity: ActivityRoot { query },
+                        start: best.start,
+                    };
+
+                    match records.iter_mut().find(|x| *x.duration == *best.duration) {
+                        Some(existing) if *best.power > *existing.power => *existing = record,
+                        Some(_) => (),
+                        None => records.push(record),
+                    }
+                }
+            }
+
+            records.sort_by_key(|x| *x.duration);
+
+            Ok(records)
+        })
+        .await?
+    }
+
+    #[graphql(guard = "OAuthGuard::new(Read(scopes::Activity))")]
+    async fn fitness(
+        &self,
+        ctx: &Context<'_>,
+        from: NaiveDate,
+        to: NaiveDate,
+        #[graphql(default)] planned: Vec<PlannedLoad>,
+    ) -> Result<FitnessSeries> {
+        if to < from || (to - from).num_days() > 3660 {
+            return Err(
+                "the date range must not end before it starts or span more than ten years".into(),
+            );
+        }
+
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+
+        tokio::task::spawn_blocking(move || {
+            let fitness = db
+                .root::<User>()?
+                .traverse::<Fitness>()?
+                .get(&query)?
+                .unwrap_or_default();
+
+            let mut projection = fitness.clone();
+
+            for load in &planned {
+                projection.add(load.date, load.tss);
+            }
+
+            let days = from
+                .iter_days()
+                .take_while(|date| *date <= to)
+                .map(|date| {
+                    let actual = fitness.day(date);
+                    let projected = projection.day(date);
+
+                    FitnessDay {
+                        date,
+                        tss: actual.stress,
+                        planned_tss: projected.stress - actual.stress,
+                        ctl: projected.ctl,
+                        atl: projected.atl,
+                        tsb: projection.form(date),
+                    }
+                })
+                .collect();
+
+            Ok(FitnessSeries {
+                ctl_days: fitness.ctl_days,
+                atl_days: fitness.atl_days,
+                days,
+            })
+        })
+        .await?
+    }
+
+    #[graphql(guard = "OAuthGuard::new(Read(scopes::Activity))")]
+    async fn time_in_zones(
+        &self,
+        ctx: &Context<'_>,
+        kind: ZoneKind,
+        period: Period,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<PeriodTimeInZones>> {
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+
+        tokio::task::spawn_blocking(move || {
+            let root = db.root::<User>()?;
+            let sessions = root.traverse::<Session>()?;
+            let collection = root.traverse::<Histograms>()?;
+            let top_level = root.traverse::<TopLevelActivity>()?;
+
+            let user = root.get(&query)?.unwrap_or_default();
+            let zones = root.traverse::<Zones>()?.get(&query)?.unwrap_or_default();
+            let thresholds = root
+                .traverse::<Thresholds>()?
+                .get(&query)?
+                .unwrap_or_default();
+
+            let total_count = top_level.keys(&query, 0, 0, false)?.total_count;
+
+            let mut periods: Vec<(NaiveDate, usize, Vec<f64>)> = Vec::new();
+
+            for query in top_level.keys(&query, 0, total_count, false)? {
+                let date = match sessions.get(&query)? {
+                    Some(session) => session.local_start_time().date_naive(),
+                    None => continue,
+                };
+
+                if from.filter(|x| date < *x).is_some() || to.filter(|x| date > *x).is_some() {
+                    continue;
+                }
+
+                let bounds = match zones.bounds(kind, date, &user, &thresholds) {
+                    Some(bounds) => bounds,
+                    None => continue,
+                };
+
+                let seconds = match collection.get(&query)? {
+                    Some(histograms) => histograms.activity.time_in_zones(kind, &bounds),
+                    None => continue,
+                };
+
+                let start = period.start(date);
+
+                let index = match periods.iter().position(|x| x.0 == start) {
+                    Some(index) => index,
+                    None => {
+                        periods.push((start, 0, Vec::new()));
+                        periods.len() - 1
+                    }
+                };
+
+                let (_, activities, total) = &mut periods[index];
+
+                // Zones may have been changed to more or fewer in the period
+                if total.len() < seconds.len() {
+                    total.resize(seconds.len(), 0.);
+                }
+
+                for (total, seconds) in total.iter_mut().zip(seconds) {
+                    *total += seconds;
+                }
+
+                *activities += 1;
+            }
+
+            periods.sort_by_key(|x| x.0);
+
+            Ok(periods
+                .into_iter()
+                .map(|(start, activities, seconds)| PeriodTimeInZones {
+                    start,
+                    activities,
+                    times: seconds.into(),
+                })
+                .collect())
+        })
+        .await?
+    }
+
+    #[graphql(guard = "OAuthGuard::new(Read(scopes::Activity))")]
+    async fn personal_records(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] current: bool,
+    ) -> Result<Vec<PersonalRecord>> {
+        let db = ctx.data_unchecked::<Database>().clone();
+        let query = self.query;
+
+        tokio::task::spawn_blocking(move || {
+            let mut history = db
+                .root::<User>()?
+                .traverse::<PersonalRecords>()?
+                .get(&query)?
+                .unwrap_or_default()
+                .history;
+
+            if current {
+                let mut latest: Vec<tf_models::user::PersonalRecord> = Vec::new();
+
+                for record in history.into_iter().rev() {
+                    if !latest
+                        .iter()
+                        .any(|x| *x.effort.distance == *record.effort.distance)
+                    {
+                        latest.push(record);
+                    }
+                }
+
+                latest.sort_by(|a, b| a.effort.distance.value.total_cmp(&b.effort.distance.value));
+                history = latest;
+            }
+
+            Ok(history
+                .into_iter()
+                .map(|record| PersonalRecord {
+                    distance: record.effort.distance,
+                    duration: record.effort.duration,
+                    activity: ActivityRoot {
+                        query: ActivityQuery {
+                            user_id: query.user_id,
+                            id: record.activity,
+                        },
+                    },
+                    start_time: record.start_time,
+                    start: record.effort.start,
+                })
+                .collect())
+        })
+        .await?
+    }
+
     #[graphql(guard = "OAuthGuard::new(Read(scopes::Gear))")]
     async fn default_gear(&self, ctx: &Context<'_>) -> Result<Option<GearRoot>> {
         let db = ctx.data_unchecked::<Database>().clone();
@@ -0,0 +1,31 @@
+use crate::guard::OAuthGuard;
+use futures_util::Stream;
+use tf_events::{Broker, ImportEvent};
+use tf_models::{import::ImportProgress, UserId};
+use tf_scopes::{self as scopes, Read};
+
+use async_graphql::{Context, Subscription};
+
+#[derive(Default)]
+pub struct ImportRoot;
+
+#[Subscription]
+impl ImportRoot {
+    #[graphql(guard = "OAuthGuard::new(Read(scopes::Activity))")]
+    async fn import_progress(
+        &self,
+        ctx: &Context<'_>,
+        user: UserId,
+    ) -> impl Stream<Item = ImportProgress> {
+        let receiver = ctx
+            .data_unchecked::<Broker>()
+            .subscribe::<ImportEvent>(user);
+
+        futures_util::stream::unfold(receiver, |mut receiver| async move {
+            receiver.changed().await.ok()?;
+            let progress = receiver.borrow().clone();
+
+            Some((progress, receiver))
+        })
+    }
+}
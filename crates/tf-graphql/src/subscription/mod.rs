@@ -1,4 +1,8 @@
 use async_graphql::MergedSubscription;
 
+mod import;
+
+use self::import::ImportRoot;
+
 #[derive(Default, MergedSubscription)]
-pub struct Subscription();
+pub struct Subscription(ImportRoot);
@@ -1,10 +1,14 @@
 use crate::Sport;
+use chrono::FixedOffset;
 use serde::{Deserialize, Serialize};
+use std::ops::Range;
 
 use crate::{
     types::{AngularVelocity, DateTime, Duration, Energy, LengthF64, LengthU32, Power, Velocity},
+    user::{Threshold, ZoneKind},
     ActivityId,
 };
+use uom::si::{power::watt, velocity::meter_per_second};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Activity {
@@ -12,10 +16,78 @@ pub struct Activity {
     pub session: Session,
     pub record: Record,
     pub lap: Vec<Lap>,
+    pub file_id: Option<FileId>,
+    /// The sports of a multisport activity, each as its own activity. A sport
+    /// is stored under its own id and linked to its parent, but it has no
+    /// record of its own, only the [`RecordRange`] of the parent it covers.
+    /// The sports are made again from the parent whenever it is written, and
+    /// they are counted in the fitness and listings of the user only through
+    /// the parent.
+    pub children: Vec<Activity>,
+    /// See `children`.
+    #[serde(default)]
+    pub record_range: Option<RecordRange>,
+    pub swim: Option<Swim>,
+    pub devices: Vec<Device>,
+    pub pauses: Vec<Pause>,
+}
+
+/// Identifies the device and recording an activity file came from, as given
+/// by the FIT `file_id` message.
+#[derive(Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct FileId {
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial_number: Option<u32>,
+    pub time_created: Option<DateTime>,
+}
+
+impl FileId {
+    /// Whether both files are known to come from the same device.
+    pub fn same_device(&self, other: &Self) -> bool {
+        self.serial_number.is_some()
+            && self.serial_number == other.serial_number
+            && self.manufacturer == other.manufacturer
+            && self.product == other.product
+    }
+}
+
+/// A device that took part in recording an activity, either the recording
+/// device itself or a paired sensor, as given by FIT `device_info` messages.
+#[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct Device {
+    /// Whether this is the device that recorded the file.
+    pub creator: bool,
+    pub device_type: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial_number: Option<u32>,
+    pub software_version: Option<f64>,
+    pub battery_status: Option<String>,
+    pub battery_voltage: Option<f64>,
+}
+
+impl Device {
+    /// Whether both are known to be the same physical device.
+    pub fn same_device(&self, other: &Self) -> bool {
+        self.serial_number.is_some()
+            && self.serial_number == other.serial_number
+            && self.manufacturer == other.manufacturer
+            && self.product == other.product
+    }
+}
+
+/// The file an activity was parsed from, kept so it can be parsed again.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct File {
+    pub data: Vec<u8>,
 }
 
 #[derive(Default, Clone, Copy, Serialize, Deserialize)]
 #[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+#[cfg_attr(feature = "graphql", graphql(complex))]
 pub struct Session {
     pub cadence_avg: Option<AngularVelocity>,
     pub cadence_max: Option<AngularVelocity>,
@@ -37,7 +109,44 @@ pub struct Session {
     pub distance: Option<LengthF64>,
     pub duration: Duration,
     pub duration_active: Duration,
+    /// Start time in UTC.
     pub start_time: DateTime,
+    /// Seconds east of UTC of the local time where the activity took place.
+    pub utc_offset: Option<i32>,
+    pub temperature_avg: Option<i8>,
+    pub temperature_max: Option<i8>,
+    pub left_right_balance_avg: Option<f64>,
+    pub vertical_oscillation_avg: Option<LengthF64>,
+    pub ground_contact_time_avg: Option<Duration>,
+    pub stance_time_balance_avg: Option<f64>,
+    pub step_length_avg: Option<LengthF64>,
+    pub respiration_rate_avg: Option<f64>,
+    /// Aerobic training effect as rated by the device, from 0 to 5.
+    pub training_effect: Option<f64>,
+    /// Anaerobic training effect as rated by the device, from 0 to 5.
+    pub anaerobic_training_effect: Option<f64>,
+}
+
+impl Session {
+    /// Wall-clock start time where the activity took place, or the start
+    /// time in UTC if the offset is not known.
+    pub fn local_start_time(&self) -> chrono::DateTime<FixedOffset> {
+        let offset = self
+            .utc_offset
+            .and_then(FixedOffset::east_opt)
+            .unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+
+        self.start_time.with_timezone(&offset)
+    }
+}
+
+#[cfg(feature = "graphql")]
+#[async_graphql::ComplexObject]
+impl Session {
+    #[graphql(name = "localStartTime")]
+    async fn resolve_local_start_time(&self) -> chrono::DateTime<FixedOffset> {
+        self.local_start_time()
+    }
 }
 
 #[derive(Default, Clone, Serialize, Deserialize)]
@@ -52,7 +161,130 @@ pub struct Record {
     pub lat: Vec<Option<f64>>,
     pub lon: Vec<Option<f64>>,
     pub timestamp: Vec<Option<DateTime>>,
+    /// Time elapsed since the start.
     pub duration: Vec<Duration>,
+    /// Time elapsed since the start, without pauses.
+    #[serde(default)]
+    pub timer_time: Vec<Duration>,
+    #[serde(default)]
+    pub temperature: Vec<Option<i8>>,
+    /// Percentage of power from the right side.
+    #[serde(default)]
+    pub left_right_balance: Vec<Option<f64>>,
+    #[serde(default)]
+    pub vertical_oscillation: Vec<Option<LengthF64>>,
+    #[serde(default)]
+    pub ground_contact_time: Vec<Option<Duration>>,
+    /// Percentage of ground contact time on the left foot.
+    #[serde(default)]
+    pub stance_time_balance: Vec<Option<f64>>,
+    #[serde(default)]
+    pub step_length: Vec<Option<LengthF64>>,
+    /// Breaths per minute.
+    #[serde(default)]
+    pub respiration_rate: Vec<Option<f64>>,
+    #[serde(default)]
+    pub developer_fields: Vec<DeveloperField>,
+}
+
+impl Record {
+    /// The records in `range`, with the durations and timer times counted
+    /// from the first of them. Channels missing in records stored before
+    /// they were added stay empty.
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        fn slice<T: Clone>(values: &[T], range: &Range<usize>) -> Vec<T> {
+            values
+                .get(range.clone())
+                .map(<[T]>::to_vec)
+                .unwrap_or_default()
+        }
+
+        let timestamp = slice(&self.timestamp, &range);
+        let start = timestamp.first().copied().flatten();
+        let timer_start = self
+            .timer_time
+            .get(range.start)
+            .map_or(0., |x| x.as_secs_f64());
+
+        Self {
+            cadence: slice(&self.cadence, &range),
+            distance: slice(&self.distance, &range),
+            altitude: slice(&self.altitude, &range),
+            speed: slice(&self.speed, &range),
+            heartrate: slice(&self.heartrate, &range),
+            power: slice(&self.power, &range),
+            lat: slice(&self.lat, &range),
+            lon: slice(&self.lon, &range),
+            duration: timestamp
+                .iter()
+                .map(|x| {
+                    x.zip(start)
+                        .and_then(|(x, start)| x.signed_duration_since(start).to_std().ok())
+                        .unwrap_or_default()
+                        .into()
+                })
+                .collect(),
+            timer_time: slice(&self.timer_time, &range)
+                .iter()
+                .map(|x| {
+                    std::time::Duration::from_secs_f64((x.as_secs_f64() - timer_start).max(0.))
+                        .into()
+                })
+                .collect(),
+            timestamp,
+            temperature: slice(&self.temperature, &range),
+            left_right_balance: slice(&self.left_right_balance, &range),
+            vertical_oscillation: slice(&self.vertical_oscillation, &range),
+            ground_contact_time: slice(&self.ground_contact_time, &range),
+            stance_time_balance: slice(&self.stance_time_balance, &range),
+            step_length: slice(&self.step_length, &range),
+            respiration_rate: slice(&self.respiration_rate, &range),
+            developer_fields: self
+                .developer_fields
+                .iter()
+                .map(|x| DeveloperField {
+                    developer_data_index: x.developer_data_index,
+                    field_number: x.field_number,
+                    name: x.name.clone(),
+                    units: x.units.clone(),
+                    native_field_num: x.native_field_num,
+                    values: slice(&x.values, &range),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The records of a multisport activity that one of its sports covers, see
+/// [`Activity::children`].
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct RecordRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl RecordRange {
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+}
+
+/// A channel recorded by a third party app or sensor through FIT developer
+/// fields, such as power from a Stryd footpod.
+#[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct DeveloperField {
+    /// The app the field comes from, by its index in the file.
+    #[serde(default)]
+    pub developer_data_index: u8,
+    /// The number of the field among those of the app.
+    #[serde(default)]
+    pub field_number: u8,
+    pub name: String,
+    pub units: Option<String>,
+    /// The number of the standard field this field replaces, if any.
+    pub native_field_num: Option<u8>,
+    pub values: Vec<Option<f64>>,
 }
 
 #[derive(Default, Serialize, Deserialize, Clone, Copy)]
@@ -76,4 +308,258 @@ pub struct Lap {
     pub distance: Option<LengthF64>,
     pub duration: Duration,
     pub duration_active: Duration,
+    pub temperature_avg: Option<i8>,
+    pub temperature_max: Option<i8>,
+    pub left_right_balance_avg: Option<f64>,
+    pub vertical_oscillation_avg: Option<LengthF64>,
+    pub ground_contact_time_avg: Option<Duration>,
+    pub stance_time_balance_avg: Option<f64>,
+    pub step_length_avg: Option<LengthF64>,
+    pub respiration_rate_avg: Option<f64>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::Enum))]
+pub enum PauseKind {
+    /// The timer was stopped by hand.
+    Manual,
+    /// The timer was stopped by auto-pause on the device.
+    Auto,
+    /// Standing still, found in the records of files without timer events.
+    Detected,
+}
+
+/// Name and description of an activity, such as those carried over when
+/// importing from another service.
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct Details {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+/// Altitude looked up in a digital elevation model, kept next to the
+/// altitude recorded by the device, which is noisy without a barometer.
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct Elevation {
+    /// Smoothed altitude of each record.
+    pub altitude: Vec<Option<LengthF64>>,
+    pub ascent: Option<LengthU32>,
+    pub descent: Option<LengthU32>,
+    /// Ascent and descent of each lap.
+    pub lap: Vec<ElevationChange>,
+}
+
+#[derive(Default, Clone, Copy, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct ElevationChange {
+    pub ascent: Option<LengthU32>,
+    pub descent: Option<LengthU32>,
+}
+
+/// The best average power held for a duration within an activity.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct PowerBest {
+    pub duration: Duration,
+    pub power: Power,
+    /// Time from the start of the activity to the start of the effort.
+    pub start: Duration,
+}
+
+/// Mean-maximal power of an activity, with the best average power for
+/// standard durations up to the length of the activity, shortest first.
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct PowerCurve {
+    pub bests: Vec<PowerBest>,
+}
+
+/// The fastest time over a standard distance within a run.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct BestEffort {
+    pub distance: LengthF64,
+    pub duration: Duration,
+    /// Time from the start of the activity to the start of the effort.
+    pub start: Duration,
+}
+
+/// What the training stress of an activity was worked out from.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::Enum))]
+pub enum StressSource {
+    Power,
+    Pace,
+    Heartrate,
+}
+
+/// Training load of an activity that does not depend on the thresholds of
+/// the user, so that intensity and stress are worked out with the thresholds
+/// in effect on the day, even when those are changed afterwards.
+#[derive(Default, Clone, Copy, Serialize, Deserialize)]
+pub struct Load {
+    /// Time with the timer running.
+    pub duration: Duration,
+    pub normalized_power: Option<Power>,
+    /// Speed weighted like normalized power, for runs.
+    pub normalized_speed: Option<Velocity>,
+    pub heartrate_avg: Option<u8>,
+    /// Banister training impulse, from the resting and maximum heart rate of
+    /// the user when the activity was added.
+    pub trimp: Option<f64>,
+}
+
+impl Load {
+    /// Intensity factor from normalized power over FTP, else from normalized
+    /// speed over threshold pace, else from average heart rate over LTHR.
+    pub fn intensity(&self, threshold: &Threshold) -> Option<(f64, StressSource)> {
+        let power = self
+            .normalized_power
+            .zip(threshold.ftp)
+            .filter(|(_, ftp)| ftp.get::<watt>() > 0)
+            .map(|(power, ftp)| {
+                (
+                    f64::from(power.get::<watt>()) / f64::from(ftp.get::<watt>()),
+                    StressSource::Power,
+                )
+            });
+
+        let pace = self
+            .normalized_speed
+            .zip(threshold.threshold_pace)
+            .filter(|(_, pace)| pace.as_secs_f64() > 0.)
+            .map(|(speed, pace)| {
+                (
+                    speed.get::<meter_per_second>() * pace.as_secs_f64() / 1000.,
+                    StressSource::Pace,
+                )
+            });
+
+        let heartrate = self
+            .heartrate_avg
+            .zip(threshold.lthr)
+            .filter(|(_, lthr)| *lthr > 0)
+            .map(|(heartrate, lthr)| {
+                (
+                    f64::from(heartrate) / f64::from(lthr),
+                    StressSource::Heartrate,
+                )
+            });
+
+        power.or(pace).or(heartrate)
+    }
+
+    /// Training stress score, where an hour at threshold scores 100. Called
+    /// TSS, rTSS or hrTSS depending on the source.
+    pub fn stress(&self, threshold: &Threshold) -> Option<(f64, StressSource)> {
+        self.intensity(threshold).map(|(intensity, source)| {
+            (
+                self.duration.as_secs_f64() / 3600. * intensity.powi(2) * 100.,
+                source,
+            )
+        })
+    }
+}
+
+/// Seconds spent at each heart rate and power, from which the time in zones
+/// is worked out with the zones in effect on the day.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct Histogram {
+    /// Seconds at each heart rate, by beats per minute.
+    pub heartrate: Vec<f64>,
+    /// Seconds at each power, by watts.
+    pub power: Vec<f64>,
+}
+
+impl Histogram {
+    /// Seconds below the first zone, followed by the seconds in each zone,
+    /// given the lower bound of each zone in beats per minute or watts.
+    pub fn time_in_zones(&self, kind: ZoneKind, bounds: &[f64]) -> Vec<f64> {
+        let values = match kind {
+            ZoneKind::Heartrate => &self.heartrate,
+            ZoneKind::Power => &self.power,
+        };
+
+        let mut seconds = vec![0.; bounds.len() + 1];
+
+        for (value, time) in values.iter().enumerate() {
+            let zone = bounds.iter().filter(|x| **x <= value as f64).count();
+            seconds[zone] += time;
+        }
+
+        seconds
+    }
+}
+
+/// Heart rate and power histograms of an activity and of each lap.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct Histograms {
+    pub activity: Histogram,
+    pub lap: Vec<Histogram>,
+}
+
+/// A span of time where the timer was stopped.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct Pause {
+    pub start_time: DateTime,
+    pub duration: Duration,
+    pub kind: PauseKind,
+}
+
+impl Pause {
+    pub fn end_time(&self) -> DateTime {
+        self.start_time + chrono::Duration::milliseconds(self.duration.as_millis() as i64)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::Enum))]
+pub enum Stroke {
+    Freestyle,
+    Backstroke,
+    Breaststroke,
+    Butterfly,
+    Drill,
+    Mixed,
+    IndividualMedley,
+}
+
+/// One length of the pool, or a rest between lengths if not active.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct SwimLength {
+    pub start_time: DateTime,
+    pub duration: Duration,
+    pub active: bool,
+    pub stroke: Option<Stroke>,
+    pub strokes: Option<u16>,
+    /// Seconds plus strokes for the length.
+    pub swolf: Option<u16>,
+}
+
+/// Consecutive lengths that are either all active or all rest.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct SwimInterval {
+    pub start_time: DateTime,
+    pub duration: Duration,
+    pub active: bool,
+    pub lengths: u16,
+    pub distance: Option<LengthF64>,
+    /// The stroke of all lengths, or `Mixed` if they differ.
+    pub stroke: Option<Stroke>,
+    pub strokes: Option<u16>,
+    pub swolf_avg: Option<f64>,
+}
+
+/// Pool lengths and intervals of a swimming activity.
+#[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct Swim {
+    pub pool_length: Option<LengthF64>,
+    pub lengths: Vec<SwimLength>,
+    pub intervals: Vec<SwimInterval>,
 }
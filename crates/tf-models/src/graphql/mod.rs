@@ -16,6 +16,14 @@ impl<T> AsRef<T> for Unit<T> {
     }
 }
 
+impl<T> std::ops::Deref for Unit<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 macro_rules! wrap_unit {
     ($name:ident, $storage_unit:ident, $unit:ident) => {
         wrap_unit!($name, $storage_unit, $unit, $name);
@@ -49,7 +57,7 @@ wrap_unit!(Length, f64, length, LengthF64);
 wrap_unit!(Power, u16, power);
 wrap_unit!(Velocity, f64, velocity);
 pub type Duration = Unit<std::time::Duration>;
-pub type DateTime = chrono::DateTime<chrono::Local>;
+pub type DateTime = chrono::DateTime<chrono::Utc>;
 
 async_graphql::scalar!(Duration);
 async_graphql::scalar!(ActivityId);
@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::Enum))]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStatus {
+    #[default]
+    Idle,
+    Running,
+    Finished,
+    Failed,
+}
+
+/// A file in an archive that could not be imported.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct ImportFailure {
+    pub file: String,
+    pub reason: String,
+}
+
+/// Progress of a bulk import of an archive, updated after each file.
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct ImportProgress {
+    pub status: ImportStatus,
+    /// Activity files found so far. Grows as archives within the archive
+    /// are opened.
+    pub total: usize,
+    pub imported: usize,
+    /// Files of activities that were already uploaded.
+    pub duplicates: usize,
+    pub failed: Vec<ImportFailure>,
+    /// Why the import stopped, if the archive itself could not be read.
+    pub error: Option<String>,
+}
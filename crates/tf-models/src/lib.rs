@@ -3,6 +3,7 @@ pub use sport::{Sport, SPORTS};
 pub mod activity;
 pub use activity::Activity;
 pub mod gear;
+pub mod import;
 pub mod query;
 pub mod user;
 
@@ -68,6 +69,12 @@ pub struct InvalidLengthError {
     actual: usize,
 }
 
+impl InvalidLengthError {
+    pub fn new(expected: usize, actual: usize) -> Self {
+        Self { expected, actual }
+    }
+}
+
 impl std::fmt::Display for InvalidLengthError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
@@ -129,9 +136,40 @@ macro_rules! declare_id {
 
 declare_id!(UserId, 21);
 declare_id!(GearId, 21);
-declare_id!(ActivityId, 12);
+declare_id!(ActivityId, 16);
 declare_id!(ClientId, 21);
 
+impl ActivityId {
+    pub const MAX_SEQUENCE: u8 = 99;
+
+    /// Id made from the start time down to the second, followed by a two
+    /// digit sequence number, so that ids sort chronologically. Usually the
+    /// local time where the activity took place. Fails for years that do not
+    /// have four digits.
+    pub fn from_start_time<Tz>(
+        start_time: &chrono::DateTime<Tz>,
+    ) -> Result<Self, InvalidLengthError>
+    where
+        Tz: chrono::TimeZone,
+        Tz::Offset: std::fmt::Display,
+    {
+        format!("{}00", start_time.format("%Y%m%d%H%M%S")).parse()
+    }
+
+    /// The id with the next sequence number, used when another activity
+    /// started within the same second already has this id.
+    pub fn next_sequence(&self) -> Option<Self> {
+        let (start_time, sequence) = self.as_str().split_at(Self::LENGTH - 2);
+        let sequence = sequence
+            .parse::<u8>()
+            .ok()?
+            .checked_add(1)
+            .filter(|x| *x <= Self::MAX_SEQUENCE)?;
+
+        format!("{}{:02}", start_time, sequence).parse().ok()
+    }
+}
+
 #[cfg(feature = "graphql")]
 #[path = "graphql/mod.rs"]
 pub mod types;
@@ -139,7 +177,7 @@ pub mod types;
 #[cfg(not(feature = "graphql"))]
 pub mod types {
     pub use std::time::Duration;
-    pub type DateTime = chrono::DateTime<chrono::Local>;
+    pub type DateTime = chrono::DateTime<chrono::Utc>;
     pub use uom::si::{
         f64::{AngularVelocity, Length as LengthF64, Velocity},
         u16::Power,
@@ -1,7 +1,7 @@
 use super::{ActivityId, ClientId, GearId, InvalidLengthError, UserId};
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 #[cfg_attr(feature = "graphql", derive(async_graphql::InputObject))]
 pub struct ActivityQuery {
     pub user_id: UserId,
@@ -27,6 +27,14 @@ pub struct UserQuery {
     pub user_id: UserId,
 }
 
+/// Looks up a user's activity by a hash, either of the uploaded file or of
+/// the device that recorded it.
+#[derive(Clone, Copy)]
+pub struct FingerprintQuery {
+    pub user_id: UserId,
+    pub fingerprint: [u8; 32],
+}
+
 impl std::fmt::Display for ActivityQuery {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}{}", self.user_id, self.id)
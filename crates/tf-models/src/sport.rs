@@ -175,6 +175,67 @@ impl std::str::FromStr for Sport {
     }
 }
 
+impl Sport {
+    /// The snake case name used by FIT and other file formats, the inverse of
+    /// `Sport::from_str`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Sport::Generic => "generic",
+            Sport::Running => "running",
+            Sport::Cycling => "cycling",
+            Sport::Transition => "transition",
+            Sport::FitnessEquipment => "fitness_equipment",
+            Sport::Swimming => "swimming",
+            Sport::Basketball => "basketball",
+            Sport::Soccer => "soccer",
+            Sport::Tennis => "tennis",
+            Sport::AmericanFootball => "american_football",
+            Sport::Training => "training",
+            Sport::Walking => "walking",
+            Sport::CrossCountrySkiing => "cross_country_skiing",
+            Sport::AlpineSkiing => "alpine_skiing",
+            Sport::Snowboarding => "snowboarding",
+            Sport::Rowing => "rowing",
+            Sport::Mountaineering => "mountaineering",
+            Sport::Hiking => "hiking",
+            Sport::Multisport => "multisport",
+            Sport::Paddling => "paddling",
+            Sport::Flying => "flying",
+            Sport::EBiking => "e_biking",
+            Sport::Motorcycling => "motorcycling",
+            Sport::Boating => "boating",
+            Sport::Driving => "driving",
+            Sport::Golf => "golf",
+            Sport::HangGliding => "hang_gliding",
+            Sport::HorsebackRiding => "horseback_riding",
+            Sport::Hunting => "hunting",
+            Sport::Fishing => "fishing",
+            Sport::InlineSkating => "inline_skating",
+            Sport::RockClimbing => "rock_climbing",
+            Sport::Sailing => "sailing",
+            Sport::IceSkating => "ice_skating",
+            Sport::SkyDiving => "sky_diving",
+            Sport::Snowshoeing => "snowshoeing",
+            Sport::Snowmobiling => "snowmobiling",
+            Sport::StandUpPaddleboarding => "stand_up_paddleboarding",
+            Sport::Surfing => "surfing",
+            Sport::Wakeboarding => "wakeboarding",
+            Sport::WaterSkiing => "water_skiing",
+            Sport::Kayaking => "kayaking",
+            Sport::Rafting => "rafting",
+            Sport::Windsurfing => "windsurfing",
+            Sport::Kitesurfing => "kitesurfing",
+            Sport::Tactical => "tactical",
+            Sport::Jumpmaster => "jumpmaster",
+            Sport::Boxing => "boxing",
+            Sport::FloorClimbing => "floor_climbing",
+            Sport::Diving => "diving",
+            Sport::All => "all",
+            Sport::Unknown => "unknown",
+        }
+    }
+}
+
 impl std::fmt::Display for Sport {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self {
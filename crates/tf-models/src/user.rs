@@ -1,4 +1,11 @@
 use serde::{Deserialize, Serialize};
+use uom::si::{length::meter, power::watt};
+
+use crate::{
+    activity::{BestEffort, Load, Session},
+    types::{DateTime, Duration, Power},
+    ActivityId,
+};
 
 #[derive(Clone, Default, Serialize, Deserialize)]
 #[cfg_attr(
@@ -13,31 +20,468 @@ pub struct User {
     pub heartrate_max: u8,
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+/// What training zones are of.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::Enum))]
+pub enum ZoneKind {
+    Heartrate,
+    Power,
+}
+
+/// What the bounds of training zones are given in.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::Enum))]
+pub enum ZoneUnit {
+    /// Fractions of the maximum heart rate, or of FTP for power.
+    Fraction,
+    /// Beats per minute, or watts for power.
+    Absolute,
+}
+
+/// Training zones of one kind from a day on, until the next change.
+#[derive(Clone, Serialize, Deserialize)]
 #[cfg_attr(
     feature = "graphql",
     derive(async_graphql::SimpleObject, async_graphql::InputObject)
 )]
+#[cfg_attr(feature = "graphql", graphql(input_name = "ZoneSetInput"))]
+pub struct ZoneSet {
+    pub kind: ZoneKind,
+    pub effective_from: chrono::NaiveDate,
+    pub unit: ZoneUnit,
+    /// Lower bound of each zone, lowest first.
+    pub bounds: Vec<f64>,
+}
+
+impl ZoneSet {
+    // Zones that have always been in effect, such as the defaults
+    fn fractions(kind: ZoneKind, bounds: &[f64]) -> Self {
+        Self {
+            kind,
+            effective_from: chrono::NaiveDate::from_ymd_opt(1970, 1, 1).unwrap(),
+            unit: ZoneUnit::Fraction,
+            bounds: bounds.to_vec(),
+        }
+    }
+
+    /// The bounds in beats per minute or watts, from the maximum heart rate
+    /// or FTP if given as fractions. `None` if that is not known.
+    pub fn absolute(&self, reference: Option<f64>) -> Option<Vec<f64>> {
+        match self.unit {
+            ZoneUnit::Absolute => Some(self.bounds.clone()),
+            ZoneUnit::Fraction => reference
+                .filter(|x| *x > 0.)
+                .map(|reference| self.bounds.iter().map(|x| x * reference).collect()),
+        }
+    }
+}
+
+/// Heart rate and power zones of a user as they changed over time, oldest
+/// first, so that activities are split into the zones of their day.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "StoredZones")]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct Zones {
+    pub heartrate: Vec<ZoneSet>,
+    pub power: Vec<ZoneSet>,
+}
+
+impl Default for Zones {
+    fn default() -> Self {
+        Self {
+            heartrate: vec![ZoneSet::fractions(
+                ZoneKind::Heartrate,
+                &[0.55, 0.72, 0.82, 0.87, 0.92],
+            )],
+            power: vec![ZoneSet::fractions(
+                ZoneKind::Power,
+                &[0., 0.56, 0.76, 0.91, 1.06, 1.21],
+            )],
+        }
+    }
+}
+
+impl Zones {
+    fn history(&self, kind: ZoneKind) -> &Vec<ZoneSet> {
+        match kind {
+            ZoneKind::Heartrate => &self.heartrate,
+            ZoneKind::Power => &self.power,
+        }
+    }
+
+    fn history_mut(&mut self, kind: ZoneKind) -> &mut Vec<ZoneSet> {
+        match kind {
+            ZoneKind::Heartrate => &mut self.heartrate,
+            ZoneKind::Power => &mut self.power,
+        }
+    }
+
+    /// Adds zones, replacing those of the same kind from the same day.
+    pub fn set(&mut self, mut zones: ZoneSet) {
+        zones.bounds.sort_by(f64::total_cmp);
+
+        self.remove(zones.kind, zones.effective_from);
+
+        let history = self.history_mut(zones.kind);
+        let index = history.partition_point(|x| x.effective_from < zones.effective_from);
+
+        history.insert(index, zones);
+    }
+
+    /// Removes the zones of a kind from a day. Returns whether there were
+    /// any.
+    pub fn remove(&mut self, kind: ZoneKind, effective_from: chrono::NaiveDate) -> bool {
+        let history = self.history_mut(kind);
+        let len = history.len();
+
+        history.retain(|x| x.effective_from != effective_from);
+
+        history.len() != len
+    }
+
+    /// The zones of a kind in effect on a day.
+    pub fn at(&self, kind: ZoneKind, date: chrono::NaiveDate) -> Option<&ZoneSet> {
+        self.history(kind)
+            .iter()
+            .rev()
+            .find(|x| x.effective_from <= date)
+    }
+
+    /// The lower bounds of the zones of a kind on a day in beats per minute
+    /// or watts, from the maximum heart rate of the user or the FTP of the
+    /// day for fractions.
+    pub fn bounds(
+        &self,
+        kind: ZoneKind,
+        date: chrono::NaiveDate,
+        user: &User,
+        thresholds: &Thresholds,
+    ) -> Option<Vec<f64>> {
+        let reference = match kind {
+            ZoneKind::Heartrate => Some(f64::from(user.heartrate_max)),
+            ZoneKind::Power => thresholds
+                .at(date)
+                .and_then(|x| x.ftp)
+                .map(|x| f64::from(x.get::<watt>())),
+        };
+
+        self.at(kind, date)?.absolute(reference)
+    }
+}
+
+// Zones used to be five fractions of the maximum heart rate, without dates.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredZones {
+    Current {
+        heartrate: Vec<ZoneSet>,
+        power: Vec<ZoneSet>,
+    },
+    Legacy {
+        z1: f32,
+        z2: f32,
+        z3: f32,
+        z4: f32,
+        z5: f32,
+    },
+}
+
+impl From<StoredZones> for Zones {
+    fn from(stored: StoredZones) -> Self {
+        match stored {
+            StoredZones::Current { heartrate, power } => Self { heartrate, power },
+            StoredZones::Legacy { z1, z2, z3, z4, z5 } => Self {
+                heartrate: vec![ZoneSet::fractions(
+                    ZoneKind::Heartrate,
+                    &[z1, z2, z3, z4, z5].map(f64::from),
+                )],
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Thresholds of a user from a day on, until the next change.
+#[derive(Clone, Copy, Serialize, Deserialize)]
 #[cfg_attr(
     feature = "graphql",
-    graphql(input_name = "ZonesInput", name = "Zones")
+    derive(async_graphql::SimpleObject, async_graphql::InputObject)
 )]
-pub struct Zones {
-    z1: f32,
-    z2: f32,
-    z3: f32,
-    z4: f32,
-    z5: f32,
+#[cfg_attr(feature = "graphql", graphql(input_name = "ThresholdInput"))]
+pub struct Threshold {
+    pub effective_from: chrono::NaiveDate,
+    /// Functional threshold power.
+    pub ftp: Option<Power>,
+    /// Time per kilometer at threshold.
+    pub threshold_pace: Option<Duration>,
+    /// Lactate threshold heart rate.
+    pub lthr: Option<u8>,
 }
 
-impl Default for Zones {
+/// Thresholds of a user as they changed over time, oldest first, so that
+/// activities are scored with the thresholds of their day.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Thresholds {
+    pub history: Vec<Threshold>,
+}
+
+impl Thresholds {
+    /// Adds thresholds, replacing those from the same day.
+    pub fn set(&mut self, threshold: Threshold) {
+        self.remove(threshold.effective_from);
+
+        let index = self
+            .history
+            .partition_point(|x| x.effective_from < threshold.effective_from);
+
+        self.history.insert(index, threshold);
+    }
+
+    /// Removes the thresholds from a day. Returns whether there were any.
+    pub fn remove(&mut self, effective_from: chrono::NaiveDate) -> bool {
+        let len = self.history.len();
+
+        self.history.retain(|x| x.effective_from != effective_from);
+
+        self.history.len() != len
+    }
+
+    /// The thresholds in effect on a day.
+    pub fn at(&self, date: chrono::NaiveDate) -> Option<&Threshold> {
+        self.history.iter().rev().find(|x| x.effective_from <= date)
+    }
+
+    /// The training stress of an activity with the thresholds of its day,
+    /// along with the day.
+    pub fn stress(&self, load: &Load, session: &Session) -> Option<(chrono::NaiveDate, f64)> {
+        let date = session.local_start_time().date_naive();
+
+        self.at(date)
+            .and_then(|x| load.stress(x))
+            .map(|(stress, _)| (date, stress))
+    }
+}
+
+/// Training stress of a user on a day, with the fitness and fatigue built up
+/// until then.
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct FitnessDay {
+    pub stress: f64,
+    /// Chronic training load.
+    pub ctl: f64,
+    /// Acute training load.
+    pub atl: f64,
+}
+
+/// Daily training stress of a user from the day of the first activity to
+/// the last, with fitness and fatigue as exponentially weighted averages of
+/// the stress over `ctl_days` and `atl_days`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Fitness {
+    pub ctl_days: u16,
+    pub atl_days: u16,
+    pub start: Option<chrono::NaiveDate>,
+    pub days: Vec<FitnessDay>,
+}
+
+impl Default for Fitness {
     fn default() -> Self {
         Self {
-            z1: 0.55,
-            z2: 0.72,
-            z3: 0.82,
-            z4: 0.87,
-            z5: 0.92,
+            ctl_days: 42,
+            atl_days: 7,
+            start: None,
+            days: Vec::new(),
+        }
+    }
+}
+
+impl Fitness {
+    /// The weight of the stress of a day in fitness and fatigue.
+    fn rates(&self) -> (f64, f64) {
+        (
+            1. / f64::from(self.ctl_days.max(1)),
+            1. / f64::from(self.atl_days.max(1)),
+        )
+    }
+
+    /// Fitness and fatigue on a day. They are zero before the first day, and
+    /// decay after the last.
+    pub fn day(&self, date: chrono::NaiveDate) -> FitnessDay {
+        let (index, last) = match (self.start, self.days.last()) {
+            (Some(start), Some(last)) => ((date - start).num_days(), last),
+            _ => return FitnessDay::default(),
+        };
+
+        match usize::try_from(index) {
+            Ok(index) if index < self.days.len() => self.days[index],
+            Ok(index) => {
+                let (ctl_rate, atl_rate) = self.rates();
+                let days = (index + 1 - self.days.len()) as i32;
+
+                FitnessDay {
+                    stress: 0.,
+                    ctl: last.ctl * (1. - ctl_rate).powi(days),
+                    atl: last.atl * (1. - atl_rate).powi(days),
+                }
+            }
+            Err(_) => FitnessDay::default(),
+        }
+    }
+
+    /// Form on a day, the balance of fitness and fatigue at the end of the
+    /// day before.
+    pub fn form(&self, date: chrono::NaiveDate) -> f64 {
+        let before = self.day(date - chrono::Duration::days(1));
+
+        before.ctl - before.atl
+    }
+
+    /// Adds stress on a day and carries it through to the last day. Negative
+    /// stress takes away what was added before.
+    pub fn add(&mut self, date: chrono::NaiveDate, stress: f64) {
+        let index = self.index(date);
+        let (ctl_rate, atl_rate) = self.rates();
+
+        self.days[index].stress += stress;
+
+        let (mut ctl, mut atl) = (stress * ctl_rate, stress * atl_rate);
+
+        for day in &mut self.days[index..] {
+            day.ctl += ctl;
+            day.atl += atl;
+            ctl *= 1. - ctl_rate;
+            atl *= 1. - atl_rate;
+        }
+    }
+
+    /// Replaces the stress of every day and works out fitness and fatigue
+    /// again.
+    pub fn rebuild<I>(&mut self, stress: I)
+    where
+        I: IntoIterator<Item = (chrono::NaiveDate, f64)>,
+    {
+        self.start = None;
+        self.days.clear();
+
+        for (date, stress) in stress {
+            let index = self.index(date);
+            self.days[index].stress += stress;
+        }
+
+        self.recompute();
+    }
+
+    /// Changes the time constants and works out fitness and fatigue again.
+    pub fn set_time_constants(&mut self, ctl_days: u16, atl_days: u16) {
+        self.ctl_days = ctl_days;
+        self.atl_days = atl_days;
+
+        self.recompute();
+    }
+
+    fn recompute(&mut self) {
+        let (ctl_rate, atl_rate) = self.rates();
+        let mut before = FitnessDay::default();
+
+        for day in &mut self.days {
+            day.ctl = before.ctl * (1. - ctl_rate) + day.stress * ctl_rate;
+            day.atl = before.atl * (1. - atl_rate) + day.stress * atl_rate;
+            before = *day;
         }
     }
+
+    /// The index of a day, adding days before the first or after the last
+    /// as needed.
+    fn index(&mut self, date: chrono::NaiveDate) -> usize {
+        let start = match self.start {
+            Some(start) if start <= date => start,
+            Some(start) => {
+                let missing = (start - date).num_days() as usize;
+                self.days.splice(0..0, vec![FitnessDay::default(); missing]);
+                date
+            }
+            None => date,
+        };
+
+        self.start = Some(start);
+
+        let index = (date - start).num_days() as usize;
+
+        while self.days.len() <= index {
+            let day = self.day(start + chrono::Duration::days(self.days.len() as i64));
+            self.days.push(day);
+        }
+
+        index
+    }
+}
+
+/// A best effort that was the fastest of the user over its distance when it
+/// was run.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct PersonalRecord {
+    pub activity: ActivityId,
+    /// Start time of the activity.
+    pub start_time: DateTime,
+    pub effort: BestEffort,
+}
+
+/// Every personal record of a user as it was set, oldest first, so that the
+/// last one of each distance is the current record.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct PersonalRecords {
+    pub history: Vec<PersonalRecord>,
+}
+
+impl PersonalRecords {
+    /// Adds the efforts of an activity that beat the record standing at its
+    /// start time, and drops later records that they beat as well. Activities
+    /// can be added in any order.
+    pub fn insert(&mut self, activity: ActivityId, start_time: DateTime, efforts: &[BestEffort]) {
+        for effort in efforts {
+            let distance = effort.distance.get::<meter>();
+            let seconds = effort.duration.as_secs_f64();
+            let same = |x: &PersonalRecord| x.effort.distance.get::<meter>() == distance;
+
+            let standing = self
+                .history
+                .iter()
+                .rev()
+                .find(|&x| same(x) && x.start_time <= start_time);
+
+            if let Some(standing) = standing {
+                if seconds >= standing.effort.duration.as_secs_f64() {
+                    continue;
+                }
+            }
+
+            self.history.retain(|x| {
+                !(same(x)
+                    && x.start_time > start_time
+                    && x.effort.duration.as_secs_f64() >= seconds)
+            });
+
+            let index = self.history.partition_point(|x| x.start_time <= start_time);
+
+            self.history.insert(
+                index,
+                PersonalRecord {
+                    activity,
+                    start_time,
+                    effort: *effort,
+                },
+            );
+        }
+    }
+
+    /// Removes the records set in an activity. Returns whether any were
+    /// removed, in which case efforts that were beaten by them may now be
+    /// records, and the history has to be made again from all efforts.
+    pub fn remove(&mut self, activity: ActivityId) -> bool {
+        let len = self.history.len();
+
+        self.history.retain(|x| x.activity != activity);
+
+        self.history.len() != len
+    }
 }
@@ -0,0 +1,17 @@
+const CRC_TABLE: [u16; 16] = [
+    0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401, 0xA001, 0x6C00, 0x7800, 0xB401,
+    0x5000, 0x9C01, 0x8801, 0x4400,
+];
+
+/// Continues the FIT checksum of the bytes before `data`, so that a file can
+/// be checked as it is read.
+pub(crate) fn update(crc: u16, data: &[u8]) -> u16 {
+    data.iter().fold(crc, |crc, byte| {
+        let tmp = CRC_TABLE[usize::from(crc & 0xF)];
+        let crc = (crc >> 4) & 0x0FFF;
+        let crc = crc ^ tmp ^ CRC_TABLE[usize::from(byte & 0xF)];
+        let tmp = CRC_TABLE[usize::from(crc & 0xF)];
+        let crc = (crc >> 4) & 0x0FFF;
+        crc ^ tmp ^ CRC_TABLE[usize::from((byte >> 4) & 0xF)]
+    })
+}
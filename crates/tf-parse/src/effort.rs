@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use tf_models::{activity::BestEffort, types::LengthF64, Activity, Sport};
+use uom::si::length::meter;
+
+/// Distances of the best efforts in meters: 400 m, 1 km, 1 mile, 5 km,
+/// 10 km, half marathon and marathon.
+pub const DISTANCES: [f64; 7] = [400., 1000., 1609.344, 5000., 10000., 21097.5, 42195.];
+
+/// The fastest time over each of `DISTANCES` that fits in a run, from the
+/// recorded distance. Empty for other sports.
+pub fn best_efforts(activity: &Activity) -> Vec<BestEffort> {
+    if activity.session.sport != Sport::Running {
+        return Vec::new();
+    }
+
+    // Seconds since the start and meters covered, skipping records where the
+    // distance goes back
+    let mut points: Vec<(f64, f64)> = Vec::new();
+
+    for (duration, distance) in activity
+        .record
+        .duration
+        .iter()
+        .zip(&activity.record.distance)
+    {
+        let meters = match distance {
+            Some(distance) => distance.get::<meter>(),
+            None => continue,
+        };
+
+        match points.last() {
+            Some(last) if meters < last.1 => (),
+            _ => points.push((duration.as_secs_f64(), meters)),
+        }
+    }
+
+    DISTANCES
+        .iter()
+        .filter_map(|distance| {
+            fastest(&points, *distance).map(|(start, duration)| BestEffort {
+                distance: LengthF64::new::<meter>(*distance),
+                duration: Duration::from_secs_f64(duration).into(),
+                start: Duration::from_secs_f64(start).into(),
+            })
+        })
+        .collect()
+}
+
+/// Start and duration of the fastest stretch of `distance` meters. The window
+/// ends at a record and starts between two records, where the distance left
+/// to the end is exactly `distance`.
+fn fastest(points: &[(f64, f64)], distance: f64) -> Option<(f64, f64)> {
+    let mut best: Option<(f64, f64)> = None;
+    let mut first = 0;
+
+    for &(end, covered) in points {
+        while first + 1 < points.len() && covered - points[first + 1].1 >= distance {
+            first += 1;
+        }
+
+        let (time, meters) = points[first];
+
+        if covered - meters < distance {
+            continue;
+        }
+
+        let start = match points.get(first + 1) {
+            Some(&(next_time, next_meters)) if next_meters > meters => {
+                let fraction = (covered - distance - meters) / (next_meters - meters);
+
+                time + (next_time - time) * fraction.clamp(0., 1.)
+            }
+            _ => time,
+        };
+
+        let duration = end - start;
+
+        if duration <= 0. {
+            continue;
+        }
+
+        match best {
+            Some((_, fastest)) if fastest <= duration => (),
+            _ => best = Some((start, duration)),
+        }
+    }
+
+    best
+}
@@ -0,0 +1,438 @@
+use std::{
+    fs,
+    io::BufReader,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    error::{Error, Result},
+    summary::{elevation_change, haversine},
+};
+use tf_models::{
+    activity::{Elevation, ElevationChange, Lap, Record},
+    types::{LengthF64, LengthU32},
+    Activity,
+};
+use tiff::{
+    decoder::{Decoder, DecodingResult},
+    tags::Tag,
+};
+use uom::si::length::meter;
+
+// Altitude is averaged over this distance along the track, as positions
+// jitter by a few meters and the models have samples 30 to 90 meters apart.
+const SMOOTHING_DISTANCE: f64 = 100.;
+
+// Tiles kept in memory, enough for an activity around a corner of four tiles
+const LOADED_TILES: usize = 4;
+
+// Samples below this are voids, such as -32768 in SRTM tiles
+const VOID: f32 = -1000.;
+
+// GeoTIFF tags and keys
+const MODEL_PIXEL_SCALE: u16 = 33550;
+const MODEL_TIEPOINT: u16 = 33922;
+const GEO_KEY_DIRECTORY: u16 = 34735;
+const GDAL_NODATA: u16 = 42113;
+const RASTER_TYPE: u16 = 1025;
+const RASTER_PIXEL_IS_POINT: u16 = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TileFormat {
+    Hgt,
+    GeoTiff,
+}
+
+/// Where the samples of a tile are, in degrees.
+#[derive(Clone, Copy)]
+struct Grid {
+    width: usize,
+    height: usize,
+    /// Longitude of the first column.
+    west: f64,
+    /// Latitude of the first row.
+    north: f64,
+    /// Degrees between columns.
+    step_lon: f64,
+    /// Degrees between rows, going south.
+    step_lat: f64,
+}
+
+impl Grid {
+    /// Fractional column and row of a position, if it is within the grid.
+    fn position(&self, lat: f64, lon: f64) -> Option<(f64, f64)> {
+        let x = (lon - self.west) / self.step_lon;
+        let y = (self.north - lat) / self.step_lat;
+
+        let inside = (0. ..=(self.width - 1) as f64).contains(&x)
+            && (0. ..=(self.height - 1) as f64).contains(&y);
+
+        inside.then_some((x, y))
+    }
+}
+
+struct Tile {
+    path: PathBuf,
+    format: TileFormat,
+    grid: Grid,
+    nodata: Option<f32>,
+}
+
+impl Tile {
+    fn valid(&self, value: f32) -> bool {
+        value.is_finite() && value > VOID && Some(value) != self.nodata
+    }
+
+    /// Interpolates between the four samples around a position, leaving out
+    /// voids.
+    fn interpolate(&self, samples: &[f32], x: f64, y: f64) -> Option<f64> {
+        let width = self.grid.width;
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(self.grid.height - 1));
+        let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+
+        let (sum, weights) = [
+            (x0, y0, (1. - fx) * (1. - fy)),
+            (x1, y0, fx * (1. - fy)),
+            (x0, y1, (1. - fx) * fy),
+            (x1, y1, fx * fy),
+        ]
+        .into_iter()
+        .filter_map(|(x, y, weight)| {
+            let value = *samples.get(y * width + x)?;
+            self.valid(value)
+                .then_some((f64::from(value) * weight, weight))
+        })
+        .fold((0., 0.), |(sum, weights), (value, weight)| {
+            (sum + value, weights + weight)
+        });
+
+        (weights > f64::EPSILON).then_some(sum / weights)
+    }
+
+    fn load(&self) -> Result<Vec<f32>> {
+        let samples = match self.format {
+            TileFormat::Hgt => fs::read(&self.path)?
+                .chunks_exact(2)
+                .map(|x| f32::from(i16::from_be_bytes([x[0], x[1]])))
+                .collect(),
+            TileFormat::GeoTiff => {
+                let mut decoder = geotiff(&self.path)?;
+
+                match decoder.read_image().map_err(|x| invalid(&self.path, x))? {
+                    DecodingResult::I16(x) => x.into_iter().map(f32::from).collect(),
+                    DecodingResult::U16(x) => x.into_iter().map(f32::from).collect(),
+                    DecodingResult::I32(x) => x.into_iter().map(|x| x as f32).collect(),
+                    DecodingResult::F32(x) => x,
+                    DecodingResult::F64(x) => x.into_iter().map(|x| x as f32).collect(),
+                    _ => return Err(invalid(&self.path, "unsupported sample format")),
+                }
+            }
+        };
+
+        if samples.len() != self.grid.width * self.grid.height {
+            return Err(invalid(&self.path, "expected a single band of samples"));
+        }
+
+        Ok(samples)
+    }
+}
+
+/// A digital elevation model made of SRTM or Copernicus tiles in a
+/// directory, either as `.hgt` files named after their south west corner,
+/// like `N59E010.hgt`, or as GeoTIFF files in latitude and longitude. Tiles
+/// are read when first needed, and only a few are kept in memory.
+pub struct Dem {
+    tiles: Vec<Tile>,
+    // Most recently used last
+    loaded: Mutex<Vec<(usize, Arc<Vec<f32>>)>>,
+}
+
+impl Dem {
+    /// Finds the tiles in a directory. Only the headers of GeoTIFF files are
+    /// read.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut tiles = Vec::new();
+
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+            let extension = path
+                .extension()
+                .map(|x| x.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+
+            tiles.push(match extension.as_str() {
+                "hgt" => hgt_tile(path)?,
+                "tif" | "tiff" => geotiff_tile(path)?,
+                _ => continue,
+            });
+        }
+
+        Ok(Self {
+            tiles,
+            loaded: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Altitude in meters at a position, or `None` outside the tiles and in
+    /// voids.
+    pub fn altitude(&self, lat: f64, lon: f64) -> Option<f64> {
+        self.tiles.iter().enumerate().find_map(|(index, tile)| {
+            let (x, y) = tile.grid.position(lat, lon)?;
+            let samples = self.samples(index).ok()?;
+
+            tile.interpolate(&samples, x, y)
+        })
+    }
+
+    fn samples(&self, index: usize) -> Result<Arc<Vec<f32>>> {
+        let mut loaded = self.loaded.lock().unwrap();
+
+        if let Some(position) = loaded.iter().position(|(x, _)| *x == index) {
+            let entry = loaded.remove(position);
+            let samples = entry.1.clone();
+            loaded.push(entry);
+
+            return Ok(samples);
+        }
+
+        let samples = Arc::new(self.tiles[index].load()?);
+
+        if loaded.len() == LOADED_TILES {
+            loaded.remove(0);
+        }
+        loaded.push((index, samples.clone()));
+
+        Ok(samples)
+    }
+}
+
+fn invalid<T: ToString>(path: &Path, reason: T) -> Error {
+    Error::InvalidTile {
+        path: path.display().to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+/// The south west corner of an SRTM tile from its name.
+fn corner(name: &str) -> Option<(f64, f64)> {
+    let (lat, lon) = name.split_at(name.find(['E', 'W'])?);
+
+    let degrees = |value: &str, positive: char, negative: char| match value.strip_prefix(positive) {
+        Some(x) => x.parse::<f64>().ok(),
+        None => value
+            .strip_prefix(negative)?
+            .parse::<f64>()
+            .ok()
+            .map(|x| -x),
+    };
+
+    let lat = degrees(lat, 'N', 'S')?;
+    let lon = degrees(lon, 'E', 'W')?;
+
+    Some((lat, lon))
+}
+
+/// An SRTM tile, which has a square of big endian samples covering one
+/// degree, with the edges shared with the next tiles.
+fn hgt_tile(path: PathBuf) -> Result<Tile> {
+    let name = path
+        .file_stem()
+        .map(|x| x.to_string_lossy().to_uppercase())
+        .unwrap_or_default();
+
+    let (lat, lon) = corner(&name).ok_or_else(|| invalid(&path, "expected a name like N59E010"))?;
+
+    let len = fs::metadata(&path)?.len() as usize / 2;
+    let size = (len as f64).sqrt() as usize;
+
+    if size < 2 || size * size != len {
+        return Err(invalid(&path, "expected a square of samples"));
+    }
+
+    Ok(Tile {
+        grid: Grid {
+            width: size,
+            height: size,
+            west: lon,
+            north: lat + 1.,
+            step_lon: 1. / (size - 1) as f64,
+            step_lat: 1. / (size - 1) as f64,
+        },
+        path,
+        format: TileFormat::Hgt,
+        nodata: None,
+    })
+}
+
+fn geotiff(path: &Path) -> Result<Decoder<BufReader<fs::File>>> {
+    Decoder::new(BufReader::new(fs::File::open(path)?)).map_err(|x| invalid(path, x))
+}
+
+/// A GeoTIFF tile placed by a tie point and pixel scale, as in Copernicus
+/// and most other elevation models.
+fn geotiff_tile(path: PathBuf) -> Result<Tile> {
+    let mut decoder = geotiff(&path)?;
+
+    let (width, height) = decoder.dimensions().map_err(|x| invalid(&path, x))?;
+    let scale = decoder
+        .get_tag_f64_vec(Tag::from_u16_exhaustive(MODEL_PIXEL_SCALE))
+        .map_err(|x| invalid(&path, x))?;
+    let tiepoint = decoder
+        .get_tag_f64_vec(Tag::from_u16_exhaustive(MODEL_TIEPOINT))
+        .map_err(|x| invalid(&path, x))?;
+
+    let (step_lon, step_lat) = match scale.as_slice() {
+        [x, y, ..] => (*x, *y),
+        _ => return Err(invalid(&path, "missing pixel scale")),
+    };
+    let (i, j, lon, lat) = match tiepoint.as_slice() {
+        [i, j, _, lon, lat, ..] => (*i, *j, *lon, *lat),
+        _ => return Err(invalid(&path, "missing tie point")),
+    };
+
+    // Keys are stored as four values each, after a header of four values
+    let pixel_is_point = decoder
+        .get_tag_u16_vec(Tag::from_u16_exhaustive(GEO_KEY_DIRECTORY))
+        .ok()
+        .and_then(|keys| {
+            keys.chunks_exact(4)
+                .skip(1)
+                .find(|key| key[0] == RASTER_TYPE)
+                .map(|key| key[3] == RASTER_PIXEL_IS_POINT)
+        })
+        .unwrap_or_default();
+
+    // Tie points of areas are at the corner of the pixel, not the center
+    let offset = if pixel_is_point { 0. } else { 0.5 };
+
+    let nodata = decoder
+        .get_tag_ascii_string(Tag::from_u16_exhaustive(GDAL_NODATA))
+        .ok()
+        .and_then(|x| x.trim_matches(char::from(0)).trim().parse().ok());
+
+    Ok(Tile {
+        grid: Grid {
+            width: width as usize,
+            height: height as usize,
+            west: lon + (offset - i) * step_lon,
+            north: lat - (offset - j) * step_lat,
+            step_lon,
+            step_lat,
+        },
+        path,
+        format: TileFormat::GeoTiff,
+        nodata,
+    })
+}
+
+/// Averages the altitude of each point with the points within
+/// `SMOOTHING_DISTANCE` along the track.
+fn smooth(record: &Record, altitude: &[Option<f64>]) -> Vec<Option<f64>> {
+    let mut points = Vec::new();
+    let mut distance = 0.;
+    let mut last = None;
+
+    for (i, altitude) in altitude.iter().enumerate() {
+        let position = record.lat[i].zip(record.lon[i]);
+
+        if let Some((altitude, position)) = altitude.zip(position) {
+            if let Some(last) = last {
+                distance += haversine(last, position);
+            }
+            last = Some(position);
+            points.push((i, distance, altitude));
+        }
+    }
+
+    let mut smoothed = vec![None; altitude.len()];
+    let (mut start, mut end, mut sum) = (0, 0, 0.);
+
+    for &(i, distance, _) in &points {
+        while end < points.len() && points[end].1 <= distance + SMOOTHING_DISTANCE / 2. {
+            sum += points[end].2;
+            end += 1;
+        }
+        while points[start].1 < distance - SMOOTHING_DISTANCE / 2. {
+            sum -= points[start].2;
+            start += 1;
+        }
+
+        smoothed[i] = Some(sum / (end - start) as f64);
+    }
+
+    smoothed
+}
+
+/// The records of each lap, found from the lap durations as laps follow
+/// each other.
+pub(crate) fn lap_ranges(laps: &[Lap], record: &Record) -> Vec<Range<usize>> {
+    let mut start = 0;
+    let mut end_time = 0.;
+
+    laps.iter()
+        .enumerate()
+        .map(|(i, lap)| {
+            end_time += lap.duration.as_secs_f64();
+
+            let end = if i + 1 == laps.len() {
+                record.duration.len()
+            } else {
+                record
+                    .duration
+                    .partition_point(|x| x.as_secs_f64() < end_time)
+                    .max(start)
+            };
+
+            let range = start..end;
+            start = end;
+            range
+        })
+        .collect()
+}
+
+fn change(altitude: &[Option<f64>]) -> ElevationChange {
+    let (ascent, descent) = elevation_change(altitude.iter().filter_map(|x| *x));
+    let length = |x: f64| LengthU32::new::<meter>(x.round() as u32);
+
+    ElevationChange {
+        ascent: ascent.map(length),
+        descent: descent.map(length),
+    }
+}
+
+/// Looks up the altitude of each record in the model, smooths it, and sums
+/// ascent and descent of the session and laps from it. `None` if no record
+/// has a position covered by the model.
+pub fn correct(activity: &Activity, dem: &Dem) -> Option<Elevation> {
+    let record = &activity.record;
+
+    let altitude = record
+        .lat
+        .iter()
+        .zip(&record.lon)
+        .map(|(lat, lon)| lat.zip(*lon).and_then(|(lat, lon)| dem.altitude(lat, lon)))
+        .collect::<Vec<_>>();
+
+    if altitude.iter().all(Option::is_none) {
+        return None;
+    }
+
+    let altitude = smooth(record, &altitude);
+    let session = change(&altitude);
+
+    Some(Elevation {
+        lap: lap_ranges(&activity.lap, record)
+            .into_iter()
+            .map(|range| change(altitude.get(range).unwrap_or_default()))
+            .collect(),
+        altitude: altitude
+            .into_iter()
+            .map(|x| x.map(LengthF64::new::<meter>))
+            .collect(),
+        ascent: session.ascent,
+        descent: session.descent,
+    })
+}
@@ -10,6 +10,40 @@ pub enum Error {
         source: fitparser::Error,
     },
 
+    #[error("Invalid or corrupt XML file.")]
+    XmlError {
+        #[from]
+        source: quick_xml::Error,
+    },
+
+    #[error("Could not read file.")]
+    Io {
+        #[from]
+        source: std::io::Error,
+    },
+
+    #[error("Unsupported file format.")]
+    UnknownFormat,
+
     #[error("Missing vital information.")]
     MissingData,
+
+    #[error("Start time out of range.")]
+    InvalidStartTime,
+
+    #[error("Message {index} is missing the {field} field.")]
+    MissingField { index: usize, field: &'static str },
+
+    #[error("Message {index} at byte {offset} can not be decoded: {reason}")]
+    Decode {
+        index: usize,
+        offset: usize,
+        reason: String,
+    },
+
+    #[error("Checksum mismatch.")]
+    InvalidChecksum,
+
+    #[error("Invalid elevation tile {path}: {reason}")]
+    InvalidTile { path: String, reason: String },
 }
@@ -0,0 +1,78 @@
+use tf_models::Activity;
+use uom::si::{
+    angular_velocity::revolution_per_minute, length::meter, power::watt, velocity::meter_per_second,
+};
+
+const HEADER: &str = "timestamp,duration,lat,lon,altitude,distance,speed,heartrate,cadence,power";
+
+fn cell<T: ToString>(value: Option<T>) -> String {
+    value.map(|x| x.to_string()).unwrap_or_default()
+}
+
+pub fn export(activity: &Activity) -> String {
+    let record = &activity.record;
+    let mut output = String::from(HEADER);
+
+    for i in 0..record.duration.len() {
+        let row = [
+            cell(
+                record
+                    .timestamp
+                    .get(i)
+                    .copied()
+                    .flatten()
+                    .map(|x| x.to_rfc3339()),
+            ),
+            cell(record.duration.get(i).map(|x| x.as_secs_f64())),
+            cell(record.lat.get(i).copied().flatten()),
+            cell(record.lon.get(i).copied().flatten()),
+            cell(
+                record
+                    .altitude
+                    .get(i)
+                    .copied()
+                    .flatten()
+                    .map(|x| x.get::<meter>()),
+            ),
+            cell(
+                record
+                    .distance
+                    .get(i)
+                    .copied()
+                    .flatten()
+                    .map(|x| x.get::<meter>()),
+            ),
+            cell(
+                record
+                    .speed
+                    .get(i)
+                    .copied()
+                    .flatten()
+                    .map(|x| x.get::<meter_per_second>()),
+            ),
+            cell(record.heartrate.get(i).copied().flatten()),
+            cell(
+                record
+                    .cadence
+                    .get(i)
+                    .copied()
+                    .flatten()
+                    .map(|x| x.get::<revolution_per_minute>()),
+            ),
+            cell(
+                record
+                    .power
+                    .get(i)
+                    .copied()
+                    .flatten()
+                    .map(|x| x.get::<watt>()),
+            ),
+        ];
+
+        output.push('\n');
+        output.push_str(&row.join(","));
+    }
+
+    output.push('\n');
+    output
+}
@@ -0,0 +1,447 @@
+use chrono::{offset::Utc, DateTime};
+use tf_models::{Activity, Sport, SPORTS};
+use uom::si::{
+    angular_velocity::revolution_per_minute, energy::kilocalorie, length::meter, power::watt,
+    velocity::meter_per_second,
+};
+
+// Seconds from the Unix epoch to the FIT epoch, 1989-12-31T00:00:00Z
+const FIT_EPOCH: i64 = 631_065_600;
+const SEMICIRCLES: f64 = (2_u32 << 30) as f64 / 180_f64;
+const PROFILE_VERSION: u16 = 2132;
+
+mod mesg_num {
+    pub const FILE_ID: u16 = 0;
+    pub const SESSION: u16 = 18;
+    pub const LAP: u16 = 19;
+    pub const RECORD: u16 = 20;
+    pub const ACTIVITY: u16 = 34;
+}
+
+fn crc(data: &[u8]) -> u16 {
+    crate::crc::update(0, data)
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Enum(Option<u8>),
+    UInt8(Option<u8>),
+    UInt16(Option<u16>),
+    UInt32(Option<u32>),
+    SInt32(Option<i32>),
+}
+
+impl Field {
+    fn base_type(&self) -> u8 {
+        match self {
+            Self::Enum(_) => 0x00,
+            Self::UInt8(_) => 0x02,
+            Self::UInt16(_) => 0x84,
+            Self::SInt32(_) => 0x85,
+            Self::UInt32(_) => 0x86,
+        }
+    }
+
+    fn size(&self) -> u8 {
+        match self {
+            Self::Enum(_) | Self::UInt8(_) => 1,
+            Self::UInt16(_) => 2,
+            Self::UInt32(_) | Self::SInt32(_) => 4,
+        }
+    }
+
+    /// Writes the value, or the invalid value of the base type if missing.
+    fn write(&self, buffer: &mut Vec<u8>) {
+        match *self {
+            Self::Enum(x) | Self::UInt8(x) => buffer.push(x.unwrap_or(u8::MAX)),
+            Self::UInt16(x) => buffer.extend(x.unwrap_or(u16::MAX).to_le_bytes()),
+            Self::UInt32(x) => buffer.extend(x.unwrap_or(u32::MAX).to_le_bytes()),
+            Self::SInt32(x) => buffer.extend(x.unwrap_or(i32::MAX).to_le_bytes()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Encoder {
+    buffer: Vec<u8>,
+    // Global message number of each local message type defined so far
+    definitions: Vec<u16>,
+}
+
+impl Encoder {
+    fn message(&mut self, global: u16, fields: &[(u8, Field)]) {
+        let local = match self.definitions.iter().position(|x| *x == global) {
+            Some(local) => local as u8,
+            None => {
+                let local = self.definitions.len() as u8;
+                self.definitions.push(global);
+
+                self.buffer.push(0x40 | local);
+                // Reserved byte and little endian architecture
+                self.buffer.extend([0, 0]);
+                self.buffer.extend(global.to_le_bytes());
+                self.buffer.push(fields.len() as u8);
+
+                for (number, field) in fields {
+                    self.buffer
+                        .extend([*number, field.size(), field.base_type()]);
+                }
+
+                local
+            }
+        };
+
+        self.buffer.push(local);
+
+        for (_, field) in fields {
+            field.write(&mut self.buffer);
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut output = Vec::with_capacity(self.buffer.len() + 16);
+
+        output.push(14);
+        output.push(0x20);
+        output.extend(PROFILE_VERSION.to_le_bytes());
+        output.extend((self.buffer.len() as u32).to_le_bytes());
+        output.extend(b".FIT");
+        output.extend(crc(&output).to_le_bytes());
+
+        output.extend(self.buffer);
+        output.extend(crc(&output).to_le_bytes());
+
+        output
+    }
+}
+
+fn timestamp(x: DateTime<Utc>) -> Option<u32> {
+    u32::try_from(x.timestamp() - FIT_EPOCH).ok()
+}
+
+fn semicircles(x: Option<f64>) -> Field {
+    Field::SInt32(x.map(|x| (x * SEMICIRCLES).round() as i32))
+}
+
+fn scaled(x: Option<f64>, scale: f64, offset: f64) -> Field {
+    Field::UInt32(x.map(|x| ((x + offset) * scale).round() as u32))
+}
+
+fn sport(sport: Sport) -> Option<u8> {
+    match sport {
+        Sport::Diving => Some(53),
+        Sport::All => Some(254),
+        Sport::Unknown => None,
+        sport => SPORTS.iter().position(|x| *x == sport).map(|x| x as u8),
+    }
+}
+
+pub fn export(activity: &Activity) -> Vec<u8> {
+    let Activity {
+        session,
+        record,
+        lap,
+        ..
+    } = activity;
+    let mut encoder = Encoder::default();
+
+    let start_time = timestamp(session.start_time);
+    let end_time = session.start_time
+        + chrono::Duration::milliseconds((session.duration.as_secs_f64() * 1000.) as i64);
+
+    encoder.message(
+        mesg_num::FILE_ID,
+        &[
+            // Activity file made by a development manufacturer
+            (0, Field::Enum(Some(4))),
+            (1, Field::UInt16(Some(255))),
+            (2, Field::UInt16(Some(0))),
+            (4, Field::UInt32(start_time)),
+        ],
+    );
+
+    for i in 0..record.duration.len() {
+        encoder.message(
+            mesg_num::RECORD,
+            &[
+                (
+                    253,
+                    Field::UInt32(
+                        record
+                            .timestamp
+                            .get(i)
+                            .copied()
+                            .flatten()
+                            .and_then(timestamp),
+                    ),
+                ),
+                (0, semicircles(record.lat.get(i).copied().flatten())),
+                (1, semicircles(record.lon.get(i).copied().flatten())),
+                (3, Field::UInt8(record.heartrate.get(i).copied().flatten())),
+                (
+                    4,
+                    Field::UInt8(
+                        record
+                            .cadence
+                            .get(i)
+                            .copied()
+                            .flatten()
+                            .map(|x| x.get::<revolution_per_minute>().round() as u8),
+                    ),
+                ),
+                (
+                    5,
+                    scaled(
+                        record
+                            .distance
+                            .get(i)
+                            .copied()
+                            .flatten()
+                            .map(|x| x.get::<meter>()),
+                        100.,
+                        0.,
+                    ),
+                ),
+                (
+                    7,
+                    Field::UInt16(
+                        record
+                            .power
+                            .get(i)
+                            .copied()
+                            .flatten()
+                            .map(|x| x.get::<watt>()),
+                    ),
+                ),
+                (
+                    73,
+                    scaled(
+                        record
+                            .speed
+                            .get(i)
+                            .copied()
+                            .flatten()
+                            .map(|x| x.get::<meter_per_second>()),
+                        1000.,
+                        0.,
+                    ),
+                ),
+                (
+                    78,
+                    scaled(
+                        record
+                            .altitude
+                            .get(i)
+                            .copied()
+                            .flatten()
+                            .map(|x| x.get::<meter>()),
+                        5.,
+                        500.,
+                    ),
+                ),
+            ],
+        );
+    }
+
+    for (lap, lap_start) in lap.iter().zip(super::lap_start_times(activity)) {
+        let lap_end =
+            lap_start + chrono::Duration::milliseconds((lap.duration.as_secs_f64() * 1000.) as i64);
+
+        encoder.message(
+            mesg_num::LAP,
+            &[
+                (253, Field::UInt32(timestamp(lap_end))),
+                // Lap stop event
+                (0, Field::Enum(Some(9))),
+                (1, Field::Enum(Some(1))),
+                (2, Field::UInt32(timestamp(lap_start))),
+                (3, semicircles(lap.lat_start)),
+                (4, semicircles(lap.lon_start)),
+                (5, semicircles(lap.lat_end)),
+                (6, semicircles(lap.lon_end)),
+                (7, scaled(Some(lap.duration.as_secs_f64()), 1000., 0.)),
+                (
+                    8,
+                    scaled(Some(lap.duration_active.as_secs_f64()), 1000., 0.),
+                ),
+                (9, scaled(lap.distance.map(|x| x.get::<meter>()), 100., 0.)),
+                (
+                    11,
+                    Field::UInt16(
+                        lap.calories
+                            .and_then(|x| u16::try_from(x.get::<kilocalorie>()).ok()),
+                    ),
+                ),
+                (15, Field::UInt8(lap.heartrate_avg)),
+                (16, Field::UInt8(lap.heartrate_max)),
+                (
+                    17,
+                    Field::UInt8(
+                        lap.cadence_avg
+                            .map(|x| x.get::<revolution_per_minute>().round() as u8),
+                    ),
+                ),
+                (
+                    18,
+                    Field::UInt8(
+                        lap.cadence_max
+                            .map(|x| x.get::<revolution_per_minute>().round() as u8),
+                    ),
+                ),
+                (19, Field::UInt16(lap.power_avg.map(|x| x.get::<watt>()))),
+                (20, Field::UInt16(lap.power_max.map(|x| x.get::<watt>()))),
+                (
+                    21,
+                    Field::UInt16(
+                        lap.ascent
+                            .and_then(|x| u16::try_from(x.get::<meter>()).ok()),
+                    ),
+                ),
+                (
+                    22,
+                    Field::UInt16(
+                        lap.descent
+                            .and_then(|x| u16::try_from(x.get::<meter>()).ok()),
+                    ),
+                ),
+                (
+                    110,
+                    scaled(
+                        lap.speed_avg.map(|x| x.get::<meter_per_second>()),
+                        1000.,
+                        0.,
+                    ),
+                ),
+                (
+                    111,
+                    scaled(
+                        lap.speed_max.map(|x| x.get::<meter_per_second>()),
+                        1000.,
+                        0.,
+                    ),
+                ),
+            ],
+        );
+    }
+
+    encoder.message(
+        mesg_num::SESSION,
+        &[
+            (253, Field::UInt32(timestamp(end_time))),
+            // Session stop event
+            (0, Field::Enum(Some(8))),
+            (1, Field::Enum(Some(1))),
+            (2, Field::UInt32(start_time)),
+            (5, Field::Enum(sport(session.sport))),
+            (7, scaled(Some(session.duration.as_secs_f64()), 1000., 0.)),
+            (
+                8,
+                scaled(Some(session.duration_active.as_secs_f64()), 1000., 0.),
+            ),
+            (
+                9,
+                scaled(session.distance.map(|x| x.get::<meter>()), 100., 0.),
+            ),
+            (
+                11,
+                Field::UInt16(
+                    session
+                        .calories
+                        .and_then(|x| u16::try_from(x.get::<kilocalorie>()).ok()),
+                ),
+            ),
+            (16, Field::UInt8(session.heartrate_avg)),
+            (17, Field::UInt8(session.heartrate_max)),
+            (
+                18,
+                Field::UInt8(
+                    session
+                        .cadence_avg
+                        .map(|x| x.get::<revolution_per_minute>().round() as u8),
+                ),
+            ),
+            (
+                19,
+                Field::UInt8(
+                    session
+                        .cadence_max
+                        .map(|x| x.get::<revolution_per_minute>().round() as u8),
+                ),
+            ),
+            (
+                20,
+                Field::UInt16(session.power_avg.map(|x| x.get::<watt>())),
+            ),
+            (
+                21,
+                Field::UInt16(session.power_max.map(|x| x.get::<watt>())),
+            ),
+            (
+                22,
+                Field::UInt16(
+                    session
+                        .ascent
+                        .and_then(|x| u16::try_from(x.get::<meter>()).ok()),
+                ),
+            ),
+            (
+                23,
+                Field::UInt16(
+                    session
+                        .descent
+                        .and_then(|x| u16::try_from(x.get::<meter>()).ok()),
+                ),
+            ),
+            (25, Field::UInt16(Some(0))),
+            (26, Field::UInt16(session.laps)),
+            (29, semicircles(session.nec_lat)),
+            (30, semicircles(session.nec_lon)),
+            (31, semicircles(session.swc_lat)),
+            (32, semicircles(session.swc_lon)),
+            (
+                124,
+                scaled(
+                    session.speed_avg.map(|x| x.get::<meter_per_second>()),
+                    1000.,
+                    0.,
+                ),
+            ),
+            (
+                125,
+                scaled(
+                    session.speed_max.map(|x| x.get::<meter_per_second>()),
+                    1000.,
+                    0.,
+                ),
+            ),
+        ],
+    );
+
+    encoder.message(
+        mesg_num::ACTIVITY,
+        &[
+            (253, Field::UInt32(timestamp(end_time))),
+            (
+                0,
+                scaled(Some(session.duration_active.as_secs_f64()), 1000., 0.),
+            ),
+            (1, Field::UInt16(Some(1))),
+            // Manual activity, activity stop event
+            (2, Field::Enum(Some(0))),
+            (3, Field::Enum(Some(26))),
+            (4, Field::Enum(Some(1))),
+            (
+                5,
+                Field::UInt32(
+                    timestamp(end_time)
+                        .zip(session.utc_offset)
+                        .and_then(|(x, offset)| {
+                            u32::try_from(i64::from(x) + i64::from(offset)).ok()
+                        }),
+                ),
+            ),
+        ],
+    );
+
+    encoder.finish()
+}
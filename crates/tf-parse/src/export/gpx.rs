@@ -0,0 +1,88 @@
+use std::fmt::Write;
+
+use tf_models::Activity;
+use uom::si::{angular_velocity::revolution_per_minute, length::meter, power::watt};
+
+pub fn export(activity: &Activity) -> String {
+    let record = &activity.record;
+    let mut output = String::new();
+
+    output.push_str(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        "\n",
+        r#"<gpx version="1.1" creator="tf-viewer" xmlns="http://www.topografix.com/GPX/1/1" "#,
+        r#"xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v1">"#,
+        "\n"
+    ));
+
+    let _ = writeln!(
+        output,
+        "<metadata><time>{}</time></metadata>",
+        activity.session.start_time.to_rfc3339()
+    );
+    let _ = writeln!(
+        output,
+        "<trk><type>{}</type>",
+        activity.session.sport.as_str()
+    );
+
+    let mut boundaries = super::lap_boundaries(activity);
+    if boundaries.first() != Some(&0) {
+        boundaries.insert(0, 0);
+    }
+    boundaries.push(record.lat.len());
+
+    for segment in boundaries.windows(2) {
+        output.push_str("<trkseg>\n");
+
+        for i in segment[0]..segment[1] {
+            let position = record.lat.get(i).copied().flatten();
+            let (lat, lon) = match position.zip(record.lon.get(i).copied().flatten()) {
+                Some(x) => x,
+                None => continue,
+            };
+
+            let _ = write!(output, r#"<trkpt lat="{}" lon="{}">"#, lat, lon);
+
+            if let Some(altitude) = record.altitude.get(i).copied().flatten() {
+                let _ = write!(output, "<ele>{}</ele>", altitude.get::<meter>());
+            }
+            if let Some(timestamp) = record.timestamp.get(i).copied().flatten() {
+                let _ = write!(output, "<time>{}</time>", timestamp.to_rfc3339());
+            }
+
+            let heartrate = record.heartrate.get(i).copied().flatten();
+            let cadence = record.cadence.get(i).copied().flatten();
+            let power = record.power.get(i).copied().flatten();
+
+            if heartrate.is_some() || cadence.is_some() || power.is_some() {
+                output.push_str("<extensions>");
+                if let Some(power) = power {
+                    let _ = write!(output, "<power>{}</power>", power.get::<watt>());
+                }
+                if heartrate.is_some() || cadence.is_some() {
+                    output.push_str("<gpxtpx:TrackPointExtension>");
+                    if let Some(heartrate) = heartrate {
+                        let _ = write!(output, "<gpxtpx:hr>{}</gpxtpx:hr>", heartrate);
+                    }
+                    if let Some(cadence) = cadence {
+                        let _ = write!(
+                            output,
+                            "<gpxtpx:cad>{}</gpxtpx:cad>",
+                            cadence.get::<revolution_per_minute>().round()
+                        );
+                    }
+                    output.push_str("</gpxtpx:TrackPointExtension>");
+                }
+                output.push_str("</extensions>");
+            }
+
+            output.push_str("</trkpt>\n");
+        }
+
+        output.push_str("</trkseg>\n");
+    }
+
+    output.push_str("</trk>\n</gpx>\n");
+    output
+}
@@ -0,0 +1,93 @@
+use chrono::{offset::Utc, DateTime};
+use tf_models::Activity;
+
+mod csv;
+mod fit;
+mod gpx;
+mod tcx;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Fit,
+    Gpx,
+    Tcx,
+    Csv,
+}
+
+impl Format {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Fit => "application/vnd.ant.fit",
+            Self::Gpx => "application/gpx+xml",
+            Self::Tcx => "application/vnd.garmin.tcx+xml",
+            Self::Csv => "text/csv",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Fit => "fit",
+            Self::Gpx => "gpx",
+            Self::Tcx => "tcx",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "fit" => Self::Fit,
+            "gpx" => Self::Gpx,
+            "tcx" => Self::Tcx,
+            "csv" => Self::Csv,
+            _ => return Err(()),
+        })
+    }
+}
+
+pub fn export(activity: &Activity, format: Format) -> Vec<u8> {
+    match format {
+        Format::Fit => fit::export(activity),
+        Format::Gpx => gpx::export(activity).into_bytes(),
+        Format::Tcx => tcx::export(activity).into_bytes(),
+        Format::Csv => csv::export(activity).into_bytes(),
+    }
+}
+
+/// Index of the first record of each lap, found by laying the lap durations
+/// out after each other from the start of the activity.
+fn lap_boundaries(activity: &Activity) -> Vec<usize> {
+    let mut elapsed = 0_f64;
+
+    activity
+        .lap
+        .iter()
+        .map(|lap| {
+            let start = activity
+                .record
+                .duration
+                .partition_point(|x| x.as_secs_f64() < elapsed);
+            elapsed += lap.duration.as_secs_f64();
+            start
+        })
+        .collect()
+}
+
+/// Start time of each lap, assuming the laps follow each other without gaps.
+fn lap_start_times(activity: &Activity) -> Vec<DateTime<Utc>> {
+    let mut start_time = activity.session.start_time;
+
+    activity
+        .lap
+        .iter()
+        .map(|lap| {
+            let current = start_time;
+            start_time = start_time
+                + chrono::Duration::milliseconds((lap.duration.as_secs_f64() * 1000.) as i64);
+            current
+        })
+        .collect()
+}
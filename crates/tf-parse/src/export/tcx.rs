@@ -0,0 +1,229 @@
+use std::fmt::Write;
+
+use tf_models::{
+    activity::{Lap, Session},
+    Activity, Sport,
+};
+use uom::si::{
+    angular_velocity::revolution_per_minute, energy::kilocalorie, length::meter, power::watt,
+    velocity::meter_per_second,
+};
+
+fn sport_name(sport: Sport) -> &'static str {
+    match sport {
+        Sport::Running => "Running",
+        Sport::Cycling => "Biking",
+        _ => "Other",
+    }
+}
+
+fn session_lap(session: &Session) -> Lap {
+    Lap {
+        cadence_avg: session.cadence_avg,
+        cadence_max: session.cadence_max,
+        heartrate_avg: session.heartrate_avg,
+        heartrate_max: session.heartrate_max,
+        speed_avg: session.speed_avg,
+        speed_max: session.speed_max,
+        power_avg: session.power_avg,
+        power_max: session.power_max,
+        ascent: session.ascent,
+        descent: session.descent,
+        calories: session.calories,
+        distance: session.distance,
+        duration: session.duration,
+        duration_active: session.duration_active,
+        ..Default::default()
+    }
+}
+
+pub fn export(activity: &Activity) -> String {
+    let record = &activity.record;
+    let mut output = String::new();
+
+    output.push_str(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        "\n",
+        r#"<TrainingCenterDatabase xmlns="http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2" "#,
+        r#"xmlns:ns3="http://www.garmin.com/xmlschemas/ActivityExtension/v2">"#,
+        "\n<Activities>\n"
+    ));
+
+    let _ = writeln!(
+        output,
+        r#"<Activity Sport="{}"><Id>{}</Id>"#,
+        sport_name(activity.session.sport),
+        activity.session.start_time.to_rfc3339()
+    );
+
+    let (laps, mut boundaries, start_times) = if activity.lap.is_empty() {
+        (
+            vec![session_lap(&activity.session)],
+            vec![0],
+            vec![activity.session.start_time],
+        )
+    } else {
+        (
+            activity.lap.clone(),
+            super::lap_boundaries(activity),
+            super::lap_start_times(activity),
+        )
+    };
+    boundaries.push(record.duration.len());
+
+    for ((lap, range), start_time) in laps.iter().zip(boundaries.windows(2)).zip(start_times) {
+        let _ = write!(
+            output,
+            r#"<Lap StartTime="{}"><TotalTimeSeconds>{}</TotalTimeSeconds><DistanceMeters>{}</DistanceMeters>"#,
+            start_time.to_rfc3339(),
+            lap.duration_active.as_secs_f64(),
+            lap.distance.map(|x| x.get::<meter>()).unwrap_or_default(),
+        );
+        if let Some(speed) = lap.speed_max {
+            let _ = write!(
+                output,
+                "<MaximumSpeed>{}</MaximumSpeed>",
+                speed.get::<meter_per_second>()
+            );
+        }
+        let _ = write!(
+            output,
+            "<Calories>{}</Calories>",
+            lap.calories
+                .map(|x| x.get::<kilocalorie>())
+                .unwrap_or_default()
+        );
+        if let Some(heartrate) = lap.heartrate_avg {
+            let _ = write!(
+                output,
+                "<AverageHeartRateBpm><Value>{}</Value></AverageHeartRateBpm>",
+                heartrate
+            );
+        }
+        if let Some(heartrate) = lap.heartrate_max {
+            let _ = write!(
+                output,
+                "<MaximumHeartRateBpm><Value>{}</Value></MaximumHeartRateBpm>",
+                heartrate
+            );
+        }
+        output.push_str("<Intensity>Active</Intensity>");
+        if let Some(cadence) = lap.cadence_avg {
+            let _ = write!(
+                output,
+                "<Cadence>{}</Cadence>",
+                cadence.get::<revolution_per_minute>().round()
+            );
+        }
+        output.push_str("<TriggerMethod>Manual</TriggerMethod>\n<Track>\n");
+
+        for i in range[0]..range[1] {
+            write_trackpoint(&mut output, activity, i);
+        }
+
+        output.push_str("</Track>\n<Extensions><ns3:LX>");
+        if let Some(speed) = lap.speed_avg {
+            let _ = write!(
+                output,
+                "<ns3:AvgSpeed>{}</ns3:AvgSpeed>",
+                speed.get::<meter_per_second>()
+            );
+        }
+        if let Some(power) = lap.power_avg {
+            let _ = write!(
+                output,
+                "<ns3:AvgWatts>{}</ns3:AvgWatts>",
+                power.get::<watt>()
+            );
+        }
+        if let Some(power) = lap.power_max {
+            let _ = write!(
+                output,
+                "<ns3:MaxWatts>{}</ns3:MaxWatts>",
+                power.get::<watt>()
+            );
+        }
+        output.push_str("</ns3:LX></Extensions></Lap>\n");
+    }
+
+    output.push_str("</Activity>\n</Activities>\n</TrainingCenterDatabase>\n");
+    output
+}
+
+fn write_trackpoint(output: &mut String, activity: &Activity, i: usize) {
+    let record = &activity.record;
+
+    // Time is mandatory in a TCX trackpoint
+    let timestamp = match record.timestamp.get(i).copied().flatten() {
+        Some(x) => x,
+        None => return,
+    };
+
+    let _ = write!(
+        output,
+        "<Trackpoint><Time>{}</Time>",
+        timestamp.to_rfc3339()
+    );
+
+    if let Some((lat, lon)) = record
+        .lat
+        .get(i)
+        .copied()
+        .flatten()
+        .zip(record.lon.get(i).copied().flatten())
+    {
+        let _ = write!(
+            output,
+            "<Position><LatitudeDegrees>{}</LatitudeDegrees><LongitudeDegrees>{}</LongitudeDegrees></Position>",
+            lat, lon
+        );
+    }
+    if let Some(altitude) = record.altitude.get(i).copied().flatten() {
+        let _ = write!(
+            output,
+            "<AltitudeMeters>{}</AltitudeMeters>",
+            altitude.get::<meter>()
+        );
+    }
+    if let Some(distance) = record.distance.get(i).copied().flatten() {
+        let _ = write!(
+            output,
+            "<DistanceMeters>{}</DistanceMeters>",
+            distance.get::<meter>()
+        );
+    }
+    if let Some(heartrate) = record.heartrate.get(i).copied().flatten() {
+        let _ = write!(
+            output,
+            "<HeartRateBpm><Value>{}</Value></HeartRateBpm>",
+            heartrate
+        );
+    }
+    if let Some(cadence) = record.cadence.get(i).copied().flatten() {
+        let _ = write!(
+            output,
+            "<Cadence>{}</Cadence>",
+            cadence.get::<revolution_per_minute>().round()
+        );
+    }
+
+    let speed = record.speed.get(i).copied().flatten();
+    let power = record.power.get(i).copied().flatten();
+
+    if speed.is_some() || power.is_some() {
+        output.push_str("<Extensions><ns3:TPX>");
+        if let Some(speed) = speed {
+            let _ = write!(
+                output,
+                "<ns3:Speed>{}</ns3:Speed>",
+                speed.get::<meter_per_second>()
+            );
+        }
+        if let Some(power) = power {
+            let _ = write!(output, "<ns3:Watts>{}</ns3:Watts>", power.get::<watt>());
+        }
+        output.push_str("</ns3:TPX></Extensions>");
+    }
+
+    output.push_str("</Trackpoint>\n");
+}
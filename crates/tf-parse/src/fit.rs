@@ -1,22 +1,38 @@
-use fitparser::{de::DecodeOption, profile::field_types::MesgNum, FitDataField, Value};
+use fitparser::{
+    de::{DecodeOption, FitObject, FitStreamProcessor},
+    profile::field_types::MesgNum,
+    FitDataField, FitDataRecord, Value,
+};
 use std::time::Duration;
 use std::{
-    collections::{HashMap, HashSet},
-    iter::FromIterator,
+    collections::HashMap,
+    io::{self, Read},
+    ops::Range,
     str::FromStr,
 };
 
-use crate::error::{Error, Result};
+use crate::{
+    crc,
+    error::{Error, Result},
+    pause::{self, TimerEvent},
+    summary::{self, Summary},
+    swim, Repair, Report, Warning,
+};
 
-use chrono::{offset::Local, DateTime};
+use chrono::{offset::Utc, DateTime};
 use tf_models::{
-    activity::{Lap, Record, Session},
+    activity::{
+        DeveloperField, Device, FileId, Lap, Pause, Record, RecordRange, Session, SwimLength,
+    },
     types::{AngularVelocity, Energy, LengthF64, LengthU32, Power, Velocity},
-    Activity, ActivityId, Sport,
+    Activity, Sport,
 };
 
 use uom::si::{
-    angular_velocity::revolution_per_minute, energy::kilocalorie, length::meter, power::watt,
+    angular_velocity::revolution_per_minute,
+    energy::kilocalorie,
+    length::{meter, millimeter},
+    power::watt,
     velocity::meter_per_second,
 };
 
@@ -32,13 +48,15 @@ macro_rules! map_value {
 }
 
 map_value!(map_uint8, u8, Value::UInt8(x) => *x);
+map_value!(map_sint8, i8, Value::SInt8(x) => *x);
 map_value!(map_uint16, u16, Value::UInt16(x) => *x);
+map_value!(map_uint32, u32, Value::UInt32(x) | Value::UInt32z(x) => *x);
 map_value!(map_sint32, i32, Value::SInt32(x) => *x);
 map_value!(map_float64, f64, Value::Float64(x) => *x);
 map_value!(map_string, String, Value::String(x) => x.to_string());
-map_value!(map_timestamp, DateTime<Local>, Value::Timestamp(x) => *x);
+map_value!(map_timestamp, DateTime<Utc>, Value::Timestamp(x) => x.with_timezone(&Utc));
 
-fn between(lhs: &Option<DateTime<Local>>, rhs: Option<DateTime<Local>>) -> Option<Duration> {
+fn between(lhs: &Option<DateTime<Utc>>, rhs: Option<DateTime<Utc>>) -> Option<Duration> {
     if let Some((x, y)) = lhs.zip(rhs) {
         chrono::Duration::to_std(&x.signed_duration_since(y)).ok()
     } else {
@@ -46,24 +64,311 @@ fn between(lhs: &Option<DateTime<Local>>, rhs: Option<DateTime<Local>>) -> Optio
     }
 }
 
+const FIT_EPOCH: i64 = 631_065_600;
+const MAX_UTC_OFFSET: i32 = 14 * 3600;
 const MULTIPLIER: f64 = 180_f64 / (2_u32 << 30) as f64;
 
-pub fn parse(fit_data: &[u8]) -> Result<Activity> {
-    let mut session: Session = Session::default();
-    let mut record: Record = Record::default();
-    let mut lap_vec: Vec<Lap> = Vec::new();
+// Bytes read from the file at a time
+const READ_SIZE: usize = 64 * 1024;
+// Largest possible message, with 255 fields and 255 developer fields
+const MAX_MESSAGE_SIZE: usize = 1 + 2 * 255 * 255;
+// Records the columns grow by at the least
+const RECORD_CHUNK: usize = 4096;
+
+/// Percentage from the right side, from either a `left_right_balance` or a
+/// `left_right_balance_100` value. Values without the right flag do not say
+/// which side they belong to, and are skipped.
+fn map_balance(v: &&fitparser::Value) -> Option<f64> {
+    match v {
+        Value::UInt8(x) => (x & 0x80 != 0).then(|| f64::from(x & 0x7F)),
+        Value::UInt16(x) => (x & 0x8000 != 0).then(|| f64::from(x & 0x3FFF) / 100.),
+        _ => None,
+    }
+}
 
-    let options = HashSet::from_iter([DecodeOption::SkipHeaderCrcValidation]);
-    let file = fitparser::de::from_bytes_with_options(fit_data, &options)?;
+/// Parses a FIT file. In lenient mode, as much as possible is recovered from
+/// a file that is cut short, for example by a dead battery, or fails its
+/// checksum.
+pub fn parse<R: Read>(fit_data: R, lenient: bool, report: &mut Report) -> Result<Activity> {
+    activity(&mut Messages::new(fit_data, lenient), report)
+}
 
-    if !file.iter().any(|x| x.kind() == MesgNum::Session) {
-        return Err(Error::MissingData);
+fn processor() -> FitStreamProcessor {
+    let mut processor = FitStreamProcessor::new();
+    processor.add_option(DecodeOption::SkipHeaderCrcValidation);
+    processor.add_option(DecodeOption::SkipDataCrcValidation);
+    processor
+}
+
+/// Decodes the messages of a file one at a time as it is read, so that no
+/// more than a read buffer and the current message are held in memory.
+/// Checksums are verified here rather than by `fitparser`, letting a lenient
+/// parse carry on past a mismatch. What was repaired or dropped on the way is
+/// collected for the report.
+struct Messages<R> {
+    reader: R,
+    processor: FitStreamProcessor,
+    buffer: Vec<u8>,
+    // Start of the unread part of the buffer
+    position: usize,
+    // Bytes of the file consumed so far
+    offset: usize,
+    // Data messages seen so far
+    index: usize,
+    crc: u16,
+    // Whether the last file in the stream ended with its checksum
+    complete: bool,
+    eof: bool,
+    done: bool,
+    lenient: bool,
+    repairs: Vec<Repair>,
+    warnings: Vec<Warning>,
+}
+
+impl<R: Read> Messages<R> {
+    fn new(reader: R, lenient: bool) -> Self {
+        Self {
+            reader,
+            processor: processor(),
+            buffer: Vec::with_capacity(MAX_MESSAGE_SIZE + READ_SIZE),
+            position: 0,
+            offset: 0,
+            index: 0,
+            crc: 0,
+            complete: false,
+            eof: false,
+            done: false,
+            lenient,
+            repairs: Vec::new(),
+            warnings: Vec::new(),
+        }
     }
 
-    for data in file {
+    /// Reads the next part of the file, dropping what has been decoded.
+    fn fill(&mut self) -> Result<()> {
+        self.buffer.drain(..self.position);
+        self.position = 0;
+
+        let len = self.buffer.len();
+        self.buffer.resize(len + READ_SIZE, 0);
+
+        let read = loop {
+            match self.reader.read(&mut self.buffer[len..]) {
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => (),
+                result => break result,
+            }
+        };
+
+        self.buffer.truncate(len + *read.as_ref().unwrap_or(&0));
+        self.eof = read? == 0;
+
+        Ok(())
+    }
+
+    /// Stops decoding. In lenient mode the file is cut off where the error
+    /// was found, and the messages before it are kept.
+    fn fail(&mut self, error: Error) -> Option<Result<(usize, FitDataRecord)>> {
+        self.done = true;
+
+        if !self.lenient {
+            return Some(Err(error));
+        }
+
+        self.repairs.push(match error {
+            Error::Decode { offset, reason, .. } => Repair::Truncated { offset, reason },
+            error => Repair::Truncated {
+                offset: self.offset,
+                reason: error.to_string(),
+            },
+        });
+
+        None
+    }
+}
+
+impl<R: Read> Iterator for Messages<R> {
+    type Item = Result<(usize, FitDataRecord)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            // Keeping a whole message buffered means that a message failing
+            // to decode is broken, and not just cut off by the buffer
+            while self.buffer.len() - self.position < MAX_MESSAGE_SIZE && !self.eof {
+                if let Err(error) = self.fill() {
+                    return self.fail(error);
+                }
+            }
+
+            let offset = self.offset;
+            let input = &self.buffer[self.position..];
+
+            if input.is_empty() {
+                if self.complete {
+                    self.done = true;
+                    return None;
+                }
+
+                return self.fail(Error::Decode {
+                    index: self.index,
+                    offset,
+                    reason: "unexpected end of file".to_string(),
+                });
+            }
+
+            let decoded = self
+                .processor
+                .deserialize_next(input)
+                .map(|(rest, object)| (input.len() - rest.len(), object));
+
+            let (consumed, object) = match decoded {
+                Ok((0, _)) => {
+                    return self.fail(Error::Decode {
+                        index: self.index,
+                        offset,
+                        reason: "no progress".to_string(),
+                    })
+                }
+                Ok(decoded) => decoded,
+                Err(error) => {
+                    return self.fail(Error::Decode {
+                        index: self.index,
+                        offset,
+                        reason: error.to_string(),
+                    })
+                }
+            };
+
+            let bytes = &self.buffer[self.position..self.position + consumed];
+            self.position += consumed;
+            self.offset += consumed;
+
+            match object {
+                FitObject::Header(_) => {
+                    self.crc = crc::update(0, bytes);
+                    self.complete = false;
+                }
+                FitObject::Crc(expected) => {
+                    // Chained files start over with a header of their own
+                    self.processor = processor();
+                    self.complete = true;
+
+                    if self.crc != expected {
+                        if !self.lenient {
+                            self.done = true;
+                            return Some(Err(Error::InvalidChecksum));
+                        }
+                        self.repairs.push(Repair::InvalidChecksum);
+                    }
+                }
+                FitObject::DataMessage(message) => {
+                    self.crc = crc::update(self.crc, bytes);
+
+                    let index = self.index;
+                    self.index += 1;
+
+                    match self.processor.decode_message(message) {
+                        Ok(message) => return Some(Ok((index, message))),
+                        Err(error) if self.lenient => self.warnings.push(Warning::DroppedRecord {
+                            index,
+                            reason: error.to_string(),
+                        }),
+                        Err(error) => {
+                            self.done = true;
+                            return Some(Err(Error::Decode {
+                                index,
+                                offset,
+                                reason: error.to_string(),
+                            }));
+                        }
+                    }
+                }
+                _ => self.crc = crc::update(self.crc, bytes),
+            }
+        }
+
+        None
+    }
+}
+
+/// Grows the record columns ahead of the next record. Left to themselves the
+/// columns double when full, which for a long activity sets aside nearly as
+/// much memory again as the records take, so they grow by a quarter instead.
+fn reserve_records(record: &mut Record) {
+    let len = record.timestamp.len();
+
+    if len < record.timestamp.capacity() {
+        return;
+    }
+
+    let additional = (len / 4).max(RECORD_CHUNK);
+
+    record.cadence.reserve_exact(additional);
+    record.distance.reserve_exact(additional);
+    record.altitude.reserve_exact(additional);
+    record.speed.reserve_exact(additional);
+    record.heartrate.reserve_exact(additional);
+    record.power.reserve_exact(additional);
+    record.lat.reserve_exact(additional);
+    record.lon.reserve_exact(additional);
+    record.timestamp.reserve_exact(additional);
+    record.duration.reserve_exact(additional);
+    record.temperature.reserve_exact(additional);
+    record.left_right_balance.reserve_exact(additional);
+    record.vertical_oscillation.reserve_exact(additional);
+    record.ground_contact_time.reserve_exact(additional);
+    record.stance_time_balance.reserve_exact(additional);
+    record.step_length.reserve_exact(additional);
+    record.respiration_rate.reserve_exact(additional);
+
+    for developer_field in record.developer_fields.iter_mut() {
+        developer_field.values.reserve_exact(additional);
+    }
+}
+
+fn activity<R: Read>(messages: &mut Messages<R>, report: &mut Report) -> Result<Activity> {
+    let mut sessions: Vec<(Session, Range<usize>)> = Vec::new();
+    let mut record: Record = Record::default();
+    let mut lap_vec: Vec<Lap> = Vec::new();
+    let mut file_id = None;
+    let mut lengths: Vec<SwimLength> = Vec::new();
+    let mut pool_length = None;
+    let mut sport = None;
+    let mut utc_offset = None;
+    let mut devices: Vec<(String, Device)> = Vec::new();
+    let mut timer_events: Vec<TimerEvent> = Vec::new();
+
+    for message in messages.by_ref() {
+        let (index, data) = message?;
+
         match data.kind() {
-            MesgNum::Session => parse_session(data.fields(), &mut session)?,
-            MesgNum::Record => parse_record(data.fields(), &mut record),
+            MesgNum::FileId if file_id.is_none() => file_id = Some(parse_file_id(data.fields())),
+            MesgNum::Sport => sport = parse_sport(data.fields(), report),
+            MesgNum::Activity => utc_offset = parse_utc_offset(data.fields()),
+            MesgNum::DeviceInfo => parse_device_info(data.fields(), &mut devices),
+            MesgNum::Event => timer_events.extend(parse_timer_event(data.fields())),
+            MesgNum::Session => {
+                let mut session = Session::default();
+                parse_session(data.fields(), index, &mut session)?;
+                session.sport = parse_sport(data.fields(), report).unwrap_or(Sport::Unknown);
+
+                let first_lap = sessions.last().map_or(0, |(_, laps)| laps.end);
+                let laps = session_laps(data.fields(), first_lap, lap_vec.len());
+
+                pool_length = pool_length.or_else(|| {
+                    data.fields()
+                        .iter()
+                        .find(|x| x.name() == "pool_length")
+                        .and_then(|x| map_float64(&x.value()))
+                });
+
+                sessions.push((session, laps));
+            }
+            MesgNum::Record => {
+                reserve_records(&mut record);
+                parse_record(data.fields(), &mut record);
+            }
+            MesgNum::Length => lengths.extend(parse_length(data.fields())),
+            MesgNum::FieldDescription => parse_field_description(data.fields(), &mut record),
             MesgNum::Lap => {
                 let mut lap = Lap::default();
                 parse_lap(data.fields(), &mut lap);
@@ -73,8 +378,125 @@ pub fn parse(fit_data: &[u8]) -> Result<Activity> {
         }
     }
 
-    // Some fit-files do not contain corner coordinates,
-    // so find them manually if missing
+    report.repairs.append(&mut messages.repairs);
+    report.warnings.append(&mut messages.warnings);
+
+    // Descriptions are also written for fields of other messages
+    record
+        .developer_fields
+        .retain(|x| x.values.iter().any(Option::is_some));
+
+    if sessions.is_empty() {
+        if !messages.lenient {
+            return Err(Error::MissingData);
+        }
+
+        let session = synthesize_session(
+            &record,
+            &mut lap_vec,
+            sport.unwrap_or(Sport::Generic),
+            report,
+        )?;
+        sessions.push((session, 0..lap_vec.len()));
+    }
+
+    for (session, _) in sessions.iter_mut() {
+        session.utc_offset = utc_offset;
+    }
+
+    let pauses = if timer_events.is_empty() {
+        pause::detect(&record)
+    } else {
+        pause::from_events(&timer_events)
+    };
+
+    let (mut session, children, swim) = match sessions.as_slice() {
+        [(session, _)] => (*session, Vec::new(), swim::swim(pool_length, lengths)),
+        _ => (
+            multisport(&sessions),
+            children(&sessions, &record, &lap_vec, &lengths, &pauses, pool_length)?,
+            None,
+        ),
+    };
+
+    fill_corners(&mut session, &record);
+    session.utc_offset = utc_offset;
+
+    let mut devices = devices
+        .into_iter()
+        .map(|(_, device)| device)
+        .collect::<Vec<_>>();
+
+    // Files without device info still name the recording device
+    if let Some(file_id) = file_id
+        .as_ref()
+        .filter(|_| !devices.iter().any(|x| x.creator))
+    {
+        devices.insert(
+            0,
+            Device {
+                creator: true,
+                manufacturer: file_id.manufacturer.clone(),
+                product: file_id.product.clone(),
+                serial_number: file_id.serial_number,
+                ..Default::default()
+            },
+        );
+    }
+
+    Ok(Activity {
+        id: crate::activity_id(&session)?,
+        session,
+        record,
+        lap: lap_vec,
+        file_id,
+        children,
+        record_range: None,
+        swim,
+        devices,
+        pauses,
+    })
+}
+
+/// Computes the session of a file that ended before its session message was
+/// written. Records after the last complete lap get a lap of their own.
+fn synthesize_session(
+    record: &Record,
+    laps: &mut Vec<Lap>,
+    sport: Sport,
+    report: &mut Report,
+) -> Result<Session> {
+    let points = summary::points(record);
+    let start = points
+        .iter()
+        .find_map(|x| x.timestamp)
+        .ok_or(Error::MissingData)?;
+
+    let lapped = laps.iter().map(|x| x.duration.as_secs_f64()).sum::<f64>();
+    let end_of_laps = start + chrono::Duration::milliseconds((lapped * 1000.).round() as i64);
+
+    let first = if laps.is_empty() {
+        0
+    } else {
+        points
+            .iter()
+            .position(|x| x.timestamp.filter(|x| *x > end_of_laps).is_some())
+            .unwrap_or(points.len())
+    };
+
+    if first < points.len() {
+        laps.push(Summary::new(&points[first..]).lap());
+        report.repairs.push(Repair::SynthesizedLap);
+    }
+
+    report.repairs.push(Repair::SynthesizedSession);
+
+    Ok(Summary::new(&points).session(sport, laps.len()))
+}
+
+// Some fit-files do not contain corner coordinates,
+// so find them manually if missing
+fn fill_corners(session: &mut Session, record: &Record) {
     if session.nec_lat.is_none()
         || session.nec_lon.is_none()
         || session.swc_lat.is_none()
@@ -113,23 +535,385 @@ pub fn parse(fit_data: &[u8]) -> Result<Activity> {
                 .fold(f64::NAN, f64::min),
         );
     }
+}
 
-    Ok(Activity {
-        id: ActivityId::from_bytes(
-            session
-                .start_time
-                .format("%Y%m%d%H%M")
-                .to_string()
-                .as_bytes(),
-        )
-        .unwrap(),
-        session,
-        record,
-        lap: lap_vec,
+/// The laps belonging to a session. Uses the lap index in the session
+/// message if present, and otherwise the laps written since the previous
+/// session.
+fn session_laps(fields: &[FitDataField], first_lap: usize, laps: usize) -> Range<usize> {
+    let field_map: HashMap<&str, &fitparser::Value> =
+        fields.iter().map(|x| (x.name(), x.value())).collect();
+
+    let first = field_map.get("first_lap_index").and_then(map_uint16);
+    let count = field_map.get("num_laps").and_then(map_uint16);
+
+    match first.zip(count) {
+        Some((first, count)) => {
+            let start = usize::from(first).min(laps);
+            start..(start + usize::from(count)).min(laps)
+        }
+        None => first_lap.min(laps)..laps,
+    }
+}
+
+/// Splits a multisport file into one activity per session. Records and pool
+/// lengths are assigned by timestamp, each session getting those from its
+/// start time until the start of the next session.
+fn children(
+    sessions: &[(Session, Range<usize>)],
+    record: &Record,
+    laps: &[Lap],
+    lengths: &[SwimLength],
+    pauses: &[Pause],
+    pool_length: Option<f64>,
+) -> Result<Vec<Activity>> {
+    let starts = std::iter::once(0)
+        .chain(sessions.iter().skip(1).scan(0, |last, (session, _)| {
+            let start = record
+                .timestamp
+                .partition_point(|x| x.filter(|x| *x >= session.start_time).is_none());
+
+            *last = start.max(*last);
+            Some(*last)
+        }))
+        .collect::<Vec<_>>();
+
+    let ends = starts
+        .iter()
+        .skip(1)
+        .copied()
+        .chain(std::iter::once(record.timestamp.len()));
+
+    let next_start_times = sessions
+        .iter()
+        .skip(1)
+        .map(|(session, _)| Some(session.start_time))
+        .chain(std::iter::once(None));
+
+    sessions
+        .iter()
+        .zip(starts.iter().copied().zip(ends))
+        .zip(next_start_times)
+        .map(|(((session, lap), (start, end)), next_start_time)| {
+            let mut session = *session;
+            // Timer times are filled in once the pauses of the child are known
+            let record = record.slice(start..end);
+
+            let within = |start_time: &DateTime<Utc>| {
+                *start_time >= session.start_time
+                    && next_start_time.filter(|next| start_time >= next).is_none()
+            };
+
+            let lengths = lengths
+                .iter()
+                .filter(|x| within(&x.start_time))
+                .copied()
+                .collect();
+
+            fill_corners(&mut session, &record);
+
+            Ok(Activity {
+                id: crate::activity_id(&session)?,
+                session,
+                record,
+                lap: laps[lap.clone()].to_vec(),
+                file_id: None,
+                children: Vec::new(),
+                record_range: Some(RecordRange { start, end }),
+                swim: swim::swim(pool_length, lengths),
+                devices: Vec::new(),
+                pauses: pauses
+                    .iter()
+                    .filter(|x| within(&x.start_time))
+                    .copied()
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// Combines the sessions of a multisport file into a session for the whole
+/// activity. Totals are summed, while averages other than heart rate are
+/// left out as they make little sense across sports.
+fn multisport(sessions: &[(Session, Range<usize>)]) -> Session {
+    let sessions = || sessions.iter().map(|(session, _)| session);
+
+    let duration = sessions().map(|x| x.duration.as_secs_f64()).sum::<f64>();
+    let duration_active = sessions()
+        .map(|x| x.duration_active.as_secs_f64())
+        .sum::<f64>();
+
+    let heartrate_avg = sessions()
+        .filter_map(|x| {
+            x.heartrate_avg
+                .map(|hr| (f64::from(hr), x.duration_active.as_secs_f64()))
+        })
+        .fold((0., 0.), |(sum, total), (hr, duration)| {
+            (sum + hr * duration, total + duration)
+        });
+
+    Session {
+        heartrate_avg: (heartrate_avg.1 > 0.)
+            .then(|| (heartrate_avg.0 / heartrate_avg.1).round() as u8),
+        heartrate_max: sessions().filter_map(|x| x.heartrate_max).max(),
+        speed_max: sessions()
+            .filter_map(|x| x.speed_max)
+            .map(|x| x.get::<meter_per_second>())
+            .reduce(f64::max)
+            .map(Velocity::new::<meter_per_second>)
+            .map(Into::into),
+        power_max: sessions()
+            .filter_map(|x| x.power_max)
+            .map(|x| x.get::<watt>())
+            .max()
+            .map(Power::new::<watt>)
+            .map(Into::into),
+        laps: sessions().try_fold(0_u16, |total, x| Some(total.saturating_add(x.laps?))),
+        sport: Sport::Multisport,
+        ascent: sessions()
+            .map(|x| x.ascent.map(|x| x.get::<meter>()))
+            .sum::<Option<u32>>()
+            .map(LengthU32::new::<meter>)
+            .map(Into::into),
+        descent: sessions()
+            .map(|x| x.descent.map(|x| x.get::<meter>()))
+            .sum::<Option<u32>>()
+            .map(LengthU32::new::<meter>)
+            .map(Into::into),
+        calories: sessions()
+            .map(|x| x.calories.map(|x| x.get::<kilocalorie>()))
+            .sum::<Option<u32>>()
+            .map(Energy::new::<kilocalorie>)
+            .map(Into::into),
+        distance: sessions()
+            .map(|x| x.distance.map(|x| x.get::<meter>()))
+            .sum::<Option<f64>>()
+            .map(LengthF64::new::<meter>)
+            .map(Into::into),
+        duration: Duration::from_secs_f64(duration).into(),
+        duration_active: Duration::from_secs_f64(duration_active).into(),
+        start_time: sessions().map(|x| x.start_time).min().unwrap_or_default(),
+        ..Default::default()
+    }
+}
+
+/// Numeric value of a field of any number type.
+fn map_number(v: &&fitparser::Value) -> Option<f64> {
+    match v {
+        Value::Byte(x) | Value::Enum(x) | Value::UInt8(x) | Value::UInt8z(x) => Some(f64::from(*x)),
+        Value::SInt8(x) => Some(f64::from(*x)),
+        Value::SInt16(x) => Some(f64::from(*x)),
+        Value::UInt16(x) | Value::UInt16z(x) => Some(f64::from(*x)),
+        Value::SInt32(x) => Some(f64::from(*x)),
+        Value::UInt32(x) | Value::UInt32z(x) => Some(f64::from(*x)),
+        Value::SInt64(x) => Some(*x as f64),
+        Value::UInt64(x) | Value::UInt64z(x) => Some(*x as f64),
+        Value::Float32(x) => Some(f64::from(*x)),
+        Value::Float64(x) => Some(*x),
+        _ => None,
+    }
+}
+
+/// Adds a channel for a developer field, padded to the records read so far.
+/// Values are matched to the channel in `parse_record` by the developer data
+/// index and field number, as names are chosen by each app and may clash.
+fn parse_field_description(fields: &[FitDataField], record: &mut Record) {
+    let field_map: HashMap<&str, &fitparser::Value> =
+        fields.iter().map(|x| (x.name(), x.value())).collect();
+
+    let (developer_data_index, field_number, name) = match (
+        field_map.get("developer_data_index").and_then(map_uint8),
+        field_map.get("field_definition_number").and_then(map_uint8),
+        field_map.get("field_name").and_then(map_string),
+    ) {
+        (Some(index), Some(number), Some(name)) => (index, number, name),
+        _ => return,
+    };
+
+    if record
+        .developer_fields
+        .iter()
+        .any(|x| x.developer_data_index == developer_data_index && x.field_number == field_number)
+    {
+        return;
+    }
+
+    record.developer_fields.push(DeveloperField {
+        developer_data_index,
+        field_number,
+        name,
+        units: field_map.get("units").and_then(map_string),
+        native_field_num: field_map.get("native_field_num").and_then(map_uint8),
+        values: vec![None; record.timestamp.len()],
+    });
+}
+
+fn parse_length(fields: &[FitDataField]) -> Option<SwimLength> {
+    let field_map: HashMap<&str, &fitparser::Value> =
+        fields.iter().map(|x| (x.name(), x.value())).collect();
+
+    let duration = field_map
+        .get("total_elapsed_time")
+        .and_then(map_float64)
+        .unwrap_or_default();
+    let active = field_map.get("length_type").and_then(map_string).as_deref() != Some("idle");
+    let strokes = field_map.get("total_strokes").and_then(map_uint16);
+
+    Some(SwimLength {
+        start_time: field_map.get("start_time").and_then(map_timestamp)?,
+        duration: Duration::from_secs_f64(duration).into(),
+        active,
+        stroke: field_map
+            .get("swim_stroke")
+            .and_then(map_string)
+            .and_then(|x| swim::stroke(&x)),
+        strokes,
+        swolf: strokes
+            .filter(|_| active)
+            .map(|x| x.saturating_add(duration.round() as u16)),
+    })
+}
+
+fn parse_file_id(fields: &[FitDataField]) -> FileId {
+    let field_map: HashMap<&str, &fitparser::Value> =
+        fields.iter().map(|x| (x.name(), x.value())).collect();
+
+    // The product is decoded by name for known manufacturers, e.g.
+    // `garmin_product`, and as a plain number otherwise.
+    let product = fields
+        .iter()
+        .find(|x| x.name().ends_with("product"))
+        .map(|x| x.value())
+        .and_then(|x| map_string(&x).or_else(|| map_uint16(&x).map(|x| x.to_string())));
+
+    FileId {
+        manufacturer: field_map.get("manufacturer").and_then(map_string),
+        product,
+        serial_number: field_map.get("serial_number").and_then(map_uint32),
+        time_created: field_map.get("time_created").and_then(map_timestamp),
+    }
+}
+
+/// Starts and stops of the timer. Other events, such as gear changes, are
+/// skipped.
+fn parse_timer_event(fields: &[FitDataField]) -> Option<TimerEvent> {
+    let field_map: HashMap<&str, &fitparser::Value> =
+        fields.iter().map(|x| (x.name(), x.value())).collect();
+
+    if field_map.get("event").and_then(map_string).as_deref() != Some("timer") {
+        return None;
+    }
+
+    let running = match field_map.get("event_type").and_then(map_string)?.as_str() {
+        "start" => true,
+        "stop" | "stop_all" | "stop_disable" | "stop_disable_all" => false,
+        _ => return None,
+    };
+
+    Some(TimerEvent {
+        timestamp: field_map.get("timestamp").and_then(map_timestamp)?,
+        running,
+        auto: field_map
+            .get("timer_trigger")
+            .and_then(map_string)
+            .as_deref()
+            == Some("auto"),
     })
 }
 
-fn parse_session(fields: &[FitDataField], session: &mut Session) -> Result<()> {
+/// Adds the device of a `device_info` message, or updates it if seen before.
+/// Devices usually report at the start and the end of an activity, and the
+/// later values, such as the battery status, are kept.
+fn parse_device_info(fields: &[FitDataField], devices: &mut Vec<(String, Device)>) {
+    let field_map: HashMap<&str, &fitparser::Value> =
+        fields.iter().map(|x| (x.name(), x.value())).collect();
+
+    let index = match field_map
+        .get("device_index")
+        .and_then(|x| map_string(x).or_else(|| map_uint8(x).map(|x| x.to_string())))
+    {
+        Some(index) => index,
+        None => return,
+    };
+
+    // Both the product and the device type are subfields named after the
+    // manufacturer or the kind of sensor, e.g. `antplus_device_type`
+    let by_suffix = |suffix: &str| {
+        fields
+            .iter()
+            .find(|x| x.name().ends_with(suffix))
+            .map(|x| x.value())
+            .and_then(|x| map_string(&x).or_else(|| map_uint16(&x).map(|x| x.to_string())))
+    };
+
+    let device = Device {
+        creator: index == "creator" || index == "0",
+        device_type: by_suffix("device_type"),
+        manufacturer: field_map.get("manufacturer").and_then(map_string),
+        product: field_map
+            .get("product_name")
+            .and_then(map_string)
+            .or_else(|| by_suffix("product")),
+        serial_number: field_map.get("serial_number").and_then(map_uint32),
+        software_version: field_map.get("software_version").and_then(map_float64),
+        battery_status: field_map.get("battery_status").and_then(map_string),
+        battery_voltage: field_map.get("battery_voltage").and_then(map_float64),
+    };
+
+    match devices.iter_mut().find(|(x, _)| *x == index) {
+        Some((_, existing)) => {
+            existing.device_type = device.device_type.or(existing.device_type.take());
+            existing.manufacturer = device.manufacturer.or(existing.manufacturer.take());
+            existing.product = device.product.or(existing.product.take());
+            existing.serial_number = device.serial_number.or(existing.serial_number);
+            existing.software_version = device.software_version.or(existing.software_version);
+            existing.battery_status = device.battery_status.or(existing.battery_status.take());
+            existing.battery_voltage = device.battery_voltage.or(existing.battery_voltage);
+        }
+        None => devices.push((index, device)),
+    }
+}
+
+/// Seconds east of UTC, from the local and UTC timestamps of the activity
+/// message. Rounded to a quarter of an hour, as the timestamps of some
+/// devices differ by a few seconds.
+fn parse_utc_offset(fields: &[FitDataField]) -> Option<i32> {
+    let seconds = |name: &str| {
+        fields
+            .iter()
+            .find(|x| x.name() == name)
+            .and_then(|x| match x.value() {
+                Value::Timestamp(x) => Some(x.timestamp()),
+                Value::UInt32(x) => Some(i64::from(*x) + FIT_EPOCH),
+                _ => None,
+            })
+    };
+
+    let offset = seconds("local_timestamp")? - seconds("timestamp")?;
+    let offset = (offset as f64 / 900.).round() as i32 * 900;
+
+    (offset.abs() <= MAX_UTC_OFFSET).then_some(offset)
+}
+
+/// The sport of a session or sport message, warning about names that are
+/// not known.
+fn parse_sport(fields: &[FitDataField], report: &mut Report) -> Option<Sport> {
+    let value = fields
+        .iter()
+        .find(|x| x.name() == "sport")
+        .and_then(|x| map_string(&x.value()))?;
+
+    let sport = Sport::from_str(&value).unwrap_or(Sport::Unknown);
+    let warning = Warning::UnknownSport { value };
+
+    if sport == Sport::Unknown && !report.warnings.contains(&warning) {
+        report.warnings.push(warning);
+    }
+
+    Some(sport)
+}
+
+fn parse_session(fields: &[FitDataField], index: usize, session: &mut Session) -> Result<()> {
     let field_map: HashMap<&str, &fitparser::Value> =
         fields.iter().map(|x| (x.name(), x.value())).collect();
 
@@ -197,14 +981,6 @@ fn parse_session(fields: &[FitDataField], session: &mut Session) -> Result<()> {
 
     session.laps = field_map.get("num_laps").and_then(map_uint16);
 
-    session.sport = Sport::from_str(
-        &field_map
-            .get("sport")
-            .and_then(map_string)
-            .unwrap_or_default(),
-    )
-    .unwrap_or_default();
-
     session.ascent = field_map
         .get("total_ascent")
         .and_then(map_uint16)
@@ -246,12 +1022,52 @@ fn parse_session(fields: &[FitDataField], session: &mut Session) -> Result<()> {
         .map(Into::into)
         .unwrap_or_default();
 
-    session.start_time = field_map
-        .get("start_time")
-        .and_then(map_timestamp)
-        // https://github.com/chronotope/chrono/issues/576
-        //.map(|x| DateTime::from_utc(x.naive_utc(), *x.offset()))
-        .ok_or(Error::MissingData)?;
+    session.start_time =
+        field_map
+            .get("start_time")
+            .and_then(map_timestamp)
+            .ok_or(Error::MissingField {
+                index,
+                field: "start_time",
+            })?;
+
+    session.temperature_avg = field_map.get("avg_temperature").and_then(map_sint8);
+
+    session.temperature_max = field_map.get("max_temperature").and_then(map_sint8);
+
+    session.left_right_balance_avg = field_map.get("left_right_balance").and_then(map_balance);
+
+    session.vertical_oscillation_avg = field_map
+        .get("avg_vertical_oscillation")
+        .and_then(map_float64)
+        .map(LengthF64::new::<millimeter>)
+        .map(Into::into);
+
+    session.ground_contact_time_avg = field_map
+        .get("avg_stance_time")
+        .and_then(map_float64)
+        .map(|x| Duration::from_secs_f64(x / 1000.))
+        .map(Into::into);
+
+    session.stance_time_balance_avg = field_map
+        .get("avg_stance_time_balance")
+        .and_then(map_float64);
+
+    session.step_length_avg = field_map
+        .get("avg_step_length")
+        .and_then(map_float64)
+        .map(LengthF64::new::<millimeter>)
+        .map(Into::into);
+
+    session.respiration_rate_avg = field_map
+        .get("enhanced_avg_respiration_rate")
+        .and_then(map_float64);
+
+    session.training_effect = field_map.get("total_training_effect").and_then(map_float64);
+
+    session.anaerobic_training_effect = field_map
+        .get("total_anaerobic_training_effect")
+        .and_then(map_float64);
 
     Ok(())
 }
@@ -330,6 +1146,74 @@ fn parse_record(fields: &[FitDataField], record: &mut Record) {
 
     record.duration.push(duration);
     record.timestamp.push(timestamp);
+
+    record
+        .temperature
+        .push(field_map.get("temperature").and_then(map_sint8));
+
+    record
+        .left_right_balance
+        .push(field_map.get("left_right_balance").and_then(map_balance));
+
+    record.vertical_oscillation.push(
+        field_map
+            .get("vertical_oscillation")
+            .and_then(map_float64)
+            .map(LengthF64::new::<millimeter>)
+            .map(Into::into),
+    );
+
+    record.ground_contact_time.push(
+        field_map
+            .get("stance_time")
+            .and_then(map_float64)
+            .map(|x| Duration::from_secs_f64(x / 1000.))
+            .map(Into::into),
+    );
+
+    record
+        .stance_time_balance
+        .push(field_map.get("stance_time_balance").and_then(map_float64));
+
+    record.step_length.push(
+        field_map
+            .get("step_length")
+            .and_then(map_float64)
+            .map(LengthF64::new::<millimeter>)
+            .map(Into::into),
+    );
+
+    record.respiration_rate.push(
+        field_map
+            .get("enhanced_respiration_rate")
+            .and_then(map_float64)
+            .or_else(|| {
+                field_map
+                    .get("respiration_rate")
+                    .and_then(map_uint8)
+                    .map(f64::from)
+            }),
+    );
+
+    for developer_field in record.developer_fields.iter_mut() {
+        developer_field.values.push(None);
+    }
+
+    for field in fields {
+        let index = match field.developer_data_index() {
+            Some(index) => index,
+            None => continue,
+        };
+
+        if let Some(value) = record
+            .developer_fields
+            .iter_mut()
+            .find(|x| x.developer_data_index == index && x.field_number == field.number())
+            .and_then(|x| x.values.last_mut())
+        {
+            *value = map_number(&field.value());
+        }
+    }
 }
 
 fn parse_lap(fields: &[FitDataField], lap: &mut Lap) {
@@ -442,4 +1326,36 @@ fn parse_lap(fields: &[FitDataField], lap: &mut Lap) {
         .map(Duration::from_secs_f64)
         .map(Into::into)
         .unwrap_or_default();
+
+    lap.temperature_avg = field_map.get("avg_temperature").and_then(map_sint8);
+
+    lap.temperature_max = field_map.get("max_temperature").and_then(map_sint8);
+
+    lap.left_right_balance_avg = field_map.get("left_right_balance").and_then(map_balance);
+
+    lap.vertical_oscillation_avg = field_map
+        .get("avg_vertical_oscillation")
+        .and_then(map_float64)
+        .map(LengthF64::new::<millimeter>)
+        .map(Into::into);
+
+    lap.ground_contact_time_avg = field_map
+        .get("avg_stance_time")
+        .and_then(map_float64)
+        .map(|x| Duration::from_secs_f64(x / 1000.))
+        .map(Into::into);
+
+    lap.stance_time_balance_avg = field_map
+        .get("avg_stance_time_balance")
+        .and_then(map_float64);
+
+    lap.step_length_avg = field_map
+        .get("avg_step_length")
+        .and_then(map_float64)
+        .map(LengthF64::new::<millimeter>)
+        .map(Into::into);
+
+    lap.respiration_rate_avg = field_map
+        .get("enhanced_avg_respiration_rate")
+        .and_then(map_float64);
 }
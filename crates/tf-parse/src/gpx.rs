@@ -0,0 +1,124 @@
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::io::BufRead;
+use std::str::FromStr;
+
+use crate::{
+    error::{Error, Result},
+    pause,
+    summary::{self, Point, Summary},
+    Report,
+};
+
+use tf_models::{Activity, Sport};
+
+fn attribute<T: FromStr>(element: &BytesStart, name: &str) -> Option<T> {
+    element
+        .try_get_attribute(name)
+        .ok()
+        .flatten()
+        .and_then(|x| x.unescape_value().ok()?.parse().ok())
+}
+
+fn trackpoint(element: &BytesStart) -> Point {
+    Point {
+        lat: attribute(element, "lat"),
+        lon: attribute(element, "lon"),
+        ..Default::default()
+    }
+}
+
+pub fn parse<R: BufRead>(gpx_data: R, report: &mut Report) -> Result<Activity> {
+    let mut reader = Reader::from_reader(gpx_data);
+    reader.trim_text(true);
+    let mut buffer = Vec::new();
+
+    let mut points: Vec<Point> = Vec::new();
+    let mut segments: Vec<usize> = Vec::new();
+    let mut sport = None;
+
+    let mut point: Option<Point> = None;
+    let mut path: Vec<Vec<u8>> = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buffer)? {
+            Event::Start(element) => {
+                match element.local_name().as_ref() {
+                    b"trkseg" => segments.push(points.len()),
+                    b"trkpt" => point = Some(trackpoint(&element)),
+                    _ => (),
+                }
+                path.push(element.local_name().as_ref().to_vec());
+            }
+            Event::Empty(element) if element.local_name().as_ref() == b"trkpt" => {
+                points.push(trackpoint(&element));
+            }
+            Event::End(element) => {
+                if element.local_name().as_ref() == b"trkpt" {
+                    points.extend(point.take());
+                }
+                path.pop();
+            }
+            Event::Text(text) => {
+                let text = text.unescape()?;
+                let parent = path.iter().rev().nth(1).map(Vec::as_slice);
+
+                match (path.last().map(Vec::as_slice), point.as_mut()) {
+                    (Some(b"ele"), Some(point)) => point.altitude = text.parse().ok(),
+                    (Some(b"time"), Some(point)) => point.timestamp = summary::parse_time(&text),
+                    (Some(b"hr"), Some(point)) => point.heartrate = text.parse().ok(),
+                    (Some(b"cad"), Some(point)) => point.cadence = text.parse().ok(),
+                    (Some(b"atemp"), Some(point)) => {
+                        point.temperature = text.parse::<f64>().ok().map(|x| x.round() as i8)
+                    }
+                    (Some(b"power" | b"PowerInWatts"), Some(point)) => {
+                        point.power = text.parse().ok()
+                    }
+                    (Some(b"type"), None) if parent == Some(b"trk".as_slice()) => {
+                        sport = Some(summary::sport(&text, report))
+                    }
+                    _ => (),
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+
+        buffer.clear();
+    }
+
+    if !points.iter().any(|x| x.timestamp.is_some()) {
+        return Err(Error::MissingData);
+    }
+
+    summary::fill_derived(&mut points);
+
+    if segments.first() != Some(&0) {
+        segments.insert(0, 0);
+    }
+
+    let lap = summary::lap_ranges(&segments, points.len())
+        .into_iter()
+        .filter(|range| !range.is_empty())
+        .map(|range| Summary::new(&points[range]).lap())
+        .collect::<Vec<_>>();
+
+    let mut session = Summary::new(&points).session(sport.unwrap_or(Sport::Generic), lap.len());
+    let record = summary::record(&points);
+
+    let pauses = pause::detect(&record);
+    session.duration_active = pause::active_duration(&session, &pauses).into();
+
+    Ok(Activity {
+        id: crate::activity_id(&session)?,
+        session,
+        record,
+        lap,
+        file_id: None,
+        children: Vec::new(),
+        record_range: None,
+        swim: None,
+        devices: Vec::new(),
+        pauses,
+    })
+}
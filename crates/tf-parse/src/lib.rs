@@ -1,3 +1,174 @@
+mod crc;
+pub mod effort;
+pub mod elevation;
 pub mod error;
+pub mod export;
 mod fit;
-pub use fit::*;
+mod gpx;
+pub mod load;
+mod pause;
+pub mod power;
+mod report;
+mod summary;
+mod swim;
+mod tcx;
+mod timezone;
+pub mod zones;
+
+use error::{Error, Result};
+pub use report::{Fatal, Repair, Report, Warning};
+use std::io::{BufRead, Cursor, Read};
+use tf_models::{activity::Session, Activity, ActivityId};
+
+// Leading bytes of a file looked at to find its format, read further up to
+// the maximum while an XML prolog with long comments or a document type
+// declaration has not ended
+const HEAD_SIZE: usize = 1024;
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Fit,
+    Gpx,
+    Tcx,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Fit => "fit",
+            Self::Gpx => "gpx",
+            Self::Tcx => "tcx",
+        }
+    }
+
+    /// Sniffs the format from the leading bytes of the file, which for XML
+    /// files must hold the start of the root element.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.get(8..12) == Some(b".FIT".as_slice()) {
+            return Some(Self::Fit);
+        }
+
+        let name = root_element(&data[..data.len().min(MAX_HEAD_SIZE)])?;
+
+        // With or without a namespace prefix
+        match name.rsplit(|x| *x == b':').next()? {
+            b"gpx" => Some(Self::Gpx),
+            b"TrainingCenterDatabase" => Some(Self::Tcx),
+            _ => None,
+        }
+    }
+}
+
+/// The name of the root element of an XML document, found by skipping the
+/// byte order mark, XML declaration, processing instructions, comments and
+/// document type declaration before it.
+fn root_element(data: &[u8]) -> Option<&[u8]> {
+    let find = |data: &[u8], pattern: &[u8]| {
+        data.windows(pattern.len())
+            .position(|x| x == pattern)
+            .map(|x| x + pattern.len())
+    };
+
+    let mut data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+
+    loop {
+        data = data.trim_ascii_start();
+
+        let end = if data.starts_with(b"<?") {
+            find(data, b"?>")?
+        } else if data.starts_with(b"<!--") {
+            find(data, b"-->")?
+        } else if data.starts_with(b"<!") {
+            // Any internal subset of the document type is in brackets, and
+            // may hold markup of its own
+            let mut depth = 0_usize;
+            data.iter().position(|x| {
+                match *x {
+                    b'[' => depth += 1,
+                    b']' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                *x == b'>' && depth == 0
+            })? + 1
+        } else if let Some(element) = data.strip_prefix(b"<") {
+            let end = element
+                .iter()
+                .position(|x| x.is_ascii_whitespace() || *x == b'>' || *x == b'/')?;
+            return Some(&element[..end]).filter(|x| !x.is_empty());
+        } else {
+            return None;
+        };
+
+        data = &data[end..];
+    }
+}
+
+pub fn parse(data: &[u8]) -> Result<Activity> {
+    parse_with_report(data, false).0
+}
+
+/// Like `parse`, but recovers what it can from truncated or corrupt FIT
+/// files instead of rejecting them. The repairs made are in the report.
+pub fn parse_lenient(data: &[u8]) -> Result<(Activity, Report)> {
+    let (activity, report) = parse_with_report(data, true);
+
+    activity.map(|activity| (activity, report))
+}
+
+/// Parses a file and reports the problems found on the way. If parsing
+/// fails, the error is in the report along with the warnings found before.
+pub fn parse_with_report(data: &[u8], lenient: bool) -> (Result<Activity>, Report) {
+    parse_reader(data, lenient)
+}
+
+/// Like `parse_with_report`, but reads the file as it is parsed. FIT
+/// messages are decoded one at a time and dropped once their values are in
+/// the record columns, so memory use grows with the number of records, but
+/// the file is never held in memory.
+pub fn parse_reader<R: BufRead>(mut reader: R, lenient: bool) -> (Result<Activity>, Report) {
+    let mut report = Report::default();
+
+    // The head is read in full, as a reader may return less than asked for,
+    // and then put back in front of the rest of the file
+    let mut head = Vec::with_capacity(HEAD_SIZE);
+    let mut size = HEAD_SIZE;
+    let format = loop {
+        if let Err(error) = Read::by_ref(&mut reader)
+            .take((size - head.len()) as u64)
+            .read_to_end(&mut head)
+        {
+            break Err(error.into());
+        }
+
+        match Format::detect(&head) {
+            Some(format) => break Ok(format),
+            None if head.len() == size && size < MAX_HEAD_SIZE => size *= 4,
+            None => break Err(Error::UnknownFormat),
+        }
+    };
+    let reader = Cursor::new(head).chain(reader);
+
+    let activity = format.and_then(|format| match format {
+        Format::Fit => fit::parse(reader, lenient, &mut report),
+        Format::Gpx => gpx::parse(reader, &mut report),
+        Format::Tcx => tcx::parse(reader, &mut report),
+    });
+
+    let activity = activity.and_then(|mut activity| {
+        timezone::localize(&mut activity, None)?;
+        pause::fill_timer_time(&mut activity);
+        Ok(activity)
+    });
+
+    match &activity {
+        Ok(activity) => report.check(activity),
+        Err(error) => report.errors.push(error.into()),
+    }
+
+    (activity, report)
+}
+
+fn activity_id(session: &Session) -> Result<ActivityId> {
+    ActivityId::from_start_time(&session.local_start_time()).map_err(|_| Error::InvalidStartTime)
+}
@@ -0,0 +1,110 @@
+use tf_models::{
+    activity::{Load, Record},
+    types::{Power, Velocity},
+    user::User,
+    Activity, Sport,
+};
+use uom::si::{power::watt, velocity::meter_per_second};
+
+use crate::power::{resample, MAX_GAP};
+
+// Seconds of the rolling average that normalized power is worked out from,
+// roughly how long the body takes to respond to a change in effort.
+const WINDOW: usize = 30;
+
+/// Training load of an activity that does not depend on thresholds. TRIMP
+/// needs the resting and maximum heart rate of the user.
+pub fn load(activity: &Activity, user: Option<&User>) -> Load {
+    let record = &activity.record;
+
+    let normalized_power = normalized(
+        record,
+        record
+            .power
+            .iter()
+            .map(|x| x.map(|x| f64::from(x.get::<watt>()))),
+    )
+    .map(|x| Power::new::<watt>(x.round() as u16));
+
+    // Pace is only a measure of effort for runs
+    let normalized_speed = match activity.session.sport {
+        Sport::Running => normalized(
+            record,
+            record
+                .speed
+                .iter()
+                .map(|x| x.map(|x| x.get::<meter_per_second>())),
+        )
+        .map(Velocity::new::<meter_per_second>),
+        _ => None,
+    };
+
+    Load {
+        duration: activity.session.duration_active,
+        normalized_power,
+        normalized_speed,
+        heartrate_avg: activity.session.heartrate_avg,
+        trimp: user.and_then(|user| trimp(record, user)),
+    }
+}
+
+/// Fourth root of the mean fourth power of the rolling average over
+/// `WINDOW` seconds, on a timeline resampled to one second. `None` without
+/// values, or when shorter than the window.
+fn normalized<I>(record: &Record, values: I) -> Option<f64>
+where
+    I: IntoIterator<Item = Option<f64>>,
+{
+    let values = values.into_iter().collect::<Vec<_>>();
+
+    if values.iter().all(Option::is_none) {
+        return None;
+    }
+
+    let samples = resample(record, values).values;
+
+    if samples.len() < WINDOW {
+        return None;
+    }
+
+    let mut sums = Vec::with_capacity(samples.len() + 1);
+    sums.push(0.);
+    for value in &samples {
+        sums.push(sums[sums.len() - 1] + value);
+    }
+
+    let averages = (WINDOW..sums.len()).map(|end| (sums[end] - sums[end - WINDOW]) / WINDOW as f64);
+    let count = sums.len() - WINDOW;
+
+    Some((averages.map(|x| x.powi(4)).sum::<f64>() / count as f64).powf(0.25))
+}
+
+/// Banister training impulse, the minutes between records weighted by the
+/// heart rate reserve at the start of each. Uses the weighting for men, as
+/// the sex of the user is not known.
+fn trimp(record: &Record, user: &User) -> Option<f64> {
+    let rest = f64::from(user.heartrate_rest);
+    let max = f64::from(user.heartrate_max);
+
+    if max <= rest {
+        return None;
+    }
+
+    let mut trimp = None;
+
+    for ((start, heartrate), end) in record
+        .duration
+        .iter()
+        .zip(&record.heartrate)
+        .zip(record.duration.iter().skip(1))
+    {
+        if let Some(heartrate) = heartrate {
+            let minutes = (end.as_secs_f64() - start.as_secs_f64()).clamp(0., MAX_GAP) / 60.;
+            let reserve = ((f64::from(*heartrate) - rest) / (max - rest)).clamp(0., 1.);
+
+            *trimp.get_or_insert(0.) += minutes * reserve * 0.64 * (1.92 * reserve).exp();
+        }
+    }
+
+    trimp
+}
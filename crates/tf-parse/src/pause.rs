@@ -0,0 +1,157 @@
+use std::time::Duration;
+
+use chrono::{offset::Utc, DateTime};
+use tf_models::{
+    activity::{Pause, PauseKind, Record, Session},
+    Activity,
+};
+use uom::si::velocity::meter_per_second;
+
+// Slower than this counts as standing still
+const MOVING_SPEED: f64 = 0.5;
+
+// Gaps between records longer than this are treated as the recording being
+// paused, as devices with smart recording write at least every few seconds.
+const MAX_GAP: i64 = 10;
+
+// Shorter stops, like waiting at a crossing, are not pauses
+const MIN_PAUSE: i64 = 10;
+
+/// A `timer` event, with whether the timer runs after it and whether it was
+/// triggered by auto-pause.
+pub(crate) struct TimerEvent {
+    pub timestamp: DateTime<Utc>,
+    pub running: bool,
+    pub auto: bool,
+}
+
+fn pause(start: DateTime<Utc>, end: DateTime<Utc>, kind: PauseKind) -> Pause {
+    Pause {
+        start_time: start,
+        duration: end
+            .signed_duration_since(start)
+            .to_std()
+            .unwrap_or_default()
+            .into(),
+        kind,
+    }
+}
+
+/// The spans between stopping and starting the timer. A stop without a later
+/// start ends the activity and is not a pause.
+pub(crate) fn from_events(events: &[TimerEvent]) -> Vec<Pause> {
+    let mut pauses = Vec::new();
+    let mut stopped: Option<&TimerEvent> = None;
+
+    for event in events {
+        match (stopped, event.running) {
+            (None, false) => stopped = Some(event),
+            (Some(stop), true) => {
+                let kind = if stop.auto {
+                    PauseKind::Auto
+                } else {
+                    PauseKind::Manual
+                };
+
+                pauses.push(pause(stop.timestamp, event.timestamp, kind));
+                stopped = None;
+            }
+            _ => (),
+        }
+    }
+
+    pauses
+}
+
+/// Finds the spans where the records stand still or have a gap, for files
+/// that do not say when the timer was stopped.
+pub(crate) fn detect(record: &Record) -> Vec<Pause> {
+    let mut pauses = Vec::new();
+    let mut current: Option<(DateTime<Utc>, DateTime<Utc>)> = None;
+    let mut last = None;
+
+    for (i, timestamp) in record.timestamp.iter().enumerate() {
+        let timestamp = match timestamp {
+            Some(x) => *x,
+            None => continue,
+        };
+
+        if let Some(last) = last {
+            let gap = timestamp.signed_duration_since(last).num_seconds() > MAX_GAP;
+            let standing = record
+                .speed
+                .get(i)
+                .and_then(Option::as_ref)
+                .filter(|x| x.get::<meter_per_second>() < MOVING_SPEED)
+                .is_some();
+
+            if gap || standing {
+                current = Some((current.map_or(last, |(start, _)| start), timestamp));
+            } else if let Some((start, end)) = current.take() {
+                if end.signed_duration_since(start).num_seconds() >= MIN_PAUSE {
+                    pauses.push(pause(start, end, PauseKind::Detected));
+                }
+            }
+        }
+
+        last = Some(timestamp);
+    }
+
+    if let Some((start, end)) = current {
+        if end.signed_duration_since(start).num_seconds() >= MIN_PAUSE {
+            pauses.push(pause(start, end, PauseKind::Detected));
+        }
+    }
+
+    pauses
+}
+
+/// Time not spent in pauses between the two points in time.
+pub(crate) fn moving_time(pauses: &[Pause], start: DateTime<Utc>, end: DateTime<Utc>) -> Duration {
+    let paused = pauses
+        .iter()
+        .map(|pause| {
+            let from = pause.start_time.max(start);
+            let to = pause.end_time().min(end);
+
+            to.signed_duration_since(from).to_std().unwrap_or_default()
+        })
+        .sum::<Duration>();
+
+    end.signed_duration_since(start)
+        .to_std()
+        .unwrap_or_default()
+        .saturating_sub(paused)
+}
+
+/// Duration of a session without its pauses.
+pub(crate) fn active_duration(session: &Session, pauses: &[Pause]) -> Duration {
+    let end =
+        session.start_time + chrono::Duration::milliseconds(session.duration.as_millis() as i64);
+
+    moving_time(pauses, session.start_time, end)
+}
+
+/// Fills the timer time channel of an activity and its children from their
+/// pauses.
+pub(crate) fn fill_timer_time(activity: &mut Activity) {
+    let record = &mut activity.record;
+    let start = record.timestamp.iter().find_map(|x| *x);
+    let mut last = Duration::ZERO;
+
+    record.timer_time = record
+        .timestamp
+        .iter()
+        .map(|timestamp| {
+            // Records without a timestamp keep the timer time of the last
+            if let Some((start, timestamp)) = start.zip(*timestamp) {
+                last = moving_time(&activity.pauses, start, timestamp);
+            }
+            last.into()
+        })
+        .collect();
+
+    for child in &mut activity.children {
+        fill_timer_time(child);
+    }
+}
@@ -0,0 +1,139 @@
+use std::time::Duration;
+
+use tf_models::{
+    activity::{PowerBest, PowerCurve, Record},
+    types::Power,
+};
+use uom::si::power::watt;
+
+// Records further apart than this leave a gap without power, such as when
+// the timer was stopped, as devices with smart recording write at least
+// every few seconds.
+pub(crate) const MAX_GAP: f64 = 10.;
+
+/// Durations of the power curve in seconds.
+pub const DURATIONS: [u64; 24] = [
+    1, 2, 5, 10, 15, 20, 30, 45, 60, 120, 180, 300, 480, 600, 900, 1200, 1800, 2700, 3600, 5400,
+    7200, 10800, 14400, 21600,
+];
+
+/// Values for each second of an activity, from the last record at or before
+/// the second. Records without a value count as zero.
+pub(crate) struct Timeline {
+    pub values: Vec<f64>,
+    // Seconds left out before the value at each index, in order
+    skips: Vec<(usize, usize)>,
+}
+
+impl Timeline {
+    /// Seconds since the start of the activity at an index of `values`.
+    pub fn second(&self, index: usize) -> usize {
+        index
+            + self
+                .skips
+                .iter()
+                .take_while(|(at, _)| *at <= index)
+                .map(|(_, seconds)| seconds)
+                .sum::<usize>()
+    }
+}
+
+/// Resamples values to one second. A record counts for the seconds up to
+/// `MAX_GAP` after it, and the seconds of longer gaps are left out, so the
+/// timeline is no longer than the time that was recorded, whatever the
+/// times of the records.
+pub(crate) fn resample<I>(record: &Record, values: I) -> Timeline
+where
+    I: IntoIterator<Item = Option<f64>>,
+{
+    let samples = record
+        .duration
+        .iter()
+        .zip(values)
+        .map(|(duration, value)| (duration.as_secs_f64(), value.unwrap_or_default()))
+        .collect::<Vec<_>>();
+
+    let mut timeline = Timeline {
+        values: Vec::new(),
+        skips: Vec::new(),
+    };
+
+    let end = match samples.iter().map(|x| x.0).reduce(f64::max) {
+        Some(end) => end.floor() as usize,
+        None => return timeline,
+    };
+
+    let mut current = None;
+    let mut next = 0;
+    let mut second = 0;
+
+    while second <= end {
+        while let Some(sample) = samples.get(next).filter(|x| x.0 <= second as f64) {
+            current = Some(*sample);
+            next += 1;
+        }
+
+        match current.filter(|(time, _)| second as f64 - time < MAX_GAP) {
+            Some((_, value)) => {
+                timeline.values.push(value);
+                second += 1;
+            }
+            None => {
+                let resume = match samples.get(next) {
+                    Some((time, _)) => (time.ceil() as usize).max(second + 1),
+                    None => break,
+                };
+
+                timeline
+                    .skips
+                    .push((timeline.values.len(), resume - second));
+                second = resume;
+            }
+        }
+    }
+
+    timeline
+}
+
+/// The best average power for each of `DURATIONS` that fits in the activity,
+/// on a timeline resampled to one second, see `resample`. `None` without
+/// power data.
+pub fn curve(record: &Record) -> Option<PowerCurve> {
+    if record.power.iter().all(Option::is_none) {
+        return None;
+    }
+
+    let timeline = resample(
+        record,
+        record
+            .power
+            .iter()
+            .map(|x| x.map(|x| f64::from(x.get::<watt>()))),
+    );
+    let power = &timeline.values;
+
+    let mut sums = Vec::with_capacity(power.len() + 1);
+    sums.push(0.);
+    for watts in power {
+        sums.push(sums[sums.len() - 1] + watts);
+    }
+
+    let bests = DURATIONS
+        .iter()
+        .map(|x| *x as usize)
+        .take_while(|duration| *duration <= power.len())
+        .map(|duration| {
+            let (start, sum) = (0..=power.len() - duration)
+                .map(|start| (start, sums[start + duration] - sums[start]))
+                .fold((0, f64::MIN), |best, x| if x.1 > best.1 { x } else { best });
+
+            PowerBest {
+                duration: Duration::from_secs(duration as u64).into(),
+                power: Power::new::<watt>((sum / duration as f64).round() as u16),
+                start: Duration::from_secs(timeline.second(start) as u64).into(),
+            }
+        })
+        .collect();
+
+    Some(PowerCurve { bests })
+}
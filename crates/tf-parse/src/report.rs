@@ -0,0 +1,124 @@
+use serde::Serialize;
+use tf_models::Activity;
+use uom::si::length::meter;
+
+use crate::error::Error;
+
+/// Everything noteworthy found while parsing a file, so that uploaders can
+/// flag suspicious files even when they were accepted.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Report {
+    pub warnings: Vec<Warning>,
+    pub repairs: Vec<Repair>,
+    pub errors: Vec<Fatal>,
+}
+
+/// Something unexpected in a file that was parsed anyway.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Warning {
+    /// The sport could not be mapped and was stored as unknown.
+    UnknownSport { value: String },
+    /// Records without a timestamp.
+    MissingTimestamps { count: usize },
+    /// Records with a latitude or longitude outside the valid range.
+    CoordinatesOutOfRange { count: usize, first_index: usize },
+    /// Records where the distance decreases.
+    NonMonotonicDistance { count: usize, first_index: usize },
+    /// A message could not be decoded and was skipped.
+    DroppedRecord { index: usize, reason: String },
+}
+
+/// A repair made by `parse_lenient` to turn a damaged file into an
+/// activity.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Repair {
+    /// Decoding stopped at byte `offset`, and the rest of the file was
+    /// dropped.
+    Truncated { offset: usize, reason: String },
+    /// The file decoded fine, but its checksum did not match.
+    InvalidChecksum,
+    /// The session was missing and was computed from the records.
+    SynthesizedSession,
+    /// The records after the last lap were put in a lap of their own.
+    SynthesizedLap,
+}
+
+/// The error that made parsing fail, located where possible.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Fatal {
+    pub message: String,
+    /// Index of the message, counting data messages from the start.
+    pub index: Option<usize>,
+    pub field: Option<&'static str>,
+}
+
+impl From<&Error> for Fatal {
+    fn from(error: &Error) -> Self {
+        let (index, field) = match error {
+            Error::Decode { index, .. } => (Some(*index), None),
+            Error::MissingField { index, field } => (Some(*index), Some(*field)),
+            _ => (None, None),
+        };
+
+        Self {
+            message: error.to_string(),
+            index,
+            field,
+        }
+    }
+}
+
+impl Report {
+    /// Adds warnings for suspicious values in the records of an activity.
+    pub(crate) fn check(&mut self, activity: &Activity) {
+        let record = &activity.record;
+
+        let missing = record.timestamp.iter().filter(|x| x.is_none()).count();
+        if missing > 0 {
+            self.warnings
+                .push(Warning::MissingTimestamps { count: missing });
+        }
+
+        let out_of_range = record
+            .lat
+            .iter()
+            .zip(&record.lon)
+            .enumerate()
+            .filter(|(_, (lat, lon))| {
+                lat.filter(|x| !(-90. ..=90.).contains(x)).is_some()
+                    || lon.filter(|x| !(-180. ..=180.).contains(x)).is_some()
+            })
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        if let Some(first_index) = out_of_range.first() {
+            self.warnings.push(Warning::CoordinatesOutOfRange {
+                count: out_of_range.len(),
+                first_index: *first_index,
+            });
+        }
+
+        let mut last = None;
+        let decreasing = record
+            .distance
+            .iter()
+            .enumerate()
+            .filter_map(|(i, x)| x.as_ref().map(|x| (i, x.get::<meter>())))
+            .filter(|(_, distance)| {
+                let decreased = last.filter(|last| distance < last).is_some();
+                last = Some(*distance);
+                decreased
+            })
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        if let Some(first_index) = decreasing.first() {
+            self.warnings.push(Warning::NonMonotonicDistance {
+                count: decreasing.len(),
+                first_index: *first_index,
+            });
+        }
+    }
+}
@@ -0,0 +1,406 @@
+use std::{ops::Range, str::FromStr, time::Duration};
+
+use chrono::{offset::Utc, DateTime};
+
+use crate::{Report, Warning};
+use tf_models::{
+    activity::{Lap, Record, Session},
+    types::{AngularVelocity, LengthF64, LengthU32, Power, Velocity},
+    Sport,
+};
+use uom::si::{
+    angular_velocity::revolution_per_minute, length::meter, power::watt, velocity::meter_per_second,
+};
+
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+// Altitude changes smaller than this are treated as noise when summing
+// ascent and descent.
+const ELEVATION_THRESHOLD: f64 = 1.;
+
+/// A single sample from a track based format, before it is split into the
+/// columns of a `Record`.
+#[derive(Default, Clone)]
+pub(crate) struct Point {
+    pub timestamp: Option<DateTime<Utc>>,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    pub altitude: Option<f64>,
+    pub distance: Option<f64>,
+    pub speed: Option<f64>,
+    pub heartrate: Option<u8>,
+    pub cadence: Option<u8>,
+    pub power: Option<u16>,
+    pub temperature: Option<i8>,
+}
+
+impl Point {
+    fn position(&self) -> Option<(f64, f64)> {
+        self.lat.zip(self.lon)
+    }
+}
+
+/// Great-circle distance in meters between two `(lat, lon)` pairs in degrees.
+pub(crate) fn haversine(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+    let (lat2, lon2) = (to.0.to_radians(), to.1.to_radians());
+
+    let a = ((lat2 - lat1) / 2.).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.).sin().powi(2);
+
+    2. * EARTH_RADIUS * a.sqrt().asin()
+}
+
+fn seconds_between(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    to.signed_duration_since(from).num_milliseconds() as f64 / 1000.
+}
+
+/// Derives cumulative distance from the coordinates and speed from the
+/// distance, unless the source already provided them.
+pub(crate) fn fill_derived(points: &mut [Point]) {
+    if points.iter().all(|x| x.distance.is_none()) {
+        let mut total = 0_f64;
+        let mut last = None;
+
+        for point in points.iter_mut() {
+            if let Some(current) = point.position() {
+                if let Some(last) = last {
+                    total += haversine(last, current);
+                }
+                last = Some(current);
+                point.distance = Some(total);
+            }
+        }
+    }
+
+    if points.iter().all(|x| x.speed.is_none()) {
+        let mut last: Option<(DateTime<Utc>, f64)> = None;
+
+        for point in points.iter_mut() {
+            if let Some((timestamp, distance)) = point.timestamp.zip(point.distance) {
+                if let Some((last_timestamp, last_distance)) = last {
+                    let seconds = seconds_between(last_timestamp, timestamp);
+
+                    if seconds > 0. {
+                        point.speed = Some((distance - last_distance) / seconds);
+                    }
+                }
+                last = Some((timestamp, distance));
+            }
+        }
+    }
+}
+
+pub(crate) fn record(points: &[Point]) -> Record {
+    let mut record = Record::default();
+    let start = points.iter().find_map(|x| x.timestamp);
+
+    for point in points {
+        record.cadence.push(
+            point
+                .cadence
+                .map(f64::from)
+                .map(AngularVelocity::new::<revolution_per_minute>),
+        );
+        record
+            .distance
+            .push(point.distance.map(LengthF64::new::<meter>));
+        record
+            .altitude
+            .push(point.altitude.map(LengthF64::new::<meter>));
+        record
+            .speed
+            .push(point.speed.map(Velocity::new::<meter_per_second>));
+        record.heartrate.push(point.heartrate);
+        record.power.push(point.power.map(Power::new::<watt>));
+        record.lat.push(point.lat);
+        record.lon.push(point.lon);
+
+        let duration = start
+            .zip(point.timestamp)
+            .and_then(|(start, timestamp)| timestamp.signed_duration_since(start).to_std().ok())
+            .unwrap_or_default();
+
+        record.duration.push(duration.into());
+        record.timestamp.push(point.timestamp);
+        record.temperature.push(point.temperature);
+        record.left_right_balance.push(None);
+        record.vertical_oscillation.push(None);
+        record.ground_contact_time.push(None);
+        record.stance_time_balance.push(None);
+        record.step_length.push(None);
+        record.respiration_rate.push(None);
+    }
+
+    record
+}
+
+/// Turns the columns of a `Record` back into points, to summarize files that
+/// lack the summaries they should contain.
+pub(crate) fn points(record: &Record) -> Vec<Point> {
+    record
+        .timestamp
+        .iter()
+        .enumerate()
+        .map(|(i, timestamp)| Point {
+            timestamp: *timestamp,
+            lat: record.lat.get(i).copied().flatten(),
+            lon: record.lon.get(i).copied().flatten(),
+            altitude: record
+                .altitude
+                .get(i)
+                .and_then(Option::as_ref)
+                .map(|x| x.get::<meter>()),
+            distance: record
+                .distance
+                .get(i)
+                .and_then(Option::as_ref)
+                .map(|x| x.get::<meter>()),
+            speed: record
+                .speed
+                .get(i)
+                .and_then(Option::as_ref)
+                .map(|x| x.get::<meter_per_second>()),
+            heartrate: record.heartrate.get(i).copied().flatten(),
+            cadence: record
+                .cadence
+                .get(i)
+                .and_then(Option::as_ref)
+                .map(|x| x.get::<revolution_per_minute>().round() as u8),
+            power: record
+                .power
+                .get(i)
+                .and_then(Option::as_ref)
+                .map(|x| x.get::<watt>()),
+            temperature: record.temperature.get(i).copied().flatten(),
+        })
+        .collect()
+}
+
+fn average<I: Iterator<Item = f64>>(iter: I) -> Option<f64> {
+    let (sum, count) = iter.fold((0_f64, 0_usize), |(sum, count), x| (sum + x, count + 1));
+
+    (count > 0).then_some(sum / count as f64)
+}
+
+fn maximum<I: Iterator<Item = f64>>(iter: I) -> Option<f64> {
+    iter.reduce(f64::max)
+}
+
+fn minimum<I: Iterator<Item = f64>>(iter: I) -> Option<f64> {
+    iter.reduce(f64::min)
+}
+
+/// Aggregates over a run of points, shared by sessions and laps.
+#[derive(Default)]
+pub(crate) struct Summary {
+    pub cadence_avg: Option<f64>,
+    pub cadence_max: Option<f64>,
+    pub heartrate_avg: Option<u8>,
+    pub heartrate_max: Option<u8>,
+    pub speed_avg: Option<f64>,
+    pub speed_max: Option<f64>,
+    pub power_avg: Option<u16>,
+    pub power_max: Option<u16>,
+    pub temperature_avg: Option<i8>,
+    pub temperature_max: Option<i8>,
+    pub nec: Option<(f64, f64)>,
+    pub swc: Option<(f64, f64)>,
+    pub start: Option<(f64, f64)>,
+    pub end: Option<(f64, f64)>,
+    pub ascent: Option<f64>,
+    pub descent: Option<f64>,
+    pub distance: Option<f64>,
+    pub duration: Duration,
+    pub start_time: Option<DateTime<Utc>>,
+}
+
+impl Summary {
+    pub fn new(points: &[Point]) -> Self {
+        let cadence = || points.iter().filter_map(|x| x.cadence).map(f64::from);
+        let heartrate = || points.iter().filter_map(|x| x.heartrate).map(f64::from);
+        let power = || points.iter().filter_map(|x| x.power).map(f64::from);
+        let temperature = || points.iter().filter_map(|x| x.temperature).map(f64::from);
+        let lat = || points.iter().filter_map(|x| x.lat);
+        let lon = || points.iter().filter_map(|x| x.lon);
+
+        let start_time = points.iter().find_map(|x| x.timestamp);
+        let end_time = points.iter().rev().find_map(|x| x.timestamp);
+        let duration = start_time
+            .zip(end_time)
+            .and_then(|(start, end)| end.signed_duration_since(start).to_std().ok())
+            .unwrap_or_default();
+
+        let distance = points
+            .iter()
+            .find_map(|x| x.distance)
+            .zip(points.iter().rev().find_map(|x| x.distance))
+            .map(|(first, last)| last - first);
+
+        let speed_avg = distance
+            .filter(|_| !duration.is_zero())
+            .map(|x| x / duration.as_secs_f64());
+
+        let (ascent, descent) = elevation_change(points.iter().filter_map(|x| x.altitude));
+
+        Self {
+            cadence_avg: average(cadence()),
+            cadence_max: maximum(cadence()),
+            heartrate_avg: average(heartrate()).map(|x| x.round() as u8),
+            heartrate_max: maximum(heartrate()).map(|x| x as u8),
+            speed_avg,
+            speed_max: maximum(points.iter().filter_map(|x| x.speed)),
+            power_avg: average(power()).map(|x| x.round() as u16),
+            power_max: maximum(power()).map(|x| x as u16),
+            temperature_avg: average(temperature()).map(|x| x.round() as i8),
+            temperature_max: maximum(temperature()).map(|x| x as i8),
+            nec: maximum(lat()).zip(maximum(lon())),
+            swc: minimum(lat()).zip(minimum(lon())),
+            start: points.iter().find_map(Point::position),
+            end: points.iter().rev().find_map(Point::position),
+            ascent,
+            descent,
+            distance,
+            duration,
+            start_time,
+        }
+    }
+
+    pub fn session(&self, sport: Sport, laps: usize) -> Session {
+        Session {
+            cadence_avg: self
+                .cadence_avg
+                .map(AngularVelocity::new::<revolution_per_minute>),
+            cadence_max: self
+                .cadence_max
+                .map(AngularVelocity::new::<revolution_per_minute>),
+            heartrate_avg: self.heartrate_avg,
+            heartrate_max: self.heartrate_max,
+            speed_avg: self.speed_avg.map(Velocity::new::<meter_per_second>),
+            speed_max: self.speed_max.map(Velocity::new::<meter_per_second>),
+            power_avg: self.power_avg.map(Power::new::<watt>),
+            power_max: self.power_max.map(Power::new::<watt>),
+            temperature_avg: self.temperature_avg,
+            temperature_max: self.temperature_max,
+            nec_lat: self.nec.map(|x| x.0),
+            nec_lon: self.nec.map(|x| x.1),
+            swc_lat: self.swc.map(|x| x.0),
+            swc_lon: self.swc.map(|x| x.1),
+            laps: u16::try_from(laps).ok(),
+            sport,
+            ascent: self
+                .ascent
+                .map(|x| LengthU32::new::<meter>(x.round() as u32)),
+            descent: self
+                .descent
+                .map(|x| LengthU32::new::<meter>(x.round() as u32)),
+            distance: self.distance.map(LengthF64::new::<meter>),
+            duration: self.duration.into(),
+            duration_active: self.duration.into(),
+            start_time: self.start_time.unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    pub fn lap(&self) -> Lap {
+        Lap {
+            cadence_avg: self
+                .cadence_avg
+                .map(AngularVelocity::new::<revolution_per_minute>),
+            cadence_max: self
+                .cadence_max
+                .map(AngularVelocity::new::<revolution_per_minute>),
+            heartrate_avg: self.heartrate_avg,
+            heartrate_max: self.heartrate_max,
+            speed_avg: self.speed_avg.map(Velocity::new::<meter_per_second>),
+            speed_max: self.speed_max.map(Velocity::new::<meter_per_second>),
+            power_avg: self.power_avg.map(Power::new::<watt>),
+            power_max: self.power_max.map(Power::new::<watt>),
+            temperature_avg: self.temperature_avg,
+            temperature_max: self.temperature_max,
+            lat_start: self.start.map(|x| x.0),
+            lon_start: self.start.map(|x| x.1),
+            lat_end: self.end.map(|x| x.0),
+            lon_end: self.end.map(|x| x.1),
+            ascent: self
+                .ascent
+                .map(|x| LengthU32::new::<meter>(x.round() as u32)),
+            descent: self
+                .descent
+                .map(|x| LengthU32::new::<meter>(x.round() as u32)),
+            distance: self.distance.map(LengthF64::new::<meter>),
+            duration: self.duration.into(),
+            duration_active: self.duration.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Sums positive and negative altitude changes, ignoring changes smaller than
+/// `ELEVATION_THRESHOLD` relative to the last counted altitude.
+pub(crate) fn elevation_change<I: Iterator<Item = f64>>(
+    mut altitude: I,
+) -> (Option<f64>, Option<f64>) {
+    let mut reference = match altitude.next() {
+        Some(x) => x,
+        None => return (None, None),
+    };
+
+    let (mut ascent, mut descent) = (0_f64, 0_f64);
+
+    for current in altitude {
+        let delta = current - reference;
+
+        if delta >= ELEVATION_THRESHOLD {
+            ascent += delta;
+            reference = current;
+        } else if delta <= -ELEVATION_THRESHOLD {
+            descent -= delta;
+            reference = current;
+        }
+    }
+
+    (Some(ascent), Some(descent))
+}
+
+/// Turns lap boundaries, each being the index of the first point of a lap,
+/// into ranges over the points.
+pub(crate) fn lap_ranges(boundaries: &[usize], len: usize) -> Vec<Range<usize>> {
+    boundaries
+        .iter()
+        .zip(boundaries.iter().skip(1).chain(std::iter::once(&len)))
+        .map(|(&start, &end)| start..end)
+        .collect()
+}
+
+pub(crate) fn parse_time(text: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(text)
+        .ok()
+        .map(|x| x.with_timezone(&Utc))
+}
+
+/// Maps the free-form sport names used by GPX and TCX exports to a `Sport`,
+/// warning about names that are not known.
+pub(crate) fn sport(value: &str, report: &mut Report) -> Sport {
+    let name = value.trim().to_lowercase().replace([' ', '-'], "_");
+
+    let sport = match name.as_str() {
+        "run" | "trail_run" | "treadmill" => Sport::Running,
+        "ride" | "biking" | "bike" | "virtual_ride" | "mountain_bike_ride" | "road_biking" => {
+            Sport::Cycling
+        }
+        "swim" | "open_water_swimming" | "lap_swimming" => Sport::Swimming,
+        "walk" => Sport::Walking,
+        "hike" => Sport::Hiking,
+        "other" => Sport::Generic,
+        name => Sport::from_str(name).unwrap_or(Sport::Unknown),
+    };
+
+    if sport == Sport::Unknown {
+        report.warnings.push(Warning::UnknownSport {
+            value: value.to_string(),
+        });
+    }
+
+    sport
+}
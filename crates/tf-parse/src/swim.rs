@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use tf_models::{
+    activity::{Stroke, Swim, SwimInterval, SwimLength},
+    types::LengthF64,
+};
+use uom::si::length::meter;
+
+pub(crate) fn stroke(name: &str) -> Option<Stroke> {
+    match name {
+        "freestyle" => Some(Stroke::Freestyle),
+        "backstroke" => Some(Stroke::Backstroke),
+        "breaststroke" => Some(Stroke::Breaststroke),
+        "butterfly" => Some(Stroke::Butterfly),
+        "drill" => Some(Stroke::Drill),
+        "mixed" => Some(Stroke::Mixed),
+        "im" => Some(Stroke::IndividualMedley),
+        _ => None,
+    }
+}
+
+/// Builds the swim of an activity, if it has any pool lengths. The pool
+/// length is in meters.
+pub(crate) fn swim(pool_length: Option<f64>, lengths: Vec<SwimLength>) -> Option<Swim> {
+    if lengths.is_empty() {
+        return None;
+    }
+
+    Some(Swim {
+        pool_length: pool_length.map(LengthF64::new::<meter>),
+        intervals: intervals(&lengths, pool_length),
+        lengths,
+    })
+}
+
+/// Groups consecutive lengths into alternating swim and rest intervals.
+fn intervals(lengths: &[SwimLength], pool_length: Option<f64>) -> Vec<SwimInterval> {
+    let mut intervals = Vec::new();
+    let mut start = 0;
+
+    for end in 1..=lengths.len() {
+        if end == lengths.len() || lengths[end].active != lengths[start].active {
+            intervals.push(interval(&lengths[start..end], pool_length));
+            start = end;
+        }
+    }
+
+    intervals
+}
+
+fn interval(lengths: &[SwimLength], pool_length: Option<f64>) -> SwimInterval {
+    let active = lengths[0].active;
+    let count = lengths.len();
+
+    let duration = lengths.iter().map(|x| x.duration.as_secs_f64()).sum();
+
+    let mut stroke = lengths.iter().filter_map(|x| x.stroke);
+    let first = stroke.next();
+
+    let strokes = lengths.iter().filter_map(|x| x.strokes).collect::<Vec<_>>();
+    let swolf = lengths
+        .iter()
+        .filter_map(|x| x.swolf)
+        .map(f64::from)
+        .collect::<Vec<_>>();
+
+    SwimInterval {
+        start_time: lengths[0].start_time,
+        duration: Duration::from_secs_f64(duration).into(),
+        active,
+        lengths: u16::try_from(count).unwrap_or(u16::MAX),
+        distance: pool_length
+            .filter(|_| active)
+            .map(|x| LengthF64::new::<meter>(x * count as f64)),
+        stroke: first.map(|first| {
+            if stroke.all(|x| x == first) {
+                first
+            } else {
+                Stroke::Mixed
+            }
+        }),
+        strokes: (!strokes.is_empty()).then(|| strokes.iter().sum()),
+        swolf_avg: (!swolf.is_empty()).then(|| swolf.iter().sum::<f64>() / swolf.len() as f64),
+    }
+}
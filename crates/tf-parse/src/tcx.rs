@@ -0,0 +1,218 @@
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::io::BufRead;
+use std::time::Duration;
+
+use crate::{
+    error::{Error, Result},
+    pause,
+    summary::{self, Point, Summary},
+    Report,
+};
+
+use tf_models::{
+    activity::Lap,
+    types::{AngularVelocity, Energy, LengthF64, Power, Velocity},
+    Activity, Sport,
+};
+
+use uom::si::{
+    angular_velocity::revolution_per_minute, energy::kilocalorie, length::meter, power::watt,
+    velocity::meter_per_second,
+};
+
+/// Totals reported by the device in a `<Lap>` element, which take precedence
+/// over the values derived from the trackpoints.
+#[derive(Default)]
+struct LapTotals {
+    duration_active: Option<f64>,
+    distance: Option<f64>,
+    calories: Option<u32>,
+    heartrate_avg: Option<u8>,
+    heartrate_max: Option<u8>,
+    speed_avg: Option<f64>,
+    speed_max: Option<f64>,
+    cadence_avg: Option<u8>,
+    cadence_max: Option<u8>,
+    power_avg: Option<u16>,
+    power_max: Option<u16>,
+}
+
+impl LapTotals {
+    fn apply(&self, lap: &mut Lap) {
+        if let Some(x) = self.duration_active {
+            lap.duration_active = Duration::from_secs_f64(x).into();
+        }
+        if let Some(x) = self.distance {
+            lap.distance = Some(LengthF64::new::<meter>(x));
+        }
+        if let Some(x) = self.calories {
+            lap.calories = Some(Energy::new::<kilocalorie>(x));
+        }
+        if let Some(x) = self.speed_avg {
+            lap.speed_avg = Some(Velocity::new::<meter_per_second>(x));
+        }
+        if let Some(x) = self.speed_max {
+            lap.speed_max = Some(Velocity::new::<meter_per_second>(x));
+        }
+        if let Some(x) = self.cadence_avg {
+            lap.cadence_avg = Some(AngularVelocity::new::<revolution_per_minute>(x.into()));
+        }
+        if let Some(x) = self.cadence_max {
+            lap.cadence_max = Some(AngularVelocity::new::<revolution_per_minute>(x.into()));
+        }
+        if let Some(x) = self.power_avg {
+            lap.power_avg = Some(Power::new::<watt>(x));
+        }
+        if let Some(x) = self.power_max {
+            lap.power_max = Some(Power::new::<watt>(x));
+        }
+        lap.heartrate_avg = self.heartrate_avg.or(lap.heartrate_avg);
+        lap.heartrate_max = self.heartrate_max.or(lap.heartrate_max);
+    }
+}
+
+pub fn parse<R: BufRead>(tcx_data: R, report: &mut Report) -> Result<Activity> {
+    let mut reader = Reader::from_reader(tcx_data);
+    reader.trim_text(true);
+    let mut buffer = Vec::new();
+
+    let mut points: Vec<Point> = Vec::new();
+    let mut laps: Vec<(usize, LapTotals)> = Vec::new();
+    let mut sport = None;
+
+    let mut point: Option<Point> = None;
+    let mut path: Vec<Vec<u8>> = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buffer)? {
+            Event::Start(element) => {
+                match element.local_name().as_ref() {
+                    b"Activity" if sport.is_none() => {
+                        sport = element
+                            .try_get_attribute("Sport")
+                            .ok()
+                            .flatten()
+                            .and_then(|x| Some(summary::sport(&x.unescape_value().ok()?, report)));
+                    }
+                    b"Lap" => laps.push((points.len(), LapTotals::default())),
+                    b"Trackpoint" => point = Some(Point::default()),
+                    _ => (),
+                }
+                path.push(element.local_name().as_ref().to_vec());
+            }
+            Event::End(element) => {
+                if element.local_name().as_ref() == b"Trackpoint" {
+                    points.extend(point.take());
+                }
+                path.pop();
+            }
+            Event::Text(text) => {
+                let text = text.unescape()?;
+                let mut ancestors = path.iter().rev().map(Vec::as_slice);
+                let element = ancestors.next();
+                let parent = ancestors.next();
+                let in_lap = path.iter().any(|x| x.as_slice() == b"Lap");
+
+                if let Some(point) = point.as_mut() {
+                    match (element, parent) {
+                        (Some(b"Time"), _) => point.timestamp = summary::parse_time(&text),
+                        (Some(b"LatitudeDegrees"), _) => point.lat = text.parse().ok(),
+                        (Some(b"LongitudeDegrees"), _) => point.lon = text.parse().ok(),
+                        (Some(b"AltitudeMeters"), _) => point.altitude = text.parse().ok(),
+                        (Some(b"DistanceMeters"), _) => point.distance = text.parse().ok(),
+                        (Some(b"Value"), Some(b"HeartRateBpm")) => {
+                            point.heartrate = text.parse().ok()
+                        }
+                        (Some(b"Cadence" | b"RunCadence"), _) => point.cadence = text.parse().ok(),
+                        (Some(b"Speed"), _) => point.speed = text.parse().ok(),
+                        (Some(b"Watts"), _) => point.power = text.parse().ok(),
+                        _ => (),
+                    }
+                } else if let Some((_, totals)) = laps.last_mut().filter(|_| in_lap) {
+                    match (element, parent) {
+                        (Some(b"TotalTimeSeconds"), _) => {
+                            totals.duration_active = text.parse().ok()
+                        }
+                        (Some(b"DistanceMeters"), _) => totals.distance = text.parse().ok(),
+                        (Some(b"Calories"), _) => totals.calories = text.parse().ok(),
+                        (Some(b"Value"), Some(b"AverageHeartRateBpm")) => {
+                            totals.heartrate_avg = text.parse().ok()
+                        }
+                        (Some(b"Value"), Some(b"MaximumHeartRateBpm")) => {
+                            totals.heartrate_max = text.parse().ok()
+                        }
+                        (Some(b"AvgSpeed"), _) => totals.speed_avg = text.parse().ok(),
+                        (Some(b"MaximumSpeed"), _) => totals.speed_max = text.parse().ok(),
+                        (Some(b"Cadence" | b"AvgRunCadence"), _) => {
+                            totals.cadence_avg = text.parse().ok()
+                        }
+                        (Some(b"MaxBikeCadence" | b"MaxRunCadence"), _) => {
+                            totals.cadence_max = text.parse().ok()
+                        }
+                        (Some(b"AvgWatts"), _) => totals.power_avg = text.parse().ok(),
+                        (Some(b"MaxWatts"), _) => totals.power_max = text.parse().ok(),
+                        _ => (),
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+
+        buffer.clear();
+    }
+
+    if !points.iter().any(|x| x.timestamp.is_some()) {
+        return Err(Error::MissingData);
+    }
+
+    summary::fill_derived(&mut points);
+
+    let boundaries = laps.iter().map(|(start, _)| *start).collect::<Vec<_>>();
+    let lap = summary::lap_ranges(&boundaries, points.len())
+        .into_iter()
+        .zip(laps.iter().map(|(_, totals)| totals))
+        .map(|(range, totals)| {
+            let mut lap = Summary::new(&points[range]).lap();
+            totals.apply(&mut lap);
+            lap
+        })
+        .collect::<Vec<_>>();
+
+    let mut session = Summary::new(&points).session(sport.unwrap_or(Sport::Generic), lap.len());
+
+    let record = summary::record(&points);
+    let pauses = pause::detect(&record);
+
+    if laps.iter().all(|(_, x)| x.duration_active.is_some()) && !laps.is_empty() {
+        let duration_active = laps.iter().filter_map(|(_, x)| x.duration_active).sum();
+        session.duration_active = Duration::from_secs_f64(duration_active).into();
+    } else {
+        session.duration_active = pause::active_duration(&session, &pauses).into();
+    }
+
+    if laps.iter().any(|(_, x)| x.calories.is_some()) {
+        let calories = laps.iter().filter_map(|(_, x)| x.calories).sum();
+        session.calories = Some(Energy::new::<kilocalorie>(calories));
+    }
+
+    session.heartrate_max = laps
+        .iter()
+        .filter_map(|(_, x)| x.heartrate_max)
+        .max()
+        .or(session.heartrate_max);
+
+    Ok(Activity {
+        id: crate::activity_id(&session)?,
+        session,
+        record,
+        lap,
+        file_id: None,
+        children: Vec::new(),
+        record_range: None,
+        swim: None,
+        devices: Vec::new(),
+        pauses,
+    })
+}
@@ -0,0 +1,54 @@
+use std::{str::FromStr, sync::OnceLock};
+
+use chrono::{offset::Utc, DateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+use tf_models::Activity;
+use tzf_rs::DefaultFinder;
+
+use crate::error::Result;
+
+// Loading the timezone boundaries takes a while, so it is done once
+static FINDER: OnceLock<DefaultFinder> = OnceLock::new();
+
+/// Seconds east of UTC of the local time at a position at the given time,
+/// looked up in the timezone boundaries bundled with `tzf-rs`.
+fn utc_offset(lat: f64, lon: f64, time: DateTime<Utc>) -> Option<i32> {
+    let finder = FINDER.get_or_init(DefaultFinder::new);
+    let timezone = Tz::from_str(finder.get_tz_name(lon, lat)).ok()?;
+
+    Some(
+        timezone
+            .offset_from_utc_datetime(&time.naive_utc())
+            .fix()
+            .local_minus_utc(),
+    )
+}
+
+/// Fills in the UTC offset from the first GPS fix if the file did not have
+/// it, falling back to the offset of the parent for multisport children.
+/// The id is made again from the local start time.
+pub(crate) fn localize(activity: &mut Activity, fallback: Option<i32>) -> Result<()> {
+    let session = &mut activity.session;
+
+    if session.utc_offset.is_none() {
+        let record = &activity.record;
+
+        session.utc_offset = record
+            .lat
+            .iter()
+            .zip(&record.lon)
+            .find_map(|(lat, lon)| lat.zip(*lon))
+            .and_then(|(lat, lon)| utc_offset(lat, lon, session.start_time))
+            .or(fallback);
+    }
+
+    activity.id = crate::activity_id(&activity.session)?;
+
+    let utc_offset = activity.session.utc_offset;
+
+    for child in &mut activity.children {
+        localize(child, utc_offset)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use std::ops::Range;
+
+use tf_models::{
+    activity::{Histogram, Histograms, Record},
+    Activity,
+};
+use uom::si::power::watt;
+
+use crate::{elevation::lap_ranges, power::MAX_GAP};
+
+/// Time at each heart rate and power of an activity and its laps, for time
+/// in zones. `None` without heart rate or power.
+pub fn histograms(activity: &Activity) -> Option<Histograms> {
+    let record = &activity.record;
+
+    if record.heartrate.iter().all(Option::is_none) && record.power.iter().all(Option::is_none) {
+        return None;
+    }
+
+    Some(Histograms {
+        activity: histogram(record, 0..record.duration.len()),
+        lap: lap_ranges(&activity.lap, record)
+            .into_iter()
+            .map(|range| histogram(record, range))
+            .collect(),
+    })
+}
+
+/// The time from each record in the range to the next, counted at the heart
+/// rate and power of the record. Gaps longer than `MAX_GAP` count as that.
+fn histogram(record: &Record, range: Range<usize>) -> Histogram {
+    let mut histogram = Histogram::default();
+
+    for i in range {
+        let seconds = match record.duration.get(i + 1) {
+            Some(next) => {
+                (next.as_secs_f64() - record.duration[i].as_secs_f64()).clamp(0., MAX_GAP)
+            }
+            None => continue,
+        };
+
+        if let Some(Some(heartrate)) = record.heartrate.get(i) {
+            add(&mut histogram.heartrate, usize::from(*heartrate), seconds);
+        }
+
+        if let Some(Some(power)) = record.power.get(i) {
+            add(
+                &mut histogram.power,
+                usize::from(power.get::<watt>()),
+                seconds,
+            );
+        }
+    }
+
+    histogram
+}
+
+fn add(values: &mut Vec<f64>, index: usize, seconds: f64) {
+    if values.len() <= index {
+        values.resize(index + 1, 0.);
+    }
+
+    values[index] += seconds;
+}
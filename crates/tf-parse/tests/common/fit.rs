@@ -0,0 +1,304 @@
+//! FIT files for tests, written message by message.
+
+use std::io::Read;
+
+// 2022-06-01T06:00:00Z in seconds from the FIT epoch
+pub const START: u32 = 1_022_997_600;
+
+pub mod mesg_num {
+    pub const FILE_ID: u16 = 0;
+    pub const SESSION: u16 = 18;
+    pub const LAP: u16 = 19;
+    pub const RECORD: u16 = 20;
+    pub const DEVICE_INFO: u16 = 23;
+    pub const LENGTH: u16 = 101;
+    pub const FIELD_DESCRIPTION: u16 = 206;
+    pub const DEVELOPER_DATA_ID: u16 = 207;
+}
+
+/// A field of a message, with its FIT base type.
+#[derive(Clone, Copy)]
+pub enum Field {
+    Enum(u8),
+    SInt8(i8),
+    UInt8(u8),
+    UInt16(u16),
+    SInt32(i32),
+    UInt32(u32),
+    String(&'static str),
+}
+
+impl Field {
+    pub fn base_type(&self) -> u8 {
+        match self {
+            Self::Enum(_) => 0x00,
+            Self::SInt8(_) => 0x01,
+            Self::UInt8(_) => 0x02,
+            Self::String(_) => 0x07,
+            Self::UInt16(_) => 0x84,
+            Self::SInt32(_) => 0x85,
+            Self::UInt32(_) => 0x86,
+        }
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        match *self {
+            Self::Enum(x) | Self::UInt8(x) => vec![x],
+            Self::SInt8(x) => x.to_le_bytes().to_vec(),
+            Self::UInt16(x) => x.to_le_bytes().to_vec(),
+            Self::SInt32(x) => x.to_le_bytes().to_vec(),
+            Self::UInt32(x) => x.to_le_bytes().to_vec(),
+            Self::String(x) => [x.as_bytes(), &[0]].concat(),
+        }
+    }
+}
+
+/// Writes FIT files with the messages and fields given, for messages the
+/// exporter does not write. Every message is written with a definition of
+/// its own.
+#[derive(Default)]
+pub struct FitWriter {
+    buffer: Vec<u8>,
+}
+
+impl FitWriter {
+    pub fn message(&mut self, global: u16, fields: &[(u8, Field)]) -> &mut Self {
+        self.developer_message(global, fields, &[])
+    }
+
+    /// Writes a message with developer fields, given by field number,
+    /// developer data index and value.
+    pub fn developer_message(
+        &mut self,
+        global: u16,
+        fields: &[(u8, Field)],
+        developer_fields: &[(u8, u8, Field)],
+    ) -> &mut Self {
+        self.buffer.push(if developer_fields.is_empty() {
+            0x40
+        } else {
+            0x60
+        });
+        // Reserved byte and little endian architecture
+        self.buffer.extend([0, 0]);
+        self.buffer.extend(global.to_le_bytes());
+        self.buffer.push(fields.len() as u8);
+
+        for (number, field) in fields {
+            self.buffer
+                .extend([*number, field.bytes().len() as u8, field.base_type()]);
+        }
+
+        if !developer_fields.is_empty() {
+            self.buffer.push(developer_fields.len() as u8);
+
+            for (number, index, field) in developer_fields {
+                self.buffer
+                    .extend([*number, field.bytes().len() as u8, *index]);
+            }
+        }
+
+        self.buffer.push(0);
+
+        for (_, field) in fields {
+            self.buffer.extend(field.bytes());
+        }
+
+        for (_, _, field) in developer_fields {
+            self.buffer.extend(field.bytes());
+        }
+
+        self
+    }
+
+    /// Takes the messages written so far, for files generated in parts.
+    pub fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buffer)
+    }
+
+    pub fn finish(&self) -> Vec<u8> {
+        let mut output = header(self.buffer.len());
+
+        output.extend(&self.buffer);
+        output.extend(crc(0, &output).to_le_bytes());
+
+        output
+    }
+}
+
+/// The header of a file with `data_size` bytes of messages.
+pub fn header(data_size: usize) -> Vec<u8> {
+    let mut header = vec![14, 0x20];
+    header.extend(2132_u16.to_le_bytes());
+    header.extend((data_size as u32).to_le_bytes());
+    header.extend(b".FIT");
+    header.extend(crc(0, &header).to_le_bytes());
+
+    header
+}
+
+/// Continues the checksum `crc` over `data`.
+pub fn crc(crc: u16, data: &[u8]) -> u16 {
+    const TABLE: [u16; 16] = [
+        0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401, 0xA001, 0x6C00, 0x7800,
+        0xB401, 0x5000, 0x9C01, 0x8801, 0x4400,
+    ];
+
+    data.iter().fold(crc, |crc, byte| {
+        let crc = (crc >> 4) ^ TABLE[usize::from(crc & 0xF)] ^ TABLE[usize::from(byte & 0xF)];
+        (crc >> 4) ^ TABLE[usize::from(crc & 0xF)] ^ TABLE[usize::from(byte >> 4)]
+    })
+}
+
+/// Seconds after `START` as a FIT timestamp.
+pub fn time(seconds: u32) -> Field {
+    Field::UInt32(START + seconds)
+}
+
+// Text of the notes in each record of a `Ride`
+static NOTE: [u8; 254] = [b'x'; 254];
+const NOTES: u8 = 15;
+
+/// A FIT file of a ride with a record every second, generated as it is read
+/// so that tests do not hold the file in memory. Next to the usual values,
+/// each record has a temperature and a number of notes from an app as
+/// developer fields, and the notes make up most of the file.
+pub struct Ride {
+    seconds: u32,
+    next: u32,
+    session: Option<Vec<u8>>,
+    pending: Vec<u8>,
+    position: usize,
+    crc: Option<u16>,
+    size: u64,
+}
+
+impl Ride {
+    pub fn new(seconds: u32) -> Self {
+        let mut fit = FitWriter::default();
+
+        fit.message(mesg_num::FILE_ID, &[(0, Field::Enum(4)), (4, time(0))]);
+        fit.message(
+            mesg_num::DEVELOPER_DATA_ID,
+            &[(3, Field::UInt8(0)), (4, Field::UInt32(1))],
+        );
+        fit.message(
+            mesg_num::FIELD_DESCRIPTION,
+            &[
+                (0, Field::UInt8(0)),
+                (1, Field::UInt8(0)),
+                (2, Field::UInt8(0x84)),
+                (3, Field::String("Core Temperature")),
+                (8, Field::String("C")),
+            ],
+        );
+        for number in 1..=NOTES {
+            fit.message(
+                mesg_num::FIELD_DESCRIPTION,
+                &[
+                    (0, Field::UInt8(0)),
+                    (1, Field::UInt8(number)),
+                    (2, Field::UInt8(0x07)),
+                    (3, Field::String("Note")),
+                ],
+            );
+        }
+        let definitions = fit.take();
+
+        let record = Self::records(0..1).len();
+
+        fit.message(
+            mesg_num::SESSION,
+            &[(253, time(seconds)), (2, time(0)), (5, Field::Enum(2))],
+        );
+        let session = fit.take();
+
+        let data_size = definitions.len() + record * seconds as usize + session.len();
+        let mut pending = header(data_size);
+        pending.extend(definitions);
+
+        Self {
+            seconds,
+            next: 0,
+            session: Some(session),
+            crc: Some(crc(0, &pending)),
+            pending,
+            position: 0,
+            size: (data_size + 16) as u64,
+        }
+    }
+
+    /// Size of the file in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn records(seconds: std::ops::Range<u32>) -> Vec<u8> {
+        let mut fit = FitWriter::default();
+        let note = Field::String(std::str::from_utf8(&NOTE).unwrap());
+
+        for second in seconds {
+            // Heading north at 8 m/s
+            let lat = (59.9 + f64::from(second) * 8. / 111_000.) * f64::from(1 << 30) / 90.;
+            let developer_fields =
+                std::iter::once((0, 0, Field::UInt16(3700 + (second % 50) as u16)))
+                    .chain((1..=NOTES).map(|number| (number, 0, note)))
+                    .collect::<Vec<_>>();
+
+            fit.developer_message(
+                mesg_num::RECORD,
+                &[
+                    (253, time(second)),
+                    (0, Field::SInt32(lat as i32)),
+                    (1, Field::SInt32(127_652_000)),
+                    (2, Field::UInt16(3000)),
+                    (3, Field::UInt8(140 + (second % 20) as u8)),
+                    (4, Field::UInt8(90)),
+                    (5, Field::UInt32(second * 800)),
+                    (6, Field::UInt16(8000)),
+                    (7, Field::UInt16(200 + (second % 100) as u16)),
+                ],
+                &developer_fields,
+            );
+        }
+
+        fit.take()
+    }
+
+    /// Generates the next part of the file.
+    fn generate(&mut self) {
+        self.position = 0;
+
+        if self.next < self.seconds {
+            let end = self.seconds.min(self.next + 256);
+            self.pending = Self::records(self.next..end);
+            self.next = end;
+        } else if let Some(session) = self.session.take() {
+            self.pending = session;
+        } else if let Some(checksum) = self.crc.take() {
+            self.pending = checksum.to_le_bytes().to_vec();
+            return;
+        } else {
+            self.pending.clear();
+            return;
+        }
+
+        if let Some(checksum) = self.crc.as_mut() {
+            *checksum = crc(*checksum, &self.pending);
+        }
+    }
+}
+
+impl Read for Ride {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position == self.pending.len() {
+            self.generate();
+        }
+
+        let len = buf.len().min(self.pending.len() - self.position);
+        buf[..len].copy_from_slice(&self.pending[self.position..self.position + len]);
+        self.position += len;
+
+        Ok(len)
+    }
+}
@@ -0,0 +1,154 @@
+//! Activity files for tests, written from a list of points.
+#![allow(dead_code)]
+
+pub mod fit;
+
+/// A point of an activity, `seconds` after 2022-06-01 06:00 UTC.
+#[derive(Clone, Copy, Default)]
+pub struct Point {
+    pub seconds: u32,
+    pub position: Option<(f64, f64)>,
+    pub altitude: Option<f64>,
+    pub distance: Option<f64>,
+    pub heartrate: Option<u8>,
+    pub speed: Option<f64>,
+    pub power: Option<u16>,
+}
+
+impl Point {
+    pub fn at(seconds: u32) -> Self {
+        Self {
+            seconds,
+            ..Default::default()
+        }
+    }
+
+    /// A point moving north by about ten meters a second from 59.9 N 10.7 E.
+    pub fn moving(seconds: u32) -> Self {
+        Self {
+            position: Some((59.9 + f64::from(seconds) * 0.0001, 10.7)),
+            ..Self::at(seconds)
+        }
+    }
+}
+
+fn time(seconds: u32) -> String {
+    format!(
+        "2022-06-01T{:02}:{:02}:{:02}Z",
+        6 + seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// A GPX file of a single track.
+pub fn gpx(sport: &str, points: &[Point]) -> String {
+    let trackpoints = points
+        .iter()
+        .map(|point| {
+            let mut trkpt = match point.position {
+                Some((lat, lon)) => format!(r#"<trkpt lat="{lat}" lon="{lon}">"#),
+                None => "<trkpt>".to_string(),
+            };
+
+            if let Some(altitude) = point.altitude {
+                trkpt += &format!("<ele>{altitude}</ele>");
+            }
+
+            trkpt += &format!("<time>{}</time>", time(point.seconds));
+
+            if point.power.is_some() || point.heartrate.is_some() {
+                trkpt += "<extensions>";
+                if let Some(power) = point.power {
+                    trkpt += &format!("<power>{power}</power>");
+                }
+                if let Some(heartrate) = point.heartrate {
+                    trkpt += &format!(
+                        "<gpxtpx:TrackPointExtension><gpxtpx:hr>{heartrate}</gpxtpx:hr></gpxtpx:TrackPointExtension>"
+                    );
+                }
+                trkpt += "</extensions>";
+            }
+
+            trkpt + "</trkpt>"
+        })
+        .collect::<String>();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1"
+  xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v1">
+  <trk><type>{sport}</type><trkseg>{trackpoints}</trkseg></trk>
+</gpx>"#
+    )
+}
+
+/// A TCX file of a single activity with a lap for each list of points. Each
+/// lap lasts until the next starts, and the last until its last point.
+pub fn tcx(sport: &str, laps: &[Vec<Point>]) -> String {
+    let start = |lap: &[Point]| lap.first().map_or(0, |x| x.seconds);
+
+    let laps = laps
+        .iter()
+        .enumerate()
+        .map(|(i, lap)| {
+            let end = match laps.get(i + 1) {
+                Some(next) => start(next),
+                None => lap.last().map_or(0, |x| x.seconds),
+            };
+
+            format!(
+                r#"<Lap StartTime="{}"><TotalTimeSeconds>{}</TotalTimeSeconds><Track>{}</Track></Lap>"#,
+                time(start(lap)),
+                end - start(lap),
+                lap.iter().map(trackpoint).collect::<String>()
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<TrainingCenterDatabase xmlns="http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2"
+  xmlns:ns3="http://www.garmin.com/xmlschemas/ActivityExtension/v2">
+  <Activities>
+    <Activity Sport="{sport}">
+      <Id>{}</Id>
+      {laps}
+    </Activity>
+  </Activities>
+</TrainingCenterDatabase>"#,
+        time(0)
+    )
+}
+
+fn trackpoint(point: &Point) -> String {
+    let mut trackpoint = format!("<Trackpoint><Time>{}</Time>", time(point.seconds));
+
+    if let Some((lat, lon)) = point.position {
+        trackpoint += &format!(
+            "<Position><LatitudeDegrees>{lat}</LatitudeDegrees><LongitudeDegrees>{lon}</LongitudeDegrees></Position>"
+        );
+    }
+    if let Some(altitude) = point.altitude {
+        trackpoint += &format!("<AltitudeMeters>{altitude}</AltitudeMeters>");
+    }
+    if let Some(distance) = point.distance {
+        trackpoint += &format!("<DistanceMeters>{distance}</DistanceMeters>");
+    }
+    if let Some(heartrate) = point.heartrate {
+        trackpoint += &format!("<HeartRateBpm><Value>{heartrate}</Value></HeartRateBpm>");
+    }
+
+    if point.speed.is_some() || point.power.is_some() {
+        trackpoint += "<Extensions><ns3:TPX>";
+        if let Some(speed) = point.speed {
+            trackpoint += &format!("<ns3:Speed>{speed}</ns3:Speed>");
+        }
+        if let Some(power) = point.power {
+            trackpoint += &format!("<ns3:Watts>{power}</ns3:Watts>");
+        }
+        trackpoint += "</ns3:TPX></Extensions>";
+    }
+
+    trackpoint + "</Trackpoint>"
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use chrono::{TimeZone, Utc};
+use common::Point;
+use tf_models::{
+    activity::BestEffort,
+    user::{PersonalRecord, PersonalRecords},
+    ActivityId,
+};
+use tf_parse::effort::best_efforts;
+use uom::si::length::meter;
+
+/// A run with a point every ten seconds, at 3 m/s apart from two minutes at
+/// 5 m/s after ten minutes.
+fn tcx(sport: &str) -> String {
+    let mut distance = 0.;
+    let points = (0..=132)
+        .map(|i| {
+            let seconds = i * 10;
+            if i > 0 {
+                distance += if seconds > 600 && seconds <= 720 {
+                    50.
+                } else {
+                    30.
+                };
+            }
+
+            Point {
+                distance: Some(distance),
+                ..Point::at(seconds)
+            }
+        })
+        .collect();
+
+    common::tcx(sport, &[points])
+}
+
+fn effort(distance: f64, seconds: f64) -> BestEffort {
+    BestEffort {
+        distance: tf_models::types::LengthF64::new::<meter>(distance),
+        duration: std::time::Duration::from_secs_f64(seconds).into(),
+        start: std::time::Duration::ZERO.into(),
+    }
+}
+
+fn durations(records: &PersonalRecords) -> Vec<(&str, f64)> {
+    records
+        .history
+        .iter()
+        .map(|x: &PersonalRecord| (x.activity.as_str(), x.effort.duration.as_secs_f64()))
+        .collect()
+}
+
+#[test]
+fn find_best_efforts() {
+    let activity = tf_parse::parse(tcx("Running").as_bytes()).unwrap();
+    let efforts = best_efforts(&activity);
+
+    // 4.2 km in total, too short for 5 km
+    assert_eq!(efforts.len(), 3);
+
+    assert_eq!(efforts[0].distance.get::<meter>(), 400.);
+    let seconds = efforts[0].duration.as_secs_f64();
+    assert!((seconds - 80.).abs() < 1e-6, "{seconds}");
+    let start = efforts[0].start.as_secs_f64();
+    assert!((600. ..=640.).contains(&start), "{start}");
+
+    // The fast two minutes and 400 meters at 3 m/s
+    let seconds = efforts[1].duration.as_secs_f64();
+    assert!((seconds - (120. + 400. / 3.)).abs() < 1e-3, "{seconds}");
+
+    let seconds = efforts[2].duration.as_secs_f64();
+    assert!((seconds - (120. + 1009.344 / 3.)).abs() < 1e-3, "{seconds}");
+}
+
+#[test]
+fn skip_other_sports() {
+    let activity = tf_parse::parse(tcx("Biking").as_bytes()).unwrap();
+
+    assert!(best_efforts(&activity).is_empty());
+}
+
+#[test]
+fn keep_personal_record_history() {
+    let day = |x| Utc.with_ymd_and_hms(2022, 6, x, 6, 0, 0).unwrap();
+    let first = "2022060106000000".parse::<ActivityId>().unwrap();
+    let second = "2022060206000000".parse::<ActivityId>().unwrap();
+    let third = "2022060306000000".parse::<ActivityId>().unwrap();
+
+    let mut records = PersonalRecords::default();
+    records.insert(third, day(3), &[effort(400., 85.)]);
+    records.insert(first, day(1), &[effort(400., 90.)]);
+    records.insert(second, day(2), &[effort(400., 80.), effort(1000., 240.)]);
+
+    // The third run was not a record after all, as the second was faster
+    assert_eq!(
+        durations(&records),
+        [
+            ("2022060106000000", 90.),
+            ("2022060206000000", 80.),
+            ("2022060206000000", 240.)
+        ]
+    );
+
+    assert!(records.remove(second));
+    assert!(!records.remove(second));
+    assert_eq!(durations(&records), [("2022060106000000", 90.)]);
+}
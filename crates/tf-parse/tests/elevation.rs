@@ -0,0 +1,81 @@
+mod common;
+
+use common::Point;
+use tf_parse::elevation::{correct, Dem};
+use uom::si::length::meter;
+
+/// A tile of three by three samples, half a degree apart, rising from 0
+/// meters in the south to 200 meters in the north.
+fn dem() -> (tempfile::TempDir, Dem) {
+    let dir = tempfile::tempdir().unwrap();
+
+    let samples = [200_i16, 200, 200, 100, 100, 100, 0, 0, 0]
+        .iter()
+        .flat_map(|x| x.to_be_bytes())
+        .collect::<Vec<_>>();
+    std::fs::write(dir.path().join("N59E010.hgt"), samples).unwrap();
+    std::fs::write(dir.path().join("README.txt"), "not a tile").unwrap();
+
+    let dem = Dem::open(dir.path()).unwrap();
+
+    (dir, dem)
+}
+
+/// A run going north with the altitude jumping up and down, like GPS
+/// altitude without a barometer.
+fn gpx() -> String {
+    let points = (0..=100)
+        .map(|i| Point {
+            position: Some((59.1 + f64::from(i) * 0.001, 10.5)),
+            altitude: Some(if i % 2 == 0 { 10. } else { 30. }),
+            ..Point::at(i)
+        })
+        .collect::<Vec<_>>();
+
+    common::gpx("running", &points)
+}
+
+#[test]
+fn look_up_altitude() {
+    let (_dir, dem) = dem();
+
+    let altitude = dem.altitude(59.1, 10.5).unwrap();
+    assert!((altitude - 20.).abs() < 1e-6, "{altitude}");
+
+    let altitude = dem.altitude(59.75, 10.25).unwrap();
+    assert!((altitude - 150.).abs() < 1e-6, "{altitude}");
+
+    assert!(dem.altitude(58.5, 10.5).is_none());
+    assert!(dem.altitude(59.5, 11.5).is_none());
+}
+
+#[test]
+fn correct_elevation() {
+    let (_dir, dem) = dem();
+    let activity = tf_parse::parse(gpx().as_bytes()).unwrap();
+
+    let recorded = activity.session.ascent.unwrap().get::<meter>();
+    assert!(recorded > 500, "{recorded}");
+
+    let elevation = correct(&activity, &dem).unwrap();
+    let ascent = elevation.ascent.unwrap().get::<meter>();
+    let descent = elevation.descent.unwrap().get::<meter>();
+
+    assert_eq!(elevation.altitude.len(), activity.record.altitude.len());
+    assert!((19..=20).contains(&ascent), "{ascent}");
+    assert_eq!(descent, 0);
+    assert_eq!(elevation.lap.len(), activity.lap.len());
+
+    // The original altitude is left as it was
+    let first = activity.record.altitude[0].unwrap().get::<meter>();
+    assert_eq!(first, 10.);
+}
+
+#[test]
+fn leave_activities_outside_the_model() {
+    let (_dir, dem) = dem();
+    let activity =
+        tf_parse::parse(gpx().replace("lon=\"10.5\"", "lon=\"12.5\"").as_bytes()).unwrap();
+
+    assert!(correct(&activity, &dem).is_none());
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use common::Point;
+use tf_parse::export::{export, Format};
+
+/// Two points half a minute apart, with heart rate and power at the first.
+fn gpx() -> String {
+    common::gpx(
+        "cycling",
+        &[
+            Point {
+                altitude: Some(10.),
+                heartrate: Some(120),
+                power: Some(210),
+                ..Point::moving(0)
+            },
+            Point {
+                altitude: Some(15.),
+                heartrate: Some(140),
+                ..Point::moving(30)
+            },
+        ],
+    )
+}
+
+#[test]
+fn export_format_from_str() {
+    assert_eq!("fit".parse(), Ok(Format::Fit));
+    assert_eq!("csv".parse(), Ok(Format::Csv));
+    assert!("pdf".parse::<Format>().is_err());
+}
+
+#[test]
+fn fit_roundtrip() {
+    let activity = tf_parse::parse(gpx().as_bytes()).unwrap();
+
+    let fit = export(&activity, Format::Fit);
+    assert_eq!(tf_parse::Format::detect(&fit), Some(tf_parse::Format::Fit));
+
+    let parsed = tf_parse::parse(&fit).unwrap();
+
+    assert_eq!(parsed.record.heartrate, activity.record.heartrate);
+    assert_eq!(parsed.record.timestamp, activity.record.timestamp);
+    assert_eq!(parsed.session.start_time, activity.session.start_time);
+    assert_eq!(parsed.session.utc_offset, activity.session.utc_offset);
+    assert_eq!(parsed.id, activity.id);
+    assert_eq!(parsed.devices.len(), 1);
+    assert!(parsed.devices[0].creator);
+    assert_eq!(parsed.session.laps, activity.session.laps);
+    assert_eq!(parsed.lap.len(), activity.lap.len());
+    assert!(parsed.session.sport == activity.session.sport);
+    assert_eq!(
+        parsed.file_id.and_then(|x| x.time_created),
+        Some(activity.session.start_time)
+    );
+
+    for (actual, expected) in parsed.record.lat.iter().zip(&activity.record.lat) {
+        assert!((actual.unwrap() - expected.unwrap()).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn gpx_roundtrip() {
+    let activity = tf_parse::parse(gpx().as_bytes()).unwrap();
+    let parsed = tf_parse::parse(&export(&activity, Format::Gpx)).unwrap();
+
+    assert_eq!(parsed.record.heartrate, activity.record.heartrate);
+    assert_eq!(parsed.record.lat, activity.record.lat);
+    assert!(parsed.record.power[0].is_some());
+}
+
+#[test]
+fn tcx_roundtrip() {
+    let activity = tf_parse::parse(gpx().as_bytes()).unwrap();
+    let parsed = tf_parse::parse(&export(&activity, Format::Tcx)).unwrap();
+
+    assert_eq!(parsed.record.heartrate, activity.record.heartrate);
+    assert_eq!(parsed.lap.len(), 1);
+}
+
+#[test]
+fn csv_rows() {
+    let activity = tf_parse::parse(gpx().as_bytes()).unwrap();
+    let csv = String::from_utf8(export(&activity, Format::Csv)).unwrap();
+    let mut lines = csv.lines();
+
+    assert_eq!(
+        lines.next(),
+        Some("timestamp,duration,lat,lon,altitude,distance,speed,heartrate,cadence,power")
+    );
+    assert_eq!(lines.count(), 2);
+}
@@ -0,0 +1,458 @@
+mod common;
+
+use common::{
+    fit::{mesg_num, time, Field, FitWriter},
+    Point,
+};
+use tf_models::{
+    activity::{RecordRange, Stroke},
+    Sport,
+};
+use tf_parse::{
+    error::Error,
+    export::{export, Format},
+    Repair,
+};
+
+fn fit() -> Vec<u8> {
+    let points = (0..120)
+        .map(|i| Point {
+            altitude: Some(10.),
+            ..Point::moving(i)
+        })
+        .collect::<Vec<_>>();
+
+    let activity = tf_parse::parse(common::gpx("running", &points).as_bytes()).unwrap();
+    export(&activity, Format::Fit)
+}
+
+#[test]
+fn recover_truncated_fit() {
+    let fit = fit();
+    let truncated = &fit[..fit.len() / 2];
+
+    assert!(matches!(
+        tf_parse::parse(truncated),
+        Err(Error::Decode { .. })
+    ));
+
+    let (activity, report) = tf_parse::parse_lenient(truncated).unwrap();
+
+    assert!(matches!(
+        report.repairs.first(),
+        Some(Repair::Truncated { .. })
+    ));
+    assert!(report.repairs.contains(&Repair::SynthesizedSession));
+    assert!(!activity.record.timestamp.is_empty());
+    assert!(activity.record.timestamp.len() < 120);
+    assert_eq!(activity.lap.len(), 1);
+    assert_eq!(
+        Some(activity.session.start_time),
+        activity.record.timestamp[0]
+    );
+    assert!(activity.session.distance.is_some());
+}
+
+#[test]
+fn recover_fit_with_invalid_checksum() {
+    let mut fit = fit();
+    *fit.last_mut().unwrap() ^= 0xFF;
+
+    assert!(matches!(tf_parse::parse(&fit), Err(Error::InvalidChecksum)));
+
+    let (activity, report) = tf_parse::parse_lenient(&fit).unwrap();
+
+    assert_eq!(report.repairs, vec![Repair::InvalidChecksum]);
+    assert_eq!(activity.record.timestamp.len(), 120);
+}
+
+#[test]
+fn lenient_parse_of_intact_fit_makes_no_repairs() {
+    let (_, report) = tf_parse::parse_lenient(&fit()).unwrap();
+
+    assert_eq!(report, tf_parse::Report::default());
+}
+
+/// A run of 60 seconds followed by a ride of 60 seconds, with a lap each.
+fn multisport() -> FitWriter {
+    let mut fit = FitWriter::default();
+
+    fit.message(mesg_num::FILE_ID, &[(0, Field::Enum(4)), (4, time(0))]);
+
+    for i in 0..120 {
+        fit.message(
+            mesg_num::RECORD,
+            &[
+                (253, time(i)),
+                (3, Field::UInt8(140)),
+                (5, Field::UInt32(i * 500)),
+                (7, Field::UInt16(if i < 60 { 0 } else { 200 })),
+            ],
+        );
+    }
+
+    for (i, (sport, distance)) in [(1, 30_000), (2, 30_000)].into_iter().enumerate() {
+        let start = i as u32 * 60;
+
+        fit.message(
+            mesg_num::LAP,
+            &[
+                (253, time(start + 59)),
+                (2, time(start)),
+                (7, Field::UInt32(59_000)),
+                (8, Field::UInt32(59_000)),
+                (9, Field::UInt32(distance)),
+            ],
+        );
+        fit.message(
+            mesg_num::SESSION,
+            &[
+                (253, time(start + 59)),
+                (2, time(start)),
+                (5, Field::Enum(sport)),
+                (7, Field::UInt32(59_000)),
+                (8, Field::UInt32(59_000)),
+                (9, Field::UInt32(distance)),
+                (16, Field::UInt8(140)),
+                (25, Field::UInt16(i as u16)),
+                (26, Field::UInt16(1)),
+            ],
+        );
+    }
+
+    fit
+}
+
+#[test]
+fn split_multisport_fit_into_children() {
+    let activity = tf_parse::parse(&multisport().finish()).unwrap();
+
+    assert!(activity.session.sport == Sport::Multisport);
+    assert_eq!(activity.session.laps, Some(2));
+    assert_eq!(activity.session.heartrate_avg, Some(140));
+    assert_eq!(activity.session.duration.as_secs(), 118);
+    assert_eq!(activity.record.timestamp.len(), 120);
+    assert_eq!(activity.record_range, None);
+
+    let [run, ride] = activity.children.as_slice() else {
+        panic!("expected two children");
+    };
+
+    assert!(run.session.sport == Sport::Running);
+    assert_eq!(run.record_range, Some(RecordRange { start: 0, end: 60 }));
+    assert_eq!(run.record.timestamp.len(), 60);
+    assert_eq!(run.lap.len(), 1);
+
+    assert!(ride.session.sport == Sport::Cycling);
+    assert_eq!(
+        ride.record_range,
+        Some(RecordRange {
+            start: 60,
+            end: 120
+        })
+    );
+    assert_eq!(ride.record.timestamp[0], activity.record.timestamp[60]);
+    assert_eq!(ride.record.duration[0].as_secs(), 0);
+    assert_eq!(ride.record.duration[59].as_secs(), 59);
+    assert_eq!(ride.lap.len(), 1);
+}
+
+#[test]
+fn count_laps_of_multisport_fit_without_overflow() {
+    let mut fit = multisport();
+
+    fit.message(
+        mesg_num::SESSION,
+        &[
+            (253, time(120)),
+            (2, time(120)),
+            (5, Field::Enum(1)),
+            (26, Field::UInt16(u16::MAX)),
+        ],
+    );
+
+    let activity = tf_parse::parse(&fit.finish()).unwrap();
+
+    assert_eq!(activity.session.laps, Some(u16::MAX));
+}
+
+/// Describes a power field of an app, with the same name and number for
+/// every app.
+fn describe_power(fit: &mut FitWriter, developer_data_index: u8) {
+    fit.message(
+        mesg_num::DEVELOPER_DATA_ID,
+        &[
+            (3, Field::UInt8(developer_data_index)),
+            (4, Field::UInt32(1)),
+        ],
+    );
+    fit.message(
+        mesg_num::FIELD_DESCRIPTION,
+        &[
+            (0, Field::UInt8(developer_data_index)),
+            (1, Field::UInt8(0)),
+            (2, Field::UInt8(0x84)),
+            (3, Field::String("Power")),
+            (8, Field::String("Watts")),
+            (14, Field::UInt16(mesg_num::RECORD)),
+            (15, Field::UInt8(7)),
+        ],
+    );
+}
+
+#[test]
+fn keep_developer_fields_of_apps_apart() {
+    let mut fit = FitWriter::default();
+
+    fit.message(mesg_num::FILE_ID, &[(0, Field::Enum(4)), (4, time(0))]);
+    describe_power(&mut fit, 0);
+
+    for i in 0..5 {
+        fit.developer_message(
+            mesg_num::RECORD,
+            &[(253, time(i))],
+            &[(0, 0, Field::UInt16(300 + i as u16))],
+        );
+    }
+
+    describe_power(&mut fit, 1);
+
+    for i in 5..10 {
+        fit.developer_message(
+            mesg_num::RECORD,
+            &[(253, time(i))],
+            &[
+                (0, 0, Field::UInt16(300 + i as u16)),
+                (0, 1, Field::UInt16(10)),
+            ],
+        );
+    }
+
+    fit.message(
+        mesg_num::SESSION,
+        &[(253, time(9)), (2, time(0)), (5, Field::Enum(1))],
+    );
+
+    let activity = tf_parse::parse(&fit.finish()).unwrap();
+
+    let [first, second] = activity.record.developer_fields.as_slice() else {
+        panic!("expected two developer fields");
+    };
+
+    assert_eq!(first.developer_data_index, 0);
+    assert_eq!(first.name, "Power");
+    assert_eq!(first.units.as_deref(), Some("Watts"));
+    assert_eq!(first.native_field_num, Some(7));
+    assert_eq!(first.values.len(), 10);
+    assert_eq!(first.values[7], Some(307.));
+
+    assert_eq!(second.developer_data_index, 1);
+    assert_eq!(second.name, "Power");
+    assert_eq!(second.values.len(), 10);
+    assert_eq!(second.values[4], None);
+    assert_eq!(second.values[5], Some(10.));
+}
+
+#[test]
+fn parse_pool_swim_lengths() {
+    let mut fit = FitWriter::default();
+
+    fit.message(mesg_num::FILE_ID, &[(0, Field::Enum(4)), (4, time(0))]);
+
+    // Start, duration, stroke, strokes and whether the length was active
+    let lengths = [
+        (0, 30, Some(0), Some(15), true),
+        (30, 32, Some(0), Some(16), true),
+        (62, 20, None, None, false),
+        (82, 40, Some(2), Some(12), true),
+    ];
+
+    for (i, (start, duration, stroke, strokes, active)) in lengths.into_iter().enumerate() {
+        let mut fields = vec![
+            (253, time(start + duration)),
+            (254, Field::UInt16(i as u16)),
+            (2, time(start)),
+            (3, Field::UInt32(duration * 1000)),
+            (4, Field::UInt32(duration * 1000)),
+            (12, Field::Enum(u8::from(active))),
+        ];
+        fields.extend(stroke.map(|x| (7, Field::Enum(x))));
+        fields.extend(strokes.map(|x| (5, Field::UInt16(x))));
+
+        fit.message(mesg_num::LENGTH, &fields);
+    }
+
+    fit.message(
+        mesg_num::SESSION,
+        &[
+            (253, time(122)),
+            (2, time(0)),
+            (5, Field::Enum(5)),
+            (6, Field::Enum(17)),
+            (7, Field::UInt32(122_000)),
+            (44, Field::UInt16(2500)),
+        ],
+    );
+
+    let activity = tf_parse::parse(&fit.finish()).unwrap();
+    let swim = activity.swim.unwrap();
+
+    assert_eq!(
+        swim.pool_length.map(|x| x.get::<uom::si::length::meter>()),
+        Some(25.)
+    );
+    assert_eq!(swim.lengths.len(), 4);
+
+    let start_time = activity.session.start_time;
+
+    for (length, (start, duration, ..)) in swim.lengths.iter().zip(lengths) {
+        assert_eq!(
+            length.start_time,
+            start_time + chrono::Duration::seconds(i64::from(start))
+        );
+        assert_eq!(length.duration.as_secs(), u64::from(duration));
+    }
+
+    assert!(swim.lengths[0].stroke == Some(Stroke::Freestyle));
+    assert_eq!(swim.lengths[0].strokes, Some(15));
+    assert_eq!(swim.lengths[0].swolf, Some(45));
+    assert!(!swim.lengths[2].active);
+    assert!(swim.lengths[2].stroke.is_none());
+    assert_eq!(swim.lengths[2].swolf, None);
+    assert!(swim.lengths[3].stroke == Some(Stroke::Breaststroke));
+
+    assert_eq!(swim.intervals.len(), 3);
+    assert_eq!(swim.intervals[0].lengths, 2);
+    assert!(swim.intervals[0].stroke == Some(Stroke::Freestyle));
+}
+
+#[test]
+fn merge_device_info_by_device_index() {
+    let mut fit = FitWriter::default();
+
+    fit.message(
+        mesg_num::FILE_ID,
+        &[
+            (0, Field::Enum(4)),
+            (1, Field::UInt16(1)),
+            (3, Field::UInt32(1111)),
+            (4, time(0)),
+        ],
+    );
+    fit.message(
+        mesg_num::DEVICE_INFO,
+        &[
+            (253, time(0)),
+            (0, Field::UInt8(0)),
+            (2, Field::UInt16(1)),
+            (3, Field::UInt32(1111)),
+            (5, Field::UInt16(1000)),
+        ],
+    );
+    // A power meter reporting at the start and the end
+    fit.message(
+        mesg_num::DEVICE_INFO,
+        &[
+            (253, time(0)),
+            (0, Field::UInt8(1)),
+            (25, Field::Enum(1)),
+            (1, Field::UInt8(11)),
+            (3, Field::UInt32(2222)),
+            (11, Field::UInt8(2)),
+        ],
+    );
+    fit.message(
+        mesg_num::DEVICE_INFO,
+        &[
+            (253, time(60)),
+            (0, Field::UInt8(1)),
+            (10, Field::UInt16(768)),
+            (11, Field::UInt8(4)),
+        ],
+    );
+    fit.message(
+        mesg_num::DEVICE_INFO,
+        &[(253, time(60)), (0, Field::UInt8(0)), (11, Field::UInt8(3))],
+    );
+    fit.message(
+        mesg_num::SESSION,
+        &[(253, time(60)), (2, time(0)), (5, Field::Enum(2))],
+    );
+
+    let activity = tf_parse::parse(&fit.finish()).unwrap();
+
+    let [creator, sensor] = activity.devices.as_slice() else {
+        panic!("expected two devices");
+    };
+
+    assert!(creator.creator);
+    assert_eq!(creator.manufacturer.as_deref(), Some("garmin"));
+    assert_eq!(creator.serial_number, Some(1111));
+    assert_eq!(creator.software_version, Some(10.));
+    assert_eq!(creator.battery_status.as_deref(), Some("ok"));
+
+    assert!(!sensor.creator);
+    assert_eq!(sensor.device_type.as_deref(), Some("bike_power"));
+    assert_eq!(sensor.serial_number, Some(2222));
+    assert_eq!(sensor.battery_status.as_deref(), Some("low"));
+    assert_eq!(sensor.battery_voltage, Some(3.));
+}
+
+#[test]
+fn parse_temperature_respiration_and_training_effect() {
+    let mut fit = FitWriter::default();
+
+    fit.message(mesg_num::FILE_ID, &[(0, Field::Enum(4)), (4, time(0))]);
+
+    for i in 0..10 {
+        fit.message(
+            mesg_num::RECORD,
+            &[
+                (253, time(i)),
+                (13, Field::SInt8(-2)),
+                (108, Field::UInt16(2150)),
+            ],
+        );
+    }
+
+    fit.message(
+        mesg_num::LAP,
+        &[
+            (253, time(9)),
+            (2, time(0)),
+            (7, Field::UInt32(9000)),
+            (50, Field::SInt8(-2)),
+            (51, Field::SInt8(-1)),
+        ],
+    );
+    fit.message(
+        mesg_num::SESSION,
+        &[
+            (253, time(9)),
+            (2, time(0)),
+            (5, Field::Enum(1)),
+            (7, Field::UInt32(9000)),
+            (24, Field::UInt8(35)),
+            (57, Field::SInt8(-2)),
+            (58, Field::SInt8(-1)),
+            (137, Field::UInt8(12)),
+            (169, Field::UInt16(2150)),
+        ],
+    );
+
+    let activity = tf_parse::parse(&fit.finish()).unwrap();
+
+    assert_eq!(activity.record.temperature, [Some(-2); 10]);
+    assert_eq!(activity.record.respiration_rate, [Some(21.5); 10]);
+
+    assert_eq!(activity.lap[0].temperature_avg, Some(-2));
+    assert_eq!(activity.lap[0].temperature_max, Some(-1));
+
+    let session = activity.session;
+
+    assert_eq!(session.temperature_avg, Some(-2));
+    assert_eq!(session.temperature_max, Some(-1));
+    assert_eq!(session.respiration_rate_avg, Some(21.5));
+    assert_eq!(session.training_effect, Some(3.5));
+    assert_eq!(session.anaerobic_training_effect, Some(1.2));
+}
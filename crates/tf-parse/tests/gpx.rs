@@ -0,0 +1,171 @@
+use tf_models::Sport;
+use tf_parse::Warning;
+
+const GPX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1"
+  xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v1">
+  <trk>
+    <name>Morning run</name>
+    <type>running</type>
+    <trkseg>
+      <trkpt lat="59.9000" lon="10.7000">
+        <ele>10.0</ele>
+        <time>2022-06-01T06:00:00Z</time>
+        <extensions>
+          <gpxtpx:TrackPointExtension>
+            <gpxtpx:hr>120</gpxtpx:hr>
+            <gpxtpx:cad>80</gpxtpx:cad>
+          </gpxtpx:TrackPointExtension>
+        </extensions>
+      </trkpt>
+      <trkpt lat="59.9010" lon="10.7000">
+        <ele>15.0</ele>
+        <time>2022-06-01T06:00:30Z</time>
+        <extensions>
+          <gpxtpx:TrackPointExtension>
+            <gpxtpx:atemp>21.6</gpxtpx:atemp>
+            <gpxtpx:hr>140</gpxtpx:hr>
+          </gpxtpx:TrackPointExtension>
+        </extensions>
+      </trkpt>
+    </trkseg>
+    <trkseg>
+      <trkpt lat="59.9020" lon="10.7010">
+        <ele>12.0</ele>
+        <time>2022-06-01T06:01:00Z</time>
+      </trkpt>
+    </trkseg>
+  </trk>
+</gpx>"#;
+
+#[test]
+fn detect_gpx() {
+    assert_eq!(
+        tf_parse::Format::detect(GPX.as_bytes()),
+        Some(tf_parse::Format::Gpx)
+    );
+}
+
+#[test]
+fn detect_gpx_after_long_prolog() {
+    let (declaration, gpx) = GPX.split_once('\n').unwrap();
+    let comment = format!("<!-- {} -->", "exported ".repeat(200));
+
+    for prolog in [
+        format!("{declaration}\n{comment}\n"),
+        format!("{declaration}\n<!DOCTYPE gpx [\n  <!ENTITY app \"<test>\">\n]>\n{comment}\n"),
+        format!("\u{feff}{declaration}\n<?xml-stylesheet href=\"track.xsl\"?>\n{comment}\n"),
+    ] {
+        assert_eq!(
+            tf_parse::Format::detect(format!("{prolog}{gpx}").as_bytes()),
+            Some(tf_parse::Format::Gpx)
+        );
+    }
+
+    // The head of a stream is read until the root element
+    let activity = tf_parse::parse(format!("{declaration}\n{comment}\n{gpx}").as_bytes());
+    assert_eq!(activity.unwrap().record.timestamp.len(), 3);
+}
+
+#[test]
+fn parse_gpx_trackpoints() {
+    let activity = tf_parse::parse(GPX.as_bytes()).unwrap();
+
+    assert_eq!(activity.record.lat.len(), 3);
+    assert_eq!(activity.record.heartrate, vec![Some(120), Some(140), None]);
+    assert_eq!(activity.lap.len(), 2);
+    assert_eq!(activity.session.laps, Some(2));
+    assert_eq!(activity.session.heartrate_max, Some(140));
+    assert_eq!(activity.session.nec_lat, Some(59.902));
+    assert_eq!(activity.session.swc_lon, Some(10.7));
+    assert!(activity.session.sport == Sport::Running);
+}
+
+#[test]
+fn parse_gpx_temperature() {
+    let activity = tf_parse::parse(GPX.as_bytes()).unwrap();
+
+    assert_eq!(activity.record.temperature, vec![None, Some(22), None]);
+    assert_eq!(activity.record.respiration_rate.len(), 3);
+    assert_eq!(activity.session.temperature_max, Some(22));
+}
+
+#[test]
+fn reject_gpx_without_timestamps() {
+    let gpx = r#"<gpx><trk><trkseg><trkpt lat="1" lon="1"/></trkseg></trk></gpx>"#;
+
+    assert!(matches!(
+        tf_parse::parse(gpx.as_bytes()),
+        Err(tf_parse::error::Error::MissingData)
+    ));
+}
+
+#[test]
+fn report_suspicious_gpx() {
+    let gpx = r#"<gpx><trk><type>underwater hockey</type><trkseg>
+        <trkpt lat="1" lon="1"><time>2022-06-01T06:00:00Z</time></trkpt>
+        <trkpt lat="95" lon="1"/>
+        <trkpt lat="1" lon="1.001"><time>2022-06-01T06:00:10Z</time></trkpt>
+    </trkseg></trk></gpx>"#;
+
+    let (activity, report) = tf_parse::parse_with_report(gpx.as_bytes(), false);
+
+    assert!(activity.is_ok());
+    assert!(report.errors.is_empty());
+    assert_eq!(
+        report.warnings,
+        vec![
+            Warning::UnknownSport {
+                value: "underwater hockey".into()
+            },
+            Warning::MissingTimestamps { count: 1 },
+            Warning::CoordinatesOutOfRange {
+                count: 1,
+                first_index: 1
+            },
+        ]
+    );
+}
+
+#[test]
+fn derive_utc_offset_from_position() {
+    let activity = tf_parse::parse(GPX.as_bytes()).unwrap();
+
+    // Oslo is on CEST in June
+    assert_eq!(activity.session.utc_offset, Some(7200));
+    assert_eq!(
+        activity.session.start_time.to_rfc3339(),
+        "2022-06-01T06:00:00+00:00"
+    );
+    assert_eq!(
+        activity.session.local_start_time().to_rfc3339(),
+        "2022-06-01T08:00:00+02:00"
+    );
+    assert_eq!(activity.id.as_str(), "2022060108000000");
+}
+
+#[test]
+fn detect_pauses_in_gpx() {
+    let gpx = r#"<gpx><trk><trkseg>
+        <trkpt lat="59.9000" lon="10.7"><time>2022-06-01T06:00:00Z</time></trkpt>
+        <trkpt lat="59.9001" lon="10.7"><time>2022-06-01T06:00:05Z</time></trkpt>
+        <trkpt lat="59.9002" lon="10.7"><time>2022-06-01T06:01:05Z</time></trkpt>
+        <trkpt lat="59.9003" lon="10.7"><time>2022-06-01T06:01:10Z</time></trkpt>
+    </trkseg></trk></gpx>"#;
+
+    let activity = tf_parse::parse(gpx.as_bytes()).unwrap();
+
+    assert_eq!(activity.pauses.len(), 1);
+    assert_eq!(activity.pauses[0].duration.as_secs(), 60);
+    assert_eq!(activity.session.duration.as_secs(), 70);
+    assert_eq!(activity.session.duration_active.as_secs(), 10);
+    assert_eq!(
+        activity
+            .record
+            .timer_time
+            .iter()
+            .map(|x| x.as_secs())
+            .collect::<Vec<_>>(),
+        vec![0, 5, 5, 10]
+    );
+}
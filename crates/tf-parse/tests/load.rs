@@ -0,0 +1,146 @@
+mod common;
+
+use chrono::NaiveDate;
+use common::Point;
+use tf_models::{
+    activity::StressSource,
+    types::Power,
+    user::{Fitness, Threshold, User},
+};
+use tf_parse::load::load;
+use uom::si::{power::watt, velocity::meter_per_second};
+
+/// An hour at 250 W, 4 m/s and 160 bpm, with a point every five seconds.
+fn tcx(sport: &str) -> String {
+    let points = (0..=720)
+        .map(|i| Point {
+            heartrate: Some(160),
+            speed: Some(4.),
+            power: Some(250),
+            ..Point::at(i * 5)
+        })
+        .collect::<Vec<_>>();
+
+    common::tcx(sport, &[points])
+}
+
+fn threshold() -> Threshold {
+    Threshold {
+        effective_from: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(),
+        ftp: None,
+        threshold_pace: None,
+        lthr: None,
+    }
+}
+
+#[test]
+fn normalize_steady_effort() {
+    let activity = tf_parse::parse(tcx("Running").as_bytes()).unwrap();
+    let load = load(&activity, None);
+
+    assert_eq!(load.normalized_power.unwrap().get::<watt>(), 250);
+    let speed = load.normalized_speed.unwrap().get::<meter_per_second>();
+    assert!((speed - 4.).abs() < 1e-9, "{speed}");
+    assert!(load.trimp.is_none());
+
+    let activity = tf_parse::parse(tcx("Biking").as_bytes()).unwrap();
+    assert!(load(&activity, None).normalized_speed.is_none());
+}
+
+#[test]
+fn score_an_hour_at_threshold() {
+    let activity = tf_parse::parse(tcx("Running").as_bytes()).unwrap();
+    let load = load(&activity, None);
+
+    let thresholds = [
+        (
+            Threshold {
+                ftp: Some(Power::new::<watt>(250)),
+                lthr: Some(170),
+                ..threshold()
+            },
+            StressSource::Power,
+        ),
+        (
+            Threshold {
+                threshold_pace: Some(std::time::Duration::from_secs(250).into()),
+                lthr: Some(170),
+                ..threshold()
+            },
+            StressSource::Pace,
+        ),
+        (
+            Threshold {
+                lthr: Some(160),
+                ..threshold()
+            },
+            StressSource::Heartrate,
+        ),
+    ];
+
+    for (threshold, expected) in thresholds {
+        let (stress, source) = load.stress(&threshold).unwrap();
+
+        assert!(source == expected);
+        assert!((stress - 100.).abs() < 0.1, "{stress}");
+    }
+
+    assert!(load.stress(&threshold()).is_none());
+}
+
+#[test]
+fn weigh_heart_rate_reserve() {
+    let activity = tf_parse::parse(tcx("Running").as_bytes()).unwrap();
+    let user = User {
+        name: String::new(),
+        heartrate_rest: 60,
+        heartrate_max: 185,
+    };
+
+    let trimp = load(&activity, Some(&user)).trimp.unwrap();
+
+    // Sixty minutes at 80 % of the heart rate reserve
+    let expected = 60. * 0.8 * 0.64 * (1.92_f64 * 0.8).exp();
+    assert!((trimp - expected).abs() < 1e-6, "{trimp}");
+}
+
+#[test]
+fn update_fitness_incrementally() {
+    let day = |x| NaiveDate::from_ymd_opt(2022, 6, x).unwrap();
+    let stress = [
+        (day(10), 100.),
+        (day(3), 50.),
+        (day(20), 80.),
+        (day(10), 30.),
+    ];
+
+    let mut fitness = Fitness::default();
+    for (date, stress) in stress {
+        fitness.add(date, stress);
+    }
+
+    let mut rebuilt = Fitness::default();
+    rebuilt.rebuild(stress);
+
+    for date in day(1).iter_days().take(30) {
+        let (a, b) = (fitness.day(date), rebuilt.day(date));
+
+        assert!((a.ctl - b.ctl).abs() < 1e-9, "{date}");
+        assert!((a.atl - b.atl).abs() < 1e-9, "{date}");
+    }
+
+    assert_eq!(fitness.day(day(2)).ctl, 0.);
+    assert!((fitness.day(day(3)).atl - 50. / 7.).abs() < 1e-9);
+    assert!(fitness.form(day(4)) < 0.);
+
+    // Fatigue fades faster than fitness after the last day
+    let last = fitness.day(day(20));
+    let later = fitness.day(day(30));
+    assert!(later.atl / last.atl < later.ctl / last.ctl);
+
+    for (date, stress) in stress {
+        fitness.add(date, -stress);
+    }
+
+    assert!(fitness.day(day(25)).ctl.abs() < 1e-9);
+}
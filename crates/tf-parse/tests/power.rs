@@ -0,0 +1,68 @@
+use std::time::Duration;
+use tf_models::{activity::Record, types::Power};
+use tf_parse::power::curve;
+use uom::si::power::watt;
+
+fn record(samples: impl Iterator<Item = (u64, u16)>) -> Record {
+    let mut record = Record::default();
+
+    for (second, power) in samples {
+        record.duration.push(Duration::from_secs(second).into());
+        record.power.push(Some(Power::new::<watt>(power)));
+    }
+
+    record
+}
+
+fn best(record: &Record, seconds: u64) -> Option<(u16, u64)> {
+    curve(record)?
+        .bests
+        .iter()
+        .find(|x| x.duration.as_secs() == seconds)
+        .map(|x| (x.power.get::<watt>(), x.start.as_secs()))
+}
+
+#[test]
+fn power_curve() {
+    // Ten minutes at 200 W with a minute at 400 W
+    let record = record((0..600).map(|x| (x, if (100..160).contains(&x) { 400 } else { 200 })));
+
+    assert_eq!(best(&record, 1), Some((400, 100)));
+    assert_eq!(best(&record, 60), Some((400, 100)));
+    assert_eq!(best(&record, 600), Some((220, 0)));
+    assert_eq!(best(&record, 1200), None);
+}
+
+#[test]
+fn power_curve_with_gaps() {
+    // Recorded every other second, and stopped for five minutes
+    let record = record(
+        (0..100)
+            .map(|x| (x, 300))
+            .chain((400..500).map(|x| (x, 400)))
+            .filter(|(x, _)| x % 2 == 0),
+    );
+
+    assert_eq!(best(&record, 60), Some((400, 400)));
+    // The stop is left out, but the last record before it counts for a while
+    assert_eq!(best(&record, 180), Some((355, 27)));
+    assert_eq!(best(&record, 300), None);
+}
+
+#[test]
+fn power_curve_of_records_far_apart() {
+    // A clock that jumped years ahead does not make the timeline that long
+    let record = record([(0, 200), (1, 200), (u32::MAX as u64, 200)].into_iter());
+
+    let curve = curve(&record).unwrap();
+    assert_eq!(curve.bests.len(), 4);
+    assert_eq!(curve.bests[2].start.as_secs(), 0);
+}
+
+#[test]
+fn no_power_curve_without_power() {
+    let mut record = record((0..10).map(|x| (x, 0)));
+    record.power.iter_mut().for_each(|x| *x = None);
+
+    assert!(curve(&record).is_none());
+}
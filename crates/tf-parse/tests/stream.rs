@@ -0,0 +1,86 @@
+mod common;
+
+use common::{fit::Ride, Point};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    io::{BufReader, Read},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use tf_parse::export::{export, Format};
+
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+            PEAK.fetch_max(allocated, Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+#[test]
+fn parse_long_ride_in_bounded_memory() {
+    // Twenty hours at 1 Hz, some 280 MB of records
+    let mut file = Ride::new(20 * 3600);
+    let size = file.size();
+
+    // Time zones are looked up in a model that is loaded once
+    tf_parse::parse(common::gpx("cycling", &[Point::moving(0)]).as_bytes()).unwrap();
+
+    let baseline = ALLOCATED.load(Ordering::SeqCst);
+    PEAK.store(baseline, Ordering::SeqCst);
+
+    let (activity, report) = tf_parse::parse_reader(BufReader::new(&mut file), false);
+    let peak = PEAK.load(Ordering::SeqCst) - baseline;
+
+    let activity = activity.unwrap();
+    let records = activity.record.timestamp.len();
+    assert!(report.errors.is_empty());
+    assert!(size > 256 << 20, "file of {size} bytes");
+    assert_eq!(records, 20 * 3600);
+    // The notes are not numbers, so only the temperature is kept
+    assert_eq!(activity.record.developer_fields.len(), 1);
+
+    // The record columns grow with each record, but the file is not held
+    assert!(peak < records * 1024, "peak allocation of {peak} bytes");
+}
+
+/// A reader that returns a few bytes at a time, like a slow network stream.
+struct Trickle<'a>(&'a [u8]);
+
+impl Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(self.0.len()).min(3);
+        buf[..len].copy_from_slice(&self.0[..len]);
+        self.0 = &self.0[len..];
+
+        Ok(len)
+    }
+}
+
+#[test]
+fn detect_format_of_trickling_reader() {
+    let gpx = common::gpx("running", &[Point::moving(0), Point::moving(5)]).into_bytes();
+    let fit = export(&tf_parse::parse(&gpx).unwrap(), Format::Fit);
+
+    for data in [gpx.as_slice(), fit.as_slice()] {
+        let (activity, report) = tf_parse::parse_reader(BufReader::new(Trickle(data)), false);
+
+        assert!(report.errors.is_empty());
+        assert_eq!(activity.unwrap().record.timestamp.len(), 2);
+    }
+}
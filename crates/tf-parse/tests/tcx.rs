@@ -0,0 +1,75 @@
+const TCX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<TrainingCenterDatabase xmlns="http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2"
+  xmlns:ns3="http://www.garmin.com/xmlschemas/ActivityExtension/v2">
+  <Activities>
+    <Activity Sport="Biking">
+      <Id>2022-06-01T06:00:00Z</Id>
+      <Lap StartTime="2022-06-01T06:00:00Z">
+        <TotalTimeSeconds>60.0</TotalTimeSeconds>
+        <DistanceMeters>500.0</DistanceMeters>
+        <Calories>12</Calories>
+        <AverageHeartRateBpm><Value>130</Value></AverageHeartRateBpm>
+        <MaximumHeartRateBpm><Value>150</Value></MaximumHeartRateBpm>
+        <Track>
+          <Trackpoint>
+            <Time>2022-06-01T06:00:00Z</Time>
+            <Position>
+              <LatitudeDegrees>59.9</LatitudeDegrees>
+              <LongitudeDegrees>10.7</LongitudeDegrees>
+            </Position>
+            <DistanceMeters>0.0</DistanceMeters>
+            <HeartRateBpm><Value>120</Value></HeartRateBpm>
+            <Cadence>85</Cadence>
+            <Extensions><ns3:TPX><ns3:Watts>200</ns3:Watts></ns3:TPX></Extensions>
+          </Trackpoint>
+          <Trackpoint>
+            <Time>2022-06-01T06:01:00Z</Time>
+            <DistanceMeters>500.0</DistanceMeters>
+            <HeartRateBpm><Value>140</Value></HeartRateBpm>
+            <Extensions><ns3:TPX><ns3:Watts>250</ns3:Watts></ns3:TPX></Extensions>
+          </Trackpoint>
+        </Track>
+      </Lap>
+      <Lap StartTime="2022-06-01T06:01:00Z">
+        <TotalTimeSeconds>30.0</TotalTimeSeconds>
+        <Calories>8</Calories>
+        <Track>
+          <Trackpoint>
+            <Time>2022-06-01T06:01:30Z</Time>
+            <DistanceMeters>700.0</DistanceMeters>
+          </Trackpoint>
+        </Track>
+      </Lap>
+    </Activity>
+  </Activities>
+</TrainingCenterDatabase>"#;
+
+#[test]
+fn detect_tcx() {
+    assert_eq!(
+        tf_parse::Format::detect(TCX.as_bytes()),
+        Some(tf_parse::Format::Tcx)
+    );
+}
+
+#[test]
+fn parse_tcx_laps() {
+    let activity = tf_parse::parse(TCX.as_bytes()).unwrap();
+
+    assert_eq!(activity.lap.len(), 2);
+    assert_eq!(activity.lap[0].heartrate_avg, Some(130));
+    assert_eq!(activity.lap[0].heartrate_max, Some(150));
+    assert_eq!(activity.lap[1].heartrate_avg, None);
+    assert_eq!(activity.session.heartrate_max, Some(150));
+    assert_eq!(activity.session.laps, Some(2));
+}
+
+#[test]
+fn parse_tcx_trackpoints() {
+    let activity = tf_parse::parse(TCX.as_bytes()).unwrap();
+
+    assert_eq!(activity.record.heartrate, vec![Some(120), Some(140), None]);
+    assert_eq!(activity.record.lat, vec![Some(59.9), None, None]);
+    assert!(activity.record.power[0].is_some());
+    assert!(activity.record.power[2].is_none());
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::Point;
+use tf_models::user::ZoneKind;
+use tf_parse::zones::histograms;
+
+/// Two laps of a minute, the first at 120 bpm and 100 W and the second at
+/// 160 bpm and 300 W, with a point every ten seconds.
+fn tcx() -> String {
+    let lap = |minute: u32, heartrate: u8, power: u16| {
+        (0..6)
+            .map(|i| Point {
+                heartrate: Some(heartrate),
+                power: Some(power),
+                ..Point::at(minute * 60 + i * 10)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    common::tcx("Biking", &[lap(0, 120, 100), lap(1, 160, 300)])
+}
+
+#[test]
+fn split_time_into_zones() {
+    let activity = tf_parse::parse(tcx().as_bytes()).unwrap();
+    let histograms = histograms(&activity).unwrap();
+
+    // The last point has no time after it
+    let seconds = histograms
+        .activity
+        .time_in_zones(ZoneKind::Heartrate, &[100., 150., 170.]);
+    assert_eq!(seconds, [0., 60., 50., 0.]);
+
+    let seconds = histograms
+        .activity
+        .time_in_zones(ZoneKind::Power, &[150., 250.]);
+    assert_eq!(seconds, [60., 0., 50.]);
+
+    assert_eq!(histograms.lap.len(), 2);
+    let first = histograms.lap[0].time_in_zones(ZoneKind::Power, &[150., 250.]);
+    let second = histograms.lap[1].time_in_zones(ZoneKind::Power, &[150., 250.]);
+    assert_eq!(first[2], 0.);
+    assert_eq!(second[2], 50.);
+    assert_eq!(first[0] + second[0], 60.);
+}
+
+#[test]
+fn skip_activities_without_heart_rate_or_power() {
+    let activity = tf_parse::parse(
+        tcx()
+            .replace("<HeartRateBpm><Value>120</Value></HeartRateBpm>", "")
+            .replace("<HeartRateBpm><Value>160</Value></HeartRateBpm>", "")
+            .replace("<ns3:Watts>100</ns3:Watts>", "")
+            .replace("<ns3:Watts>300</ns3:Watts>", "")
+            .as_bytes(),
+    )
+    .unwrap();
+
+    assert!(histograms(&activity).is_none());
+}
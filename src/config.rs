@@ -0,0 +1,61 @@
+use crate::inbox::Inbox;
+use std::sync::Arc;
+use tf_database::query::UserQuery;
+use tf_parse::elevation::Dem;
+
+// Room for a few days of one second records with every channel recorded
+const DEFAULT_MAX_UPLOAD_SIZE: usize = 256 * 1024 * 1024;
+const DEFAULT_MAX_IMPORT_SIZE: u64 = 16 * 1024 * 1024 * 1024;
+
+/// Settings read from the environment at startup.
+#[derive(Clone)]
+pub struct Config {
+    /// Largest accepted upload in bytes, set with `TF_MAX_UPLOAD_SIZE`.
+    pub max_upload_size: usize,
+    /// Largest accepted archive for bulk imports in bytes, set with
+    /// `TF_MAX_IMPORT_SIZE`. Archives are kept on disk while imported.
+    pub max_import_size: u64,
+    /// Directories watched for new activity files, set with `TF_INBOX` as a
+    /// list of `<user id>=<directory>`, separated like `PATH`.
+    pub inboxes: Vec<Inbox>,
+    /// Elevation model to correct the altitude of activities with, set with
+    /// `TF_DEM` as a directory of SRTM or Copernicus tiles.
+    pub dem: Option<Arc<Dem>>,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Self {
+            max_upload_size: std::env::var("TF_MAX_UPLOAD_SIZE")
+                .ok()
+                .and_then(|x| x.parse().ok())
+                .unwrap_or(DEFAULT_MAX_UPLOAD_SIZE),
+            max_import_size: std::env::var("TF_MAX_IMPORT_SIZE")
+                .ok()
+                .and_then(|x| x.parse().ok())
+                .unwrap_or(DEFAULT_MAX_IMPORT_SIZE),
+            inboxes: std::env::var_os("TF_INBOX")
+                .map(|x| std::env::split_paths(&x).map(inbox).collect())
+                .unwrap_or_default(),
+            dem: std::env::var_os("TF_DEM").map(|x| {
+                Arc::new(Dem::open(x).expect("TF_DEM should be a directory of elevation tiles"))
+            }),
+        }
+    }
+}
+
+fn inbox(entry: std::path::PathBuf) -> Inbox {
+    let entry = entry.to_string_lossy();
+    let (user_id, path) = entry
+        .split_once('=')
+        .expect("TF_INBOX entries should be <user id>=<directory>");
+
+    Inbox {
+        user: UserQuery {
+            user_id: user_id
+                .parse()
+                .expect("TF_INBOX should name valid user ids"),
+        },
+        path: path.into(),
+    }
+}
@@ -0,0 +1,280 @@
+use crate::error::{Error, Result};
+use std::{
+    fs,
+    io::{self, BufReader, Cursor, Read, Seek, Write},
+};
+use zip::ZipArchive;
+
+// Compressed files within compressed files are unpacked up to this depth,
+// e.g. a gzipped FIT file in a ZIP archive. Imports open archives within
+// archives up to the same depth.
+pub(crate) const MAX_DEPTH: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Compression {
+    Gzip,
+    Zstd,
+    Zip,
+}
+
+impl Compression {
+    /// The compression named by a `Content-Encoding` header. Fails for
+    /// encodings that are not supported.
+    fn from_content_encoding(value: &str) -> Result<Option<Self>> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "identity" => Ok(None),
+            "gzip" | "x-gzip" => Ok(Some(Self::Gzip)),
+            "zstd" => Ok(Some(Self::Zstd)),
+            _ => Err(Error::UnsupportedEncoding),
+        }
+    }
+
+    /// Sniffs the compression from the leading bytes of the data.
+    fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&[0x1F, 0x8B]) {
+            Some(Self::Gzip)
+        } else if data.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+            Some(Self::Zstd)
+        } else if data.starts_with(b"PK\x03\x04") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+
+    /// Sniffs the compression from the leading bytes of a file, which is
+    /// left at its start.
+    fn detect_file(file: &mut fs::File) -> Result<Option<Self>> {
+        let mut head = Vec::with_capacity(4);
+        Read::by_ref(file).take(4).read_to_end(&mut head)?;
+        file.rewind()?;
+
+        Ok(Self::detect(&head))
+    }
+
+    /// The compression given by the `Content-Encoding` of an upload, if any.
+    fn from_upload(content_encoding: Option<&str>) -> Result<Option<Self>> {
+        match content_encoding {
+            Some(value) => Self::from_content_encoding(value),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Copies all of `reader` to `writer`, failing with `Error::PayloadTooLarge`
+/// if it is larger than `limit`, so that a small compressed file can not
+/// expand without bounds.
+pub fn copy_limited<R: Read, W: Write>(reader: R, writer: &mut W, limit: u64) -> Result<()> {
+    let copied = io::copy(&mut reader.take(limit + 1), writer)?;
+
+    if copied > limit {
+        return Err(Error::PayloadTooLarge);
+    }
+
+    Ok(())
+}
+
+/// Reads all of `reader`, failing with `Error::PayloadTooLarge` if it is
+/// larger than `limit`.
+pub fn read_limited<R: Read>(reader: R, limit: usize) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    copy_limited(reader, &mut data, limit as u64)?;
+
+    Ok(data)
+}
+
+/// Unpacks one layer of compression.
+fn unpack<R, W>(reader: R, compression: Compression, writer: &mut W, limit: usize) -> Result<()>
+where
+    R: Read + Seek,
+    W: Write,
+{
+    match compression {
+        Compression::Gzip => {
+            copy_limited(flate2::read::GzDecoder::new(reader), writer, limit as u64)
+        }
+        Compression::Zstd => copy_limited(
+            zstd::stream::read::Decoder::new(reader)?,
+            writer,
+            limit as u64,
+        ),
+        Compression::Zip => unzip(reader, writer, limit),
+    }
+}
+
+/// Unpacks an uploaded file compressed with gzip or zstd, or put alone in a
+/// ZIP archive. The compression is given by the `Content-Encoding` of the
+/// upload if any, and otherwise found from the data. Data that is not
+/// compressed is returned as is. Fails with `Error::PayloadTooLarge` if the
+/// file unpacks to more than `limit` bytes.
+pub fn decompress(
+    mut data: Vec<u8>,
+    content_encoding: Option<&str>,
+    limit: usize,
+) -> Result<Vec<u8>> {
+    let mut compression =
+        Compression::from_upload(content_encoding)?.or_else(|| Compression::detect(&data));
+
+    for _ in 0..MAX_DEPTH {
+        let compression_of_data = match compression {
+            Some(compression) => compression,
+            None => break,
+        };
+
+        let mut unpacked = Vec::new();
+        unpack(
+            Cursor::new(data.as_slice()),
+            compression_of_data,
+            &mut unpacked,
+            limit,
+        )?;
+        data = unpacked;
+
+        compression = Compression::detect(&data);
+    }
+
+    Ok(data)
+}
+
+/// Like `decompress`, but for an upload kept in a file. Each layer is
+/// unpacked to a temporary file of its own, so that the file is never held
+/// in memory. The returned file is at its start.
+pub fn decompress_file(
+    mut file: fs::File,
+    content_encoding: Option<&str>,
+    limit: usize,
+) -> Result<fs::File> {
+    file.rewind()?;
+
+    let mut compression = match Compression::from_upload(content_encoding)? {
+        Some(compression) => Some(compression),
+        None => Compression::detect_file(&mut file)?,
+    };
+
+    for _ in 0..MAX_DEPTH {
+        let compression_of_file = match compression {
+            Some(compression) => compression,
+            None => break,
+        };
+
+        let mut unpacked = tempfile::tempfile()?;
+        unpack(
+            BufReader::new(&mut file),
+            compression_of_file,
+            &mut unpacked,
+            limit,
+        )?;
+        unpacked.rewind()?;
+        file = unpacked;
+
+        compression = Compression::detect_file(&mut file)?;
+    }
+
+    Ok(file)
+}
+
+/// Unpacks the only file in a ZIP archive. Folders and the metadata that
+/// macOS adds to archives are skipped.
+fn unzip<R, W>(reader: R, writer: &mut W, limit: usize) -> Result<()>
+where
+    R: Read + Seek,
+    W: Write,
+{
+    let mut archive = ZipArchive::new(reader)?;
+
+    let files = archive
+        .file_names()
+        .filter(|name| !name.ends_with('/') && !name.starts_with("__MACOSX/"))
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    match files.as_slice() {
+        [name] => copy_limited(archive.by_name(name)?, writer, limit as u64),
+        _ => Err(zip::result::ZipError::InvalidArchive("expected a single file").into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const DATA: &[u8] = b"<?xml version=\"1.0\"?><gpx></gpx>";
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            writer.start_file(*name, Default::default()).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn decompress_by_magic_bytes() {
+        let zstd = zstd::encode_all(DATA, 0).unwrap();
+        let nested = zip(&[("run.gpx.gz", &gzip(DATA)), ("__MACOSX/._run.gpx.gz", b"")]);
+
+        assert_eq!(decompress(DATA.to_vec(), None, 1024).unwrap(), DATA);
+        assert_eq!(decompress(gzip(DATA), None, 1024).unwrap(), DATA);
+        assert_eq!(decompress(zstd, None, 1024).unwrap(), DATA);
+        assert_eq!(decompress(nested, None, 1024).unwrap(), DATA);
+    }
+
+    #[test]
+    fn decompress_by_content_encoding() {
+        let zstd = zstd::encode_all(DATA, 0).unwrap();
+
+        assert_eq!(decompress(zstd, Some("zstd"), 1024).unwrap(), DATA);
+        assert_eq!(
+            decompress(gzip(DATA), Some("identity"), 1024).unwrap(),
+            DATA
+        );
+        assert!(matches!(
+            decompress(DATA.to_vec(), Some("br"), 1024),
+            Err(Error::UnsupportedEncoding)
+        ));
+    }
+
+    #[test]
+    fn reject_bombs_and_archives() {
+        let bomb = gzip(&vec![0; 1 << 20]);
+
+        assert!(matches!(
+            decompress(bomb, None, 1 << 16),
+            Err(Error::PayloadTooLarge)
+        ));
+        assert!(matches!(
+            decompress(zip(&[("a.gpx", DATA), ("b.gpx", DATA)]), None, 1024),
+            Err(Error::Archive { .. })
+        ));
+    }
+
+    #[test]
+    fn decompress_files() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&zip(&[("run.gpx.gz", &gzip(DATA))]))
+            .unwrap();
+
+        let mut data = Vec::new();
+        decompress_file(file, None, 1024)
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, DATA);
+
+        let mut bomb = tempfile::tempfile().unwrap();
+        bomb.write_all(&gzip(&vec![0; 1 << 20])).unwrap();
+
+        assert!(matches!(
+            decompress_file(bomb, Some("gzip"), 1 << 16),
+            Err(Error::PayloadTooLarge)
+        ));
+    }
+}
@@ -1,7 +1,8 @@
 use axum::{
     http::StatusCode,
-    response::{IntoResponse, Response},
+    response::{IntoResponse, Json, Response},
 };
+use tf_database::query::ActivityQuery;
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -13,25 +14,84 @@ pub enum Error {
         #[from]
         source: tf_database::error::Error,
     },
-    #[error("Parse error.")]
+    #[error("Parse error: {source}")]
     Parse {
         #[from]
         source: tf_parse::error::Error,
     },
+    #[error("Parse error: {source}")]
+    Rejected {
+        source: tf_parse::error::Error,
+        report: tf_parse::Report,
+    },
+    #[error("Invalid archive: {source}")]
+    Archive {
+        #[from]
+        source: zip::result::ZipError,
+    },
+    #[error("{source}")]
+    Io {
+        #[from]
+        source: std::io::Error,
+    },
     #[error("Not found")]
     NotFound,
 
+    #[error("Bad request")]
+    BadRequest,
+
+    #[error("Conflict")]
+    Conflict,
+
+    #[error("Too many activities started at {start_time}")]
+    SequenceExhausted { start_time: String },
+
+    #[error("Payload too large")]
+    PayloadTooLarge,
+
+    #[error("Unsupported content encoding")]
+    UnsupportedEncoding,
+
+    #[error("Duplicate of {existing}")]
+    Duplicate { existing: ActivityQuery },
+
     #[error("{source}")]
     JoinError {
         #[from]
         source: tokio::task::JoinError,
     },
+    #[error("{source}")]
+    RecvError {
+        #[from]
+        source: tokio::sync::oneshot::error::RecvError,
+    },
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
+        if let Self::Duplicate { existing } = self {
+            return (StatusCode::CONFLICT, Json(existing)).into_response();
+        }
+
+        if let Self::Rejected { source, report } = self {
+            let status_code = match source {
+                tf_parse::error::Error::UnknownFormat => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                _ => StatusCode::UNPROCESSABLE_ENTITY,
+            };
+
+            return (status_code, Json(report)).into_response();
+        }
+
         let status_code = match self {
             Self::NotFound => StatusCode::NOT_FOUND,
+            Self::BadRequest => StatusCode::BAD_REQUEST,
+            Self::Conflict | Self::SequenceExhausted { .. } => StatusCode::CONFLICT,
+            Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::UnsupportedEncoding => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::Archive { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::Parse {
+                source: tf_parse::error::Error::UnknownFormat,
+            } => StatusCode::UNSUPPORTED_MEDIA_TYPE,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
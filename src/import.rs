@@ -0,0 +1,549 @@
+use crate::{
+    decompress::{copy_limited, decompress, read_limited, MAX_DEPTH},
+    error::{Error, Result},
+    ingest,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Read, Seek},
+    sync::Mutex,
+};
+use tf_database::{
+    query::{ActivityQuery, GearQuery, UserQuery},
+    Database,
+};
+use tf_events::{Broker, ImportEvent};
+use tf_models::{
+    activity::{Details, File, Session},
+    gear::{Gear, GearType},
+    import::{ImportFailure, ImportProgress, ImportStatus},
+    user::User,
+    Activity, GearId, Sport, UserId,
+};
+use tf_parse::elevation::Dem;
+use zip::ZipArchive;
+
+// Users with an import in progress
+static RUNNING: Mutex<Option<HashSet<UserId>>> = Mutex::new(None);
+
+/// Marks an import of a user as running until dropped.
+pub struct Running(UserId);
+
+impl Running {
+    /// Fails if the user already has an import running, as their progress
+    /// would be mixed up.
+    pub fn begin(user: UserId) -> Option<Self> {
+        RUNNING
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashSet::new)
+            .insert(user)
+            .then_some(Self(user))
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if let Some(running) = RUNNING.lock().unwrap().as_mut() {
+            running.remove(&self.0);
+        }
+    }
+}
+
+/// Name, description and gear of an activity from Strava's `activities.csv`.
+struct Metadata {
+    name: Option<String>,
+    description: Option<String>,
+    gear: Option<String>,
+}
+
+struct Import<'a> {
+    db: &'a Database,
+    broker: &'a Broker,
+    user: UserQuery,
+    // Largest size an entry may decompress to
+    limit: usize,
+    // Bytes that the archives within the archive may still take up, all
+    // together
+    archive_budget: u64,
+    dem: Option<&'a Dem>,
+    progress: ImportProgress,
+    // Metadata by path in the archive
+    metadata: HashMap<String, Metadata>,
+    // Gear of the user by name, loaded when first needed
+    gear: Option<HashMap<String, GearQuery>>,
+}
+
+/// Imports the activity files of a Strava or Garmin Connect export, or any
+/// other ZIP archive of FIT, GPX and TCX files, optionally gzipped. Archives
+/// within the archive, as in Garmin Connect exports, are opened as well, up
+/// to `MAX_DEPTH` and `archive_limit` bytes between them. Progress is
+/// published as an `ImportEvent` after each file.
+pub fn run<R: Read + Seek>(
+    db: &Database,
+    broker: &Broker,
+    user: &UserQuery,
+    archive: R,
+    limit: usize,
+    archive_limit: u64,
+    dem: Option<&Dem>,
+    _running: Running,
+) -> ImportProgress {
+    let mut import = Import {
+        db,
+        broker,
+        user: *user,
+        limit,
+        archive_budget: archive_limit,
+        dem,
+        progress: ImportProgress {
+            status: ImportStatus::Running,
+            ..Default::default()
+        },
+        metadata: HashMap::new(),
+        gear: None,
+    };
+
+    import.publish();
+
+    match import.archive(archive, 1) {
+        Ok(()) => import.progress.status = ImportStatus::Finished,
+        Err(error) => {
+            import.progress.status = ImportStatus::Failed;
+            import.progress.error = Some(error.to_string());
+        }
+    }
+
+    import.publish();
+    import.progress
+}
+
+impl Import<'_> {
+    fn publish(&self) {
+        let _ = self
+            .broker
+            .publish::<ImportEvent>(self.user.user_id, self.progress.clone());
+    }
+
+    fn fail(&mut self, file: &str, reason: String) {
+        self.progress.failed.push(ImportFailure {
+            file: file.to_string(),
+            reason,
+        });
+    }
+
+    /// Imports the files of an archive at a `depth` of nesting, starting at
+    /// one.
+    fn archive<R: Read + Seek>(&mut self, reader: R, depth: usize) -> Result<()> {
+        let mut archive = ZipArchive::new(reader)?;
+
+        if let Ok(entry) = archive.by_name("activities.csv") {
+            match read_limited(entry, self.limit).and_then(|x| Ok(metadata(&x)?)) {
+                Ok(metadata) => self.metadata.extend(metadata),
+                Err(error) => self.fail("activities.csv", error.to_string()),
+            }
+        }
+
+        self.progress.total += archive.file_names().filter(|x| activity_file(x)).count();
+        self.publish();
+
+        for index in 0..archive.len() {
+            let mut entry = archive.by_index(index)?;
+            let name = entry.name().to_string();
+
+            if entry.is_dir() {
+                continue;
+            }
+
+            if name.to_lowercase().ends_with(".zip") {
+                if depth >= MAX_DEPTH {
+                    let error = zip::result::ZipError::UnsupportedArchive("nested too deeply");
+                    self.fail(&name, Error::from(error).to_string());
+                    self.publish();
+                    continue;
+                }
+
+                // Archives need to be seekable, so they are copied out first
+                let mut nested = tempfile::tempfile()?;
+                let copied = copy_limited(&mut entry, &mut nested, self.archive_budget)
+                    .and_then(|()| Ok(nested.metadata()?.len()));
+                drop(entry);
+
+                let result = copied.and_then(|size| {
+                    self.archive_budget -= size;
+                    self.archive(nested, depth + 1)
+                });
+
+                if let Err(error) = result {
+                    self.fail(&name, error.to_string());
+                    self.publish();
+                }
+            } else if activity_file(&name) {
+                match read_limited(entry, self.limit)
+                    .and_then(|data| decompress(data, None, self.limit))
+                {
+                    Ok(data) => self.file(&name, data),
+                    Err(error) => self.fail(&name, error.to_string()),
+                }
+                self.publish();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn file(&mut self, name: &str, data: Vec<u8>) {
+        let file = File { data };
+
+        let result = ingest::parse(&file, false).and_then(|(activity, _)| {
+            let query = ingest::insert(self.db, &self.user, &activity, &file, self.dem)?;
+            self.apply_metadata(name, &query, &activity)
+        });
+
+        match result {
+            Ok(()) => self.progress.imported += 1,
+            Err(Error::Duplicate { .. }) => self.progress.duplicates += 1,
+            Err(error) => self.fail(name, error.to_string()),
+        }
+    }
+
+    fn apply_metadata(
+        &mut self,
+        name: &str,
+        query: &ActivityQuery,
+        activity: &Activity,
+    ) -> Result<()> {
+        let metadata = match self.metadata.remove(name) {
+            Some(metadata) => metadata,
+            None => return Ok(()),
+        };
+
+        let root = self.db.root::<User>()?;
+
+        if metadata.name.is_some() || metadata.description.is_some() {
+            root.traverse::<Details>()?.insert(
+                query,
+                &Details {
+                    name: metadata.name,
+                    description: metadata.description,
+                },
+                &self.user,
+            )?;
+        }
+
+        if let Some(gear) = metadata.gear {
+            if let Some(gear) = self.gear(gear, activity.session.sport)? {
+                root.traverse::<Session>()?
+                    .traverse::<Gear>(query)?
+                    .link(query, &gear)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The gear of the user with the given name, created if missing. Gear is
+    /// not created for sports without a type of gear, such as swimming.
+    fn gear(&mut self, name: String, sport: Sport) -> Result<Option<GearQuery>> {
+        let collection = self.db.root::<User>()?.traverse::<Gear>()?;
+
+        if self.gear.is_none() {
+            let total_count = collection.keys(&self.user, 0, 0, false)?.total_count;
+            let mut gear = HashMap::new();
+
+            for query in collection.keys(&self.user, 0, total_count, false)? {
+                if let Some(existing) = collection.get(&query)? {
+                    gear.insert(existing.name, query);
+                }
+            }
+
+            self.gear = Some(gear);
+        }
+
+        let gear = self.gear.get_or_insert_with(HashMap::new);
+
+        if let Some(query) = gear.get(&name) {
+            return Ok(Some(*query));
+        }
+
+        let gear_type = match gear_type(sport) {
+            Some(gear_type) => gear_type,
+            None => return Ok(None),
+        };
+
+        let query = GearQuery {
+            user_id: self.user.user_id,
+            id: GearId::new(),
+        };
+
+        collection.insert(
+            &query,
+            &Gear {
+                name: name.clone(),
+                gear_type,
+            },
+            &self.user,
+        )?;
+        gear.insert(name, query);
+
+        Ok(Some(query))
+    }
+}
+
+/// The type of gear used for a sport, if any.
+fn gear_type(sport: Sport) -> Option<GearType> {
+    match sport {
+        Sport::Running | Sport::Walking | Sport::Hiking | Sport::Mountaineering => {
+            Some(GearType::RunningShoes)
+        }
+        Sport::Cycling | Sport::EBiking => Some(GearType::RoadBike),
+        _ => None,
+    }
+}
+
+fn activity_file(name: &str) -> bool {
+    let name = name.to_lowercase();
+    let name = name.strip_suffix(".gz").unwrap_or(&name);
+
+    [".fit", ".gpx", ".tcx"]
+        .iter()
+        .any(|extension| name.ends_with(extension))
+}
+
+/// Metadata by file name from the rows of `activities.csv`.
+fn metadata(data: &[u8]) -> io::Result<HashMap<String, Metadata>> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(data);
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|x| x == name);
+
+    let name = column("Activity Name");
+    let description = column("Activity Description");
+    let gear = column("Activity Gear");
+    let filename = column("Filename");
+
+    let mut metadata = HashMap::new();
+
+    for row in reader.records() {
+        let row = row?;
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|x| row.get(x))
+                .map(str::trim)
+                .filter(|x| !x.is_empty())
+                .map(String::from)
+        };
+
+        if let Some(filename) = field(filename) {
+            metadata.insert(
+                filename,
+                Metadata {
+                    name: field(name),
+                    description: field(description),
+                    gear: field(gear),
+                },
+            );
+        }
+    }
+
+    Ok(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, Point};
+    use std::io::{Cursor, Write};
+
+    fn gpx(sport: &str, hour: u32) -> Vec<u8> {
+        let points = (0..60)
+            .map(|i| Point::moving((hour - 6) * 3600 + i))
+            .collect::<Vec<_>>();
+
+        fixtures::gpx(sport, &points).into_bytes()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            writer.start_file(*name, Default::default()).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// A Strava export with a nested Garmin Connect export in it.
+    fn export() -> Vec<u8> {
+        let run = gpx("running", 6);
+        let nested = zip(&[("ride.gpx", &gpx("cycling", 10)), ("run.gpx", &run)]);
+
+        zip(&[
+            (
+                "activities.csv",
+                b"Activity ID,Activity Name,Activity Description,Activity Gear,Filename\n\
+                  1,Morning Run,Easy,Shoes,activities/run.gpx.gz\n\
+                  2,Pool,,Goggles,activities/swim.gpx\n",
+            ),
+            ("activities/run.gpx.gz", &gzip(&run)),
+            ("activities/swim.gpx", &gpx("swimming", 8)),
+            ("activities/broken.fit", b"not an activity"),
+            ("export.zip", &nested),
+            ("readme.txt", b"not an activity either"),
+        ])
+    }
+
+    fn import(
+        db: &Database,
+        broker: &Broker,
+        user: &UserQuery,
+        archive_limit: u64,
+    ) -> ImportProgress {
+        run(
+            db,
+            broker,
+            user,
+            Cursor::new(export()),
+            1 << 20,
+            archive_limit,
+            None,
+            Running::begin(user.user_id).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn import_nested_archives_with_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open(dir.path()).unwrap();
+        let broker = Broker::default();
+        let user = UserQuery {
+            user_id: UserId::new(),
+        };
+
+        let events = broker.subscribe::<ImportEvent>(user.user_id);
+        let progress = import(&db, &broker, &user, 1 << 20);
+
+        assert_eq!(progress.status, ImportStatus::Finished);
+        assert_eq!(progress.total, 5);
+        assert_eq!(progress.imported, 3);
+        assert_eq!(progress.duplicates, 1);
+        assert_eq!(progress.failed.len(), 1);
+        assert_eq!(progress.failed[0].file, "activities/broken.fit");
+
+        // The last event is the final progress
+        let published = events.borrow().clone();
+        assert_eq!(published.status, ImportStatus::Finished);
+        assert_eq!(published.imported, 3);
+
+        let root = db.root::<User>().unwrap();
+        let sessions = root.traverse::<Session>().unwrap();
+        let activities = sessions
+            .keys(&user, 0, 10, false)
+            .unwrap()
+            .collect::<Vec<_>>();
+        let activity = |sport: Sport| {
+            *activities
+                .iter()
+                .find(|x| sessions.get(x).unwrap().unwrap().sport == sport)
+                .unwrap()
+        };
+        let gear_of = |query: &ActivityQuery| {
+            sessions
+                .traverse::<Gear>(query)
+                .unwrap()
+                .get_foreign(query)
+                .unwrap()
+        };
+
+        let run = activity(Sport::Running);
+        let details = root.traverse::<Details>().unwrap().get(&run).unwrap();
+        assert_eq!(details.unwrap().name.as_deref(), Some("Morning Run"));
+
+        let shoes = gear_of(&run).unwrap();
+        let shoes = root
+            .traverse::<Gear>()
+            .unwrap()
+            .get(&shoes)
+            .unwrap()
+            .unwrap();
+        assert_eq!(shoes.name, "Shoes");
+        assert_eq!(shoes.gear_type, GearType::RunningShoes);
+
+        // Swimming has no type of gear, so none is created
+        assert!(gear_of(&activity(Sport::Swimming)).is_none());
+        assert!(gear_of(&activity(Sport::Cycling)).is_none());
+        let gear = root.traverse::<Gear>().unwrap();
+        assert_eq!(gear.keys(&user, 0, 0, false).unwrap().total_count, 1);
+    }
+
+    #[test]
+    fn reject_nested_archives_over_the_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open(dir.path()).unwrap();
+        let user = UserQuery {
+            user_id: UserId::new(),
+        };
+
+        let progress = import(&db, &Broker::default(), &user, 64);
+
+        assert_eq!(progress.status, ImportStatus::Finished);
+        assert_eq!(progress.imported, 2);
+        assert_eq!(progress.failed.len(), 2);
+        assert_eq!(progress.failed[1].file, "export.zip");
+        assert_eq!(
+            progress.failed[1].reason,
+            Error::PayloadTooLarge.to_string()
+        );
+    }
+
+    #[test]
+    fn limit_depth_and_size_of_nested_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open(dir.path()).unwrap();
+        let broker = Broker::default();
+        let user = UserQuery {
+            user_id: UserId::new(),
+        };
+        let run = |archive: Vec<u8>, archive_limit: u64| {
+            super::run(
+                &db,
+                &broker,
+                &user,
+                Cursor::new(archive),
+                1 << 20,
+                archive_limit,
+                None,
+                Running::begin(user.user_id).unwrap(),
+            )
+        };
+
+        let deepest = zip(&[("swim.gpx", &gpx("swimming", 8))]);
+        let deeper = zip(&[("deepest.zip", &deepest)]);
+        let deep = zip(&[("deeper.zip", &deeper)]);
+
+        let progress = run(zip(&[("deep.zip", &deep)]), 1 << 20);
+
+        assert_eq!(progress.imported, 0);
+        assert_eq!(progress.failed.len(), 1);
+        assert_eq!(progress.failed[0].file, "deepest.zip");
+
+        // Each fits in the limit, but not both
+        let one = zip(&[("ride.gpx", &gpx("cycling", 9))]);
+        let two = zip(&[("run.gpx", &gpx("running", 10))]);
+        let archive_limit = (one.len() + two.len() - 1) as u64;
+
+        let progress = run(zip(&[("one.zip", &one), ("two.zip", &two)]), archive_limit);
+
+        assert_eq!(progress.imported, 1);
+        assert_eq!(progress.failed.len(), 1);
+        assert_eq!(progress.failed[0].file, "two.zip");
+        assert_eq!(
+            progress.failed[0].reason,
+            Error::PayloadTooLarge.to_string()
+        );
+    }
+}
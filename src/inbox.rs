@@ -0,0 +1,332 @@
+use crate::{
+    decompress::{decompress, read_limited},
+    error::{Error, Result},
+    ingest,
+};
+use notify::{PollWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc},
+    time::{Duration, SystemTime},
+};
+use tf_database::{query::UserQuery, Database};
+use tf_models::activity::File;
+use tf_parse::elevation::Dem;
+
+// How often the inboxes are scanned without being told of changes
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+// Files modified more recently than this may still be being written
+const SETTLE: Duration = Duration::from_secs(2);
+
+/// A directory where files dropped by a user are ingested.
+#[derive(Clone)]
+pub struct Inbox {
+    pub user: UserQuery,
+    pub path: PathBuf,
+}
+
+/// Written next to a file that failed, named after it with `.error.json`
+/// added.
+#[derive(Serialize)]
+struct Sidecar {
+    error: String,
+    #[serde(flatten)]
+    report: Option<tf_parse::Report>,
+}
+
+/// Watches the inboxes of users for activity files, ingesting them the same
+/// way as uploads. Processed files are moved to `done/` in the inbox, and
+/// those that could not be ingested to `failed/`.
+pub struct Worker {
+    db: Database,
+    inboxes: Vec<Inbox>,
+    limit: usize,
+    dem: Option<Arc<Dem>>,
+    settle: Duration,
+    // Files that could not be moved out of their inbox, with the time they
+    // were modified. They are left in the inbox for the user to see, and
+    // only ingested again once changed.
+    stuck: HashMap<PathBuf, SystemTime>,
+}
+
+impl Worker {
+    pub fn new(db: Database, inboxes: Vec<Inbox>, limit: usize, dem: Option<Arc<Dem>>) -> Self {
+        Self {
+            db,
+            inboxes,
+            limit,
+            dem,
+            settle: SETTLE,
+            stuck: HashMap::new(),
+        }
+    }
+
+    pub fn spawn(self) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || self.run())
+    }
+
+    /// Scans the inboxes whenever they change. Directories that can not be
+    /// watched through the file system, such as some network shares, are
+    /// polled instead. All inboxes are also scanned every `POLL_INTERVAL`,
+    /// which picks up files that had not settled when last seen.
+    fn run(mut self) {
+        let (sender, receiver) = mpsc::channel();
+
+        let mut watcher = notify::recommended_watcher(sender.clone()).ok();
+        let mut poll_watcher = None;
+
+        for inbox in &self.inboxes {
+            let watched = watcher
+                .as_mut()
+                .map(|x| x.watch(&inbox.path, RecursiveMode::NonRecursive).is_ok())
+                .unwrap_or_default();
+
+            if !watched {
+                let poll_watcher = poll_watcher.get_or_insert_with(|| {
+                    PollWatcher::new(
+                        sender.clone(),
+                        notify::Config::default().with_poll_interval(POLL_INTERVAL),
+                    )
+                    .ok()
+                });
+
+                if let Some(poll_watcher) = poll_watcher {
+                    let _ = poll_watcher.watch(&inbox.path, RecursiveMode::NonRecursive);
+                }
+            }
+        }
+
+        loop {
+            self.scan();
+
+            // Events only tell that something changed, so they are drained
+            // and the inboxes scanned as a whole
+            let _ = receiver.recv_timeout(POLL_INTERVAL);
+            while receiver.try_recv().is_ok() {}
+        }
+    }
+
+    fn scan(&mut self) {
+        // Files that are gone or were changed are no longer stuck
+        let mut stuck = HashMap::new();
+
+        for inbox in &self.inboxes {
+            let entries = match fs::read_dir(&inbox.path) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+
+            for entry in entries.flatten() {
+                let path = entry.path();
+
+                let modified = match entry
+                    .metadata()
+                    .ok()
+                    .filter(|x| x.is_file())
+                    .and_then(|x| x.modified().ok())
+                {
+                    Some(modified) => modified,
+                    None => continue,
+                };
+
+                let settled = SystemTime::now()
+                    .duration_since(modified)
+                    .is_ok_and(|x| x >= self.settle);
+
+                if !settled || !activity_file(&path) {
+                    continue;
+                }
+
+                if self.stuck.get(&path) == Some(&modified) || self.process(inbox, &path).is_err() {
+                    stuck.insert(path, modified);
+                }
+            }
+        }
+
+        self.stuck = stuck;
+    }
+
+    fn process(&self, inbox: &Inbox, path: &Path) -> std::io::Result<()> {
+        let result = self.read(path).and_then(|file| {
+            let (activity, _) = ingest::parse(&file, false)?;
+            ingest::insert(&self.db, &inbox.user, &activity, &file, self.dem.as_deref())
+        });
+
+        let name = path.file_name().unwrap_or_default();
+
+        let error = match result {
+            Ok(_) => return move_to(path, &inbox.path.join("done"), name).map(drop),
+            Err(error) => error,
+        };
+
+        let failed = move_to(path, &inbox.path.join("failed"), name)?;
+
+        let sidecar = match error {
+            Error::Rejected { source, report } => Sidecar {
+                error: source.to_string(),
+                report: Some(report),
+            },
+            error => Sidecar {
+                error: error.to_string(),
+                report: None,
+            },
+        };
+
+        let mut sidecar_path = failed.into_os_string();
+        sidecar_path.push(".error.json");
+
+        fs::write(sidecar_path, serde_json::to_vec_pretty(&sidecar)?)
+    }
+
+    fn read(&self, path: &Path) -> Result<File> {
+        let data = read_limited(fs::File::open(path)?, self.limit)?;
+
+        Ok(File {
+            data: decompress(data, None, self.limit)?,
+        })
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|x| x.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Whether the file is one to ingest. Hidden files, such as the temporary
+/// files of sync tools, are left alone.
+fn activity_file(path: &Path) -> bool {
+    let name = file_name(path);
+
+    !name.starts_with('.')
+        && [".fit", ".fit.gz", ".gpx", ".tcx"]
+            .iter()
+            .any(|extension| name.ends_with(extension))
+}
+
+/// Moves a file into the directory, numbering it if the name is taken.
+fn move_to(path: &Path, directory: &Path, name: &std::ffi::OsStr) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(directory)?;
+
+    let mut target = directory.join(name);
+    let mut number = 1;
+
+    while target.exists() {
+        let mut numbered = std::ffi::OsString::from(format!("{number}-"));
+        numbered.push(name);
+        target = directory.join(numbered);
+        number += 1;
+    }
+
+    fs::rename(path, &target)?;
+
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, Point};
+    use tf_models::UserId;
+
+    fn gpx() -> String {
+        fixtures::gpx("running", &[0, 5, 10].map(Point::moving))
+    }
+
+    fn worker(dir: &Path) -> (Worker, Inbox) {
+        let inbox = Inbox {
+            user: UserQuery {
+                user_id: UserId::new(),
+            },
+            path: dir.join("inbox"),
+        };
+        fs::create_dir_all(&inbox.path).unwrap();
+
+        let worker = Worker {
+            db: Database::open(dir.join("db")).unwrap(),
+            inboxes: vec![inbox.clone()],
+            limit: 1 << 20,
+            dem: None,
+            settle: Duration::ZERO,
+            stuck: HashMap::new(),
+        };
+
+        (worker, inbox)
+    }
+
+    #[test]
+    fn ingest_dropped_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut worker, inbox) = worker(dir.path());
+
+        fs::write(inbox.path.join("run.gpx"), gpx()).unwrap();
+        fs::write(inbox.path.join("broken.fit"), b"not a fit file").unwrap();
+        fs::write(inbox.path.join("notes.txt"), b"left alone").unwrap();
+
+        worker.scan();
+
+        assert!(inbox.path.join("done/run.gpx").exists());
+        assert!(inbox.path.join("failed/broken.fit").exists());
+        assert!(inbox.path.join("notes.txt").exists());
+
+        let sidecar = fs::read(inbox.path.join("failed/broken.fit.error.json")).unwrap();
+        let sidecar: serde_json::Value = serde_json::from_slice(&sidecar).unwrap();
+        assert_eq!(sidecar["error"], "Unsupported file format.");
+    }
+
+    #[test]
+    fn move_duplicates_to_failed() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut worker, inbox) = worker(dir.path());
+
+        fs::write(inbox.path.join("run.gpx"), gpx()).unwrap();
+        worker.scan();
+        fs::write(inbox.path.join("run.gpx"), gpx()).unwrap();
+        worker.scan();
+
+        assert!(inbox.path.join("done/run.gpx").exists());
+        assert!(inbox.path.join("failed/run.gpx").exists());
+        assert!(inbox.path.join("failed/run.gpx.error.json").exists());
+    }
+
+    #[test]
+    fn skip_files_that_can_not_be_moved() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut worker, inbox) = worker(dir.path());
+
+        let [broken, gone] = ["broken.fit", "gone.fit"].map(|x| inbox.path.join(x));
+
+        // A file where the directory of failed files would be created
+        fs::write(inbox.path.join("failed"), b"").unwrap();
+        fs::write(&broken, b"not a fit file").unwrap();
+        fs::write(&gone, b"not a fit file").unwrap();
+
+        worker.scan();
+        assert!(worker.stuck.contains_key(&broken));
+        assert!(worker.stuck.contains_key(&gone));
+
+        fs::remove_file(inbox.path.join("failed")).unwrap();
+        fs::remove_file(&gone).unwrap();
+        worker.scan();
+
+        assert!(broken.exists());
+        assert!(!inbox.path.join("failed").exists());
+        assert!(!worker.stuck.contains_key(&gone));
+
+        // Ingested again once changed
+        fs::File::options()
+            .write(true)
+            .open(&broken)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(60))
+            .unwrap();
+        worker.scan();
+
+        assert!(inbox.path.join("failed/broken.fit").exists());
+        assert!(worker.stuck.is_empty());
+    }
+}
@@ -0,0 +1,760 @@
+use crate::error::{Error, Result};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    io::{BufReader, Read, Seek},
+};
+use tf_database::{
+    fitness,
+    query::{ActivityQuery, FingerprintQuery, UserQuery},
+    resource::index::{ContentHash, DefaultGear, DeviceFile, ParentActivity, TopLevelActivity},
+    Database,
+};
+use tf_models::{
+    activity::{
+        BestEffort, Device, Elevation, File, FileId, Histograms, Lap, Load, Pause, PowerCurve,
+        Record, RecordRange, Session, Swim,
+    },
+    gear::Gear,
+    user::{PersonalRecords, User},
+    Activity,
+};
+use tf_parse::elevation::Dem;
+
+/// Parses a newly uploaded file. Fails with `Error::Rejected`, carrying the
+/// report of what was wrong, if it can not be parsed.
+pub fn parse(file: &File, lenient: bool) -> Result<(Activity, tf_parse::Report)> {
+    let (parsed, report) = tf_parse::parse_with_report(&file.data, lenient);
+
+    match parsed {
+        Ok(parsed) => Ok((parsed, report)),
+        Err(source) => Err(Error::Rejected { source, report }),
+    }
+}
+
+/// Like `parse`, but for an upload kept in a file, which is decoded as it is
+/// read. The file is then read into memory in full to be stored, so this
+/// needs memory for the size of the file on top of the parsed records.
+pub fn parse_file(mut file: fs::File, lenient: bool) -> Result<(Activity, tf_parse::Report, File)> {
+    let (parsed, report) = tf_parse::parse_reader(BufReader::new(&file), lenient);
+
+    let activity = match parsed {
+        Ok(activity) => activity,
+        Err(source) => return Err(Error::Rejected { source, report }),
+    };
+
+    file.rewind()?;
+    let mut data = Vec::with_capacity(file.metadata()?.len() as usize);
+    file.read_to_end(&mut data)?;
+
+    Ok((activity, report, File { data }))
+}
+
+/// Stores a newly parsed activity and the file it was parsed from. Creates
+/// the owner if missing and links the user's default gear. If another
+/// activity has the same id, the next free sequence number is used.
+///
+/// Fails with `Error::Duplicate` if the same file, or another file of the
+/// same recording, was uploaded before.
+///
+/// With an elevation model, the altitude is corrected as well, see
+/// `tf_parse::elevation::correct`.
+pub fn insert(
+    db: &Database,
+    user: &UserQuery,
+    activity: &Activity,
+    file: &File,
+    dem: Option<&Dem>,
+) -> Result<ActivityQuery> {
+    let root = db.root::<User>()?;
+
+    if !root.contains_key(user)? {
+        root.insert(
+            user,
+            &User {
+                name: user.user_id.as_str().into(),
+                ..Default::default()
+            },
+        )?;
+    }
+
+    // Held while picking a free id, so that concurrent uploads of activities
+    // with the same start time are not given the same id, and while the
+    // stress of the activity is added to the fitness of the user
+    tf_database::lock::user(user, || {
+        if let Some(existing) = find_duplicate(db, user, activity, file)? {
+            return Err(Error::Duplicate { existing });
+        }
+
+        let query = free_query(db, user, activity)?;
+
+        write(db, &query, activity, file, dem)?;
+
+        root.traverse::<File>()?.insert(&query, file, user)?;
+
+        if let Some(default_gear) = root.traverse::<DefaultGear>()?.key(user)? {
+            root.traverse::<Session>()?
+                .traverse::<Gear>(&query)?
+                .link(&query, &default_gear)?;
+        }
+
+        Ok(query)
+    })
+}
+
+/// Parses the stored file of an activity again and overwrites the parsed
+/// data in place. Links to gear are kept as they are. Files are parsed
+/// leniently, as damaged files may have been stored that way.
+pub fn reprocess(db: &Database, query: &ActivityQuery, dem: Option<&Dem>) -> Result<()> {
+    let file = db
+        .root::<User>()?
+        .traverse::<File>()?
+        .get(query)?
+        .ok_or(Error::NotFound)?;
+
+    let (activity, _) = tf_parse::parse_lenient(&file.data)?;
+
+    let user = UserQuery {
+        user_id: query.user_id,
+    };
+
+    tf_database::lock::user(&user, || write(db, query, &activity, &file, dem))
+}
+
+/// The id of the activity, with the sequence number increased until it is
+/// not taken by another activity of the user.
+fn free_query(db: &Database, user: &UserQuery, activity: &Activity) -> Result<ActivityQuery> {
+    let mut query = ActivityQuery {
+        user_id: user.user_id,
+        id: activity.id,
+    };
+
+    while db.root::<Session>()?.contains_key(&query)? {
+        query.id = query
+            .id
+            .next_sequence()
+            .ok_or_else(|| Error::SequenceExhausted {
+                start_time: activity.session.local_start_time().to_rfc3339(),
+            })?;
+    }
+
+    Ok(query)
+}
+
+/// Looks for an activity of the user with the same file content, or with a
+/// file from the same device recording. Files without a serial number can
+/// not be told apart by device, so they only match on their content.
+fn find_duplicate(
+    db: &Database,
+    user: &UserQuery,
+    activity: &Activity,
+    file: &File,
+) -> Result<Option<ActivityQuery>> {
+    let root = db.root::<User>()?;
+
+    let content = FingerprintQuery {
+        user_id: user.user_id,
+        fingerprint: content_hash(file),
+    };
+
+    // Ids of deleted activities may be reused, so the stored data is
+    // compared as well.
+    if let Some(existing) = root.traverse::<ContentHash>()?.key(&content)? {
+        let stored = root.traverse::<File>()?.get(&existing)?;
+
+        if stored.map(|x| x.data).as_ref() == Some(&file.data) {
+            return Ok(Some(existing));
+        }
+    }
+
+    if let Some((file_id, fingerprint)) = activity
+        .file_id
+        .as_ref()
+        .and_then(|x| Some((x, device_hash(x)?)))
+    {
+        let device = FingerprintQuery {
+            user_id: user.user_id,
+            fingerprint,
+        };
+
+        if let Some(existing) = root.traverse::<DeviceFile>()?.key(&device)? {
+            if root.traverse::<FileId>()?.get(&existing)?.as_ref() == Some(file_id) {
+                return Ok(Some(existing));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+fn content_hash(file: &File) -> [u8; 32] {
+    Sha256::digest(&file.data).into()
+}
+
+/// Hashes the fields of a `FileId` that identify a single recording. Files
+/// without a serial number or creation time can not be told apart. FIT
+/// leaves the serial number at zero when it is not known.
+fn device_hash(file_id: &FileId) -> Option<[u8; 32]> {
+    let serial_number = file_id.serial_number.filter(|x| *x != 0)?;
+    let time_created = file_id.time_created?;
+
+    let mut hasher = Sha256::new();
+
+    hasher.update(file_id.manufacturer.as_deref().unwrap_or_default());
+    hasher.update([0_u8]);
+    hasher.update(file_id.product.as_deref().unwrap_or_default());
+    hasher.update([0_u8]);
+    hasher.update(serial_number.to_le_bytes());
+    hasher.update(time_created.timestamp().to_le_bytes());
+
+    Some(hasher.finalize().into())
+}
+
+fn write(
+    db: &Database,
+    query: &ActivityQuery,
+    activity: &Activity,
+    file: &File,
+    dem: Option<&Dem>,
+) -> Result<()> {
+    let root = db.root::<User>()?;
+    let user = UserQuery {
+        user_id: query.user_id,
+    };
+
+    // Takes away the stress of the activity as it was, when reprocessing
+    fitness::count_stress(db, query, -1.)?;
+
+    root.traverse::<Session>()?
+        .insert(query, &activity.session, &user)?;
+
+    root.traverse::<TopLevelActivity>()?.insert(query, query)?;
+
+    root.traverse::<Record>()?
+        .insert(query, &activity.record, &user)?;
+
+    root.traverse::<Vec<Lap>>()?
+        .insert(query, &activity.lap, &user)?;
+
+    root.traverse::<Vec<Pause>>()?
+        .insert(query, &activity.pauses, &user)?;
+
+    write_swim(db, query, activity)?;
+
+    write_elevation(db, query, activity, dem)?;
+
+    write_power_curve(db, query, activity)?;
+
+    write_best_efforts(db, query, activity)?;
+
+    write_load(db, query, activity)?;
+
+    fitness::count_stress(db, query, 1.)?;
+
+    write_histograms(db, query, activity)?;
+
+    root.traverse::<Vec<Device>>()?
+        .insert(query, &activity.devices, &user)?;
+
+    write_children(db, query, activity, dem)?;
+
+    root.traverse::<ContentHash>()?.insert(
+        &FingerprintQuery {
+            user_id: query.user_id,
+            fingerprint: content_hash(file),
+        },
+        query,
+    )?;
+
+    if let Some(file_id) = &activity.file_id {
+        root.traverse::<FileId>()?.insert(query, file_id, &user)?;
+
+        if let Some(fingerprint) = device_hash(file_id) {
+            root.traverse::<DeviceFile>()?.insert(
+                &FingerprintQuery {
+                    user_id: query.user_id,
+                    fingerprint,
+                },
+                query,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces the child activities of a multisport activity.
+fn write_children(
+    db: &Database,
+    parent: &ActivityQuery,
+    activity: &Activity,
+    dem: Option<&Dem>,
+) -> Result<()> {
+    let root = db.root::<User>()?;
+    let index = root.traverse::<ParentActivity>()?;
+    let user = UserQuery {
+        user_id: parent.user_id,
+    };
+
+    tf_database::activity::remove_children(db, parent)?;
+
+    for child in &activity.children {
+        let query = free_query(db, &user, child)?;
+
+        root.traverse::<Session>()?
+            .insert(&query, &child.session, &user)?;
+
+        // The record is that of the parent, see `tf_database::activity::record`
+        if let Some(range) = &child.record_range {
+            root.traverse::<RecordRange>()?
+                .insert(&query, range, &user)?;
+        }
+
+        root.traverse::<Vec<Lap>>()?
+            .insert(&query, &child.lap, &user)?;
+
+        root.traverse::<Vec<Pause>>()?
+            .insert(&query, &child.pauses, &user)?;
+
+        write_swim(db, &query, child)?;
+
+        write_elevation(db, &query, child, dem)?;
+
+        write_power_curve(db, &query, child)?;
+
+        write_best_efforts(db, &query, child)?;
+
+        write_load(db, &query, child)?;
+
+        write_histograms(db, &query, child)?;
+
+        index.insert(&query, parent)?;
+    }
+
+    Ok(())
+}
+
+fn write_swim(db: &Database, query: &ActivityQuery, activity: &Activity) -> Result<()> {
+    let collection = db.root::<User>()?.traverse::<Swim>()?;
+
+    match &activity.swim {
+        Some(swim) => collection.insert(
+            query,
+            swim,
+            &UserQuery {
+                user_id: query.user_id,
+            },
+        )?,
+        None => {
+            collection.remove(query)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_power_curve(db: &Database, query: &ActivityQuery, activity: &Activity) -> Result<()> {
+    let collection = db.root::<User>()?.traverse::<PowerCurve>()?;
+
+    match tf_parse::power::curve(&activity.record) {
+        Some(curve) => collection.insert(
+            query,
+            &curve,
+            &UserQuery {
+                user_id: query.user_id,
+            },
+        )?,
+        None => {
+            collection.remove(query)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_load(db: &Database, query: &ActivityQuery, activity: &Activity) -> Result<()> {
+    let root = db.root::<User>()?;
+    let user = UserQuery {
+        user_id: query.user_id,
+    };
+
+    let load = tf_parse::load::load(activity, root.get(&user)?.as_ref());
+
+    root.traverse::<Load>()?.insert(query, &load, &user)?;
+
+    Ok(())
+}
+
+fn write_histograms(db: &Database, query: &ActivityQuery, activity: &Activity) -> Result<()> {
+    let collection = db.root::<User>()?.traverse::<Histograms>()?;
+
+    match tf_parse::zones::histograms(activity) {
+        Some(histograms) => collection.insert(
+            query,
+            &histograms,
+            &UserQuery {
+                user_id: query.user_id,
+            },
+        )?,
+        None => {
+            collection.remove(query)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Stores the best efforts of a run and adds those that are records to the
+/// personal records of the user.
+fn write_best_efforts(db: &Database, query: &ActivityQuery, activity: &Activity) -> Result<()> {
+    tf_database::activity::remove_best_efforts(db, query)?;
+
+    let efforts = tf_parse::effort::best_efforts(activity);
+
+    if efforts.is_empty() {
+        return Ok(());
+    }
+
+    let root = db.root::<User>()?;
+    let user = UserQuery {
+        user_id: query.user_id,
+    };
+
+    root.traverse::<Vec<BestEffort>>()?
+        .insert(query, &efforts, &user)?;
+
+    let collection = root.traverse::<PersonalRecords>()?;
+    let mut records = collection.get(&user)?.unwrap_or_default();
+
+    records.insert(query.id, activity.session.start_time, &efforts);
+
+    collection.insert(&user, &records)?;
+
+    Ok(())
+}
+
+/// Stores the altitude from the elevation model. Without a model, the stored
+/// altitude is left as it is, so that it survives reprocessing.
+fn write_elevation(
+    db: &Database,
+    query: &ActivityQuery,
+    activity: &Activity,
+    dem: Option<&Dem>,
+) -> Result<()> {
+    let dem = match dem {
+        Some(dem) => dem,
+        None => return Ok(()),
+    };
+
+    let collection = db.root::<User>()?.traverse::<Elevation>()?;
+
+    match tf_parse::elevation::correct(activity, dem) {
+        Some(elevation) => collection.insert(
+            query,
+            &elevation,
+            &UserQuery {
+                user_id: query.user_id,
+            },
+        )?,
+        None => {
+            collection.remove(query)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, fit::Ride, Point};
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+    };
+    use tf_database::query::GearQuery;
+    use tf_models::{
+        user::{Fitness, Threshold, Thresholds},
+        ActivityId, GearId, UserId,
+    };
+
+    // Counts the bytes allocated by each thread, as tests run side by side
+    struct Counting;
+
+    thread_local! {
+        static ALLOCATED: Cell<isize> = const { Cell::new(0) };
+        static PEAK: Cell<isize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = System.alloc(layout);
+            if !ptr.is_null() {
+                let _ = ALLOCATED.try_with(|allocated| {
+                    allocated.set(allocated.get() + layout.size() as isize);
+                    let _ = PEAK.try_with(|peak| peak.set(peak.get().max(allocated.get())));
+                });
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+            let _ = ALLOCATED
+                .try_with(|allocated| allocated.set(allocated.get() - layout.size() as isize));
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: Counting = Counting;
+
+    fn gpx(seconds: std::ops::Range<u32>) -> File {
+        let points = seconds
+            .map(|i| Point {
+                heartrate: Some(150),
+                ..Point::moving(i)
+            })
+            .collect::<Vec<_>>();
+
+        File {
+            data: fixtures::gpx("running", &points).into_bytes(),
+        }
+    }
+
+    #[test]
+    fn attribute_multisport_stress_and_gear_to_parent() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open(dir.path()).unwrap();
+        let user = UserQuery {
+            user_id: UserId::new(),
+        };
+
+        let root = db.root::<User>().unwrap();
+        root.insert(&user, &User::default()).unwrap();
+
+        let gear = GearQuery {
+            user_id: user.user_id,
+            id: GearId::new(),
+        };
+        root.traverse::<Gear>()
+            .unwrap()
+            .insert(&gear, &Gear::default(), &user)
+            .unwrap();
+        root.traverse::<DefaultGear>()
+            .unwrap()
+            .insert(&user, &gear)
+            .unwrap();
+
+        let mut thresholds = Thresholds::default();
+        thresholds.set(Threshold {
+            effective_from: "2022-01-01".parse().unwrap(),
+            ftp: None,
+            threshold_pace: None,
+            lthr: Some(170),
+        });
+        root.traverse::<Thresholds>()
+            .unwrap()
+            .insert(&user, &thresholds)
+            .unwrap();
+
+        let file = gpx(0..120);
+        let (mut activity, _) = parse(&file, false).unwrap();
+
+        for range in [0..60, 60..120] {
+            let (mut child, _) = parse(&gpx(range.clone()), false).unwrap();
+            child.record_range = Some(RecordRange {
+                start: range.start as usize,
+                end: range.end as usize,
+            });
+            activity.children.push(child);
+        }
+
+        let parent = insert(&db, &user, &activity, &file, None).unwrap();
+
+        let children = root
+            .traverse::<ParentActivity>()
+            .unwrap()
+            .join(&parent, 0, 10, false)
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(children.len(), 2);
+
+        // Only the parent is listed with the activities of the user
+        let top_level = root
+            .traverse::<TopLevelActivity>()
+            .unwrap()
+            .keys(&user, 0, 10, false)
+            .unwrap()
+            .map(|x| x.id)
+            .collect::<Vec<_>>();
+        assert_eq!(top_level, [parent.id]);
+
+        let gear_of = |query: &ActivityQuery| {
+            root.traverse::<Session>()
+                .unwrap()
+                .traverse::<Gear>(query)
+                .unwrap()
+                .get_foreign(query)
+                .unwrap()
+        };
+
+        assert!(gear_of(&parent).is_some());
+
+        for child in &children {
+            assert!(gear_of(child).is_none());
+            // Each sport has a load, but its stress is counted in the parent
+            assert!(root
+                .traverse::<Load>()
+                .unwrap()
+                .get(child)
+                .unwrap()
+                .is_some());
+            assert!(root
+                .traverse::<Record>()
+                .unwrap()
+                .get(child)
+                .unwrap()
+                .is_none());
+
+            let record = tf_database::activity::record(&db, child).unwrap().unwrap();
+            assert_eq!(record.timestamp.len(), 60);
+        }
+
+        let load = root.traverse::<Load>().unwrap().get(&parent).unwrap();
+        let session = root.traverse::<Session>().unwrap().get(&parent).unwrap();
+        let (date, stress) = thresholds
+            .stress(&load.unwrap(), &session.unwrap())
+            .unwrap();
+
+        let fitness = root.traverse::<Fitness>().unwrap().get(&user).unwrap();
+        assert!((fitness.unwrap().day(date).stress - stress).abs() < 1e-9);
+
+        // Removing the parent removes its sports and takes its stress away
+        assert!(
+            tf_database::lock::user(&user, || { tf_database::activity::remove(&db, &parent) })
+                .unwrap()
+        );
+
+        for child in &children {
+            assert!(root
+                .traverse::<Session>()
+                .unwrap()
+                .get(child)
+                .unwrap()
+                .is_none());
+        }
+
+        assert!(!root
+            .traverse::<TopLevelActivity>()
+            .unwrap()
+            .contains_key(&parent)
+            .unwrap());
+
+        let fitness = root.traverse::<Fitness>().unwrap().get(&user).unwrap();
+        assert!(fitness.unwrap().day(date).stress.abs() < 1e-9);
+    }
+
+    #[test]
+    fn detect_duplicates_by_content_and_device() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open(dir.path()).unwrap();
+        let user = UserQuery {
+            user_id: UserId::new(),
+        };
+
+        let file = gpx(0..60);
+        let (activity, _) = parse(&file, false).unwrap();
+        let first = insert(&db, &user, &activity, &file, None).unwrap();
+
+        assert!(matches!(
+            insert(&db, &user, &activity, &file, None),
+            Err(Error::Duplicate { existing }) if existing.id == first.id
+        ));
+
+        // Files of one recording match on the device only with a serial number
+        let mut seconds = 59;
+        for (serial_number, duplicate) in [(None, false), (Some(0), false), (Some(1234), true)] {
+            let file_id = FileId {
+                manufacturer: Some("garmin".into()),
+                product: Some("edge_530".into()),
+                serial_number,
+                time_created: Some(activity.session.start_time),
+            };
+
+            let mut results = Vec::new();
+            for _ in 0..2 {
+                let file = gpx(0..seconds);
+                let (mut activity, _) = parse(&file, false).unwrap();
+                activity.file_id = Some(file_id.clone());
+                results.push(insert(&db, &user, &activity, &file, None));
+                seconds -= 1;
+            }
+
+            assert!(results[0].is_ok());
+            assert_eq!(
+                matches!(results[1], Err(Error::Duplicate { .. })),
+                duplicate
+            );
+        }
+
+        // Removing the activity lets the file be uploaded again
+        assert!(
+            tf_database::lock::user(&user, || { tf_database::activity::remove(&db, &first) })
+                .unwrap()
+        );
+        assert!(insert(&db, &user, &activity, &file, None).is_ok());
+    }
+
+    #[test]
+    fn run_out_of_sequence_numbers() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open(dir.path()).unwrap();
+        let user = UserQuery {
+            user_id: UserId::new(),
+        };
+
+        // Files of different length, so they are not duplicates
+        for seconds in 60..=60 + u32::from(ActivityId::MAX_SEQUENCE) {
+            let file = gpx(0..seconds);
+            let (activity, _) = parse(&file, false).unwrap();
+            assert!(insert(&db, &user, &activity, &file, None).is_ok());
+        }
+
+        let file = gpx(0..30);
+        let (activity, _) = parse(&file, false).unwrap();
+        assert!(matches!(
+            insert(&db, &user, &activity, &file, None),
+            Err(Error::SequenceExhausted { .. })
+        ));
+    }
+
+    #[test]
+    fn parse_long_ride_from_file() {
+        // Twenty hours at 1 Hz, some 280 MB of records
+        let mut ride = Ride::new(20 * 3600);
+        let size = ride.size() as isize;
+        let mut file = tempfile::tempfile().unwrap();
+        std::io::copy(&mut ride, &mut file).unwrap();
+        file.rewind().unwrap();
+
+        // Time zones are looked up in a model that is loaded once
+        parse(&gpx(0..60), false).unwrap();
+
+        let baseline = ALLOCATED.with(Cell::get);
+        PEAK.with(|peak| peak.set(baseline));
+
+        let (activity, report, stored) = parse_file(file, false).unwrap();
+        let peak = PEAK.with(Cell::get) - baseline;
+
+        let records = activity.record.timestamp.len() as isize;
+        assert!(report.errors.is_empty());
+        assert_eq!(records, 20 * 3600);
+        assert_eq!(stored.data.len() as isize, size);
+
+        // The file is held once to be stored, next to the records
+        assert!(
+            peak < size + records * 1024,
+            "peak allocation of {peak} bytes"
+        );
+    }
+}
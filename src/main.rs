@@ -1,8 +1,18 @@
 mod cache;
+mod config;
+mod decompress;
 mod error;
+mod import;
+mod inbox;
+mod ingest;
 mod routes;
 mod state;
 
+// Activity files for tests, shared with the tests of tf-parse
+#[cfg(test)]
+#[path = "../crates/tf-parse/tests/common/mod.rs"]
+mod fixtures;
+
 /*
 #[cfg(all(target_env = "musl", target_pointer_width = "64"))]
 #[global_allocator]
@@ -20,6 +30,7 @@ use tf_graphql::Schema;
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     let database = Database::open("db").unwrap();
+    database.migrate().unwrap();
     let auth_db = tf_auth::database::Database::open("db-auth").unwrap();
 
     let state = tf_auth::State::new(auth_db.clone());
@@ -48,8 +59,21 @@ async fn main() -> std::io::Result<()> {
 
     std::fs::write("schema.graphql", &schema.sdl()).unwrap();
 
+    let config = config::Config::from_env();
+
+    if !config.inboxes.is_empty() {
+        inbox::Worker::new(
+            database.clone(),
+            config.inboxes.clone(),
+            config.max_upload_size,
+            config.dem.clone(),
+        )
+        .spawn();
+    }
+
     let state = state::AppState {
         broker,
+        config,
         cache: Default::default(),
         state,
         schema,
@@ -1,34 +1,45 @@
 use crate::{
     cache::ThumbnailCache,
+    config::Config,
+    decompress::decompress_file,
     error::{Error, Result},
+    import, ingest,
     state::AppState,
 };
 use axum::{
-    extract::{Path, State, TypedHeader},
+    body::{Body, HttpBody},
+    extract::{Path, Query, State, TypedHeader},
     headers::{ContentType, ETag, HeaderMapExt, IfNoneMatch},
-    http::{HeaderMap, StatusCode},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
     response::{IntoResponse, Json},
     routing::{get, post},
     Router,
 };
+use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use tf_auth::scopes::{Activity, Grant, Read, Write};
 use tf_database::{
     primitives::Key,
     query::{ActivityQuery, UserQuery},
-    resource::index::DefaultGear,
     Database,
 };
+use tf_events::Broker;
 use tf_models::{
-    activity::{Lap, Record, Session},
-    gear::Gear,
+    activity::{File, FileId, Lap, Session},
     user::User,
 };
+use tf_parse::elevation::Dem;
+use tokio::io::AsyncWriteExt;
 
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/", post(post_activity_index))
+        .route("/reprocess", post(post_activity_index_reprocess))
+        .route("/import", post(post_activity_import))
         .route("/:id/thumbnail", get(get_activity_thumbnail))
+        .route("/:id/export", get(get_activity_export))
+        .route("/:id/file", get(get_activity_file))
+        .route("/:id/reprocess", post(post_activity_reprocess))
 }
 
 async fn get_activity_thumbnail(
@@ -38,11 +49,7 @@ async fn get_activity_thumbnail(
     Path(query): Path<ActivityQuery>,
     header: Option<TypedHeader<IfNoneMatch>>,
 ) -> Result<impl IntoResponse> {
-    let record = db
-        .root::<User>()?
-        .traverse::<Record>()?
-        .get(&query)?
-        .ok_or(Error::NotFound)?;
+    let record = tf_database::activity::record(&db, &query)?.ok_or(Error::NotFound)?;
     let thumbnail = cache
         .get(query.as_key(), record)
         .await
@@ -65,60 +72,432 @@ async fn get_activity_thumbnail(
     Ok((headers, thumbnail.data).into_response())
 }
 
+#[derive(Deserialize)]
+struct ExportParams {
+    format: String,
+}
+
+async fn get_activity_export(
+    _: Grant<Read<Activity>>,
+    State(db): State<Database>,
+    Path(query): Path<ActivityQuery>,
+    Query(params): Query<ExportParams>,
+) -> Result<impl IntoResponse> {
+    let format =
+        tf_parse::export::Format::from_str(&params.format).map_err(|_| Error::BadRequest)?;
+
+    let activity = tokio::task::spawn_blocking(move || {
+        let root = db.root::<User>()?;
+
+        let session = root.traverse::<Session>()?.get(&query)?;
+        let record = tf_database::activity::record(&db, &query)?;
+        let lap = root.traverse::<Vec<Lap>>()?.get(&query)?;
+        let file_id = root.traverse::<FileId>()?.get(&query)?;
+
+        Ok::<_, tf_database::error::Error>(session.zip(record).map(|(session, record)| {
+            tf_models::Activity {
+                id: query.id,
+                session,
+                record,
+                lap: lap.unwrap_or_default(),
+                file_id,
+                children: Vec::new(),
+                record_range: None,
+                swim: None,
+                devices: Vec::new(),
+                pauses: Vec::new(),
+            }
+        }))
+    })
+    .await??
+    .ok_or(Error::NotFound)?;
+
+    let task = async move {
+        let (send, recv) = tokio::sync::oneshot::channel();
+
+        rayon::spawn(move || {
+            let _ = send.send(tf_parse::export::export(&activity, format));
+        });
+
+        recv.await
+    };
+
+    let data = task.await?;
+
+    let mut headers = HeaderMap::new();
+
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_str(&format!(
+            r#"attachment; filename="{}.{}""#,
+            query.id,
+            format.extension()
+        ))
+        .unwrap(),
+    );
+
+    Ok((headers, data))
+}
+
+async fn get_activity_file(
+    _: Grant<Read<Activity>>,
+    State(db): State<Database>,
+    Path(query): Path<ActivityQuery>,
+) -> Result<impl IntoResponse> {
+    let file =
+        tokio::task::spawn_blocking(move || db.root::<User>()?.traverse::<File>()?.get(&query))
+            .await??
+            .ok_or(Error::NotFound)?;
+
+    let extension = tf_parse::Format::detect(&file.data)
+        .map(|x| x.extension())
+        .unwrap_or("bin");
+
+    let mut headers = HeaderMap::new();
+
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_str(&format!(
+            r#"attachment; filename="{}.{}""#,
+            query.id, extension
+        ))
+        .unwrap(),
+    );
+
+    Ok((headers, file.data))
+}
+
+async fn post_activity_reprocess(
+    _grant: Grant<Write<Activity>>,
+    State(db): State<Database>,
+    State(config): State<Config>,
+    Path(query): Path<ActivityQuery>,
+) -> Result<impl IntoResponse> {
+    tokio::task::spawn_blocking(move || ingest::reprocess(&db, &query, config.dem.as_deref()))
+        .await??;
+
+    Ok(Json(query))
+}
+
+#[derive(Serialize)]
+struct ReprocessFailure {
+    activity: ActivityQuery,
+    error: String,
+}
+
+#[derive(Default, Serialize)]
+struct ReprocessSummary {
+    reprocessed: Vec<ActivityQuery>,
+    failed: Vec<ReprocessFailure>,
+}
+
+/// Reprocesses every stored file of the user, carrying on past those that
+/// fail.
+fn reprocess_all(db: &Database, user: &UserQuery, dem: Option<&Dem>) -> Result<ReprocessSummary> {
+    let collection = db.root::<User>()?.traverse::<File>()?;
+    let total_count = collection.keys(user, 0, 0, false)?.total_count;
+
+    let mut summary = ReprocessSummary::default();
+
+    for activity in collection.keys(user, 0, total_count, false)? {
+        match ingest::reprocess(db, &activity, dem) {
+            Ok(()) => summary.reprocessed.push(activity),
+            Err(error) => summary.failed.push(ReprocessFailure {
+                activity,
+                error: error.to_string(),
+            }),
+        }
+    }
+
+    Ok(summary)
+}
+
+async fn post_activity_index_reprocess(
+    _grant: Grant<Write<Activity>>,
+    State(db): State<Database>,
+    State(config): State<Config>,
+    Path(query): Path<UserQuery>,
+) -> Result<impl IntoResponse> {
+    let summary =
+        tokio::task::spawn_blocking(move || reprocess_all(&db, &query, config.dem.as_deref()))
+            .await??;
+
+    Ok(Json(summary))
+}
+
+#[derive(Deserialize)]
+struct UploadParams {
+    /// Store what can be recovered from damaged files instead of rejecting
+    /// them.
+    #[serde(default)]
+    lenient: bool,
+}
+
+#[derive(Serialize)]
+struct UploadSummary {
+    activity: ActivityQuery,
+    #[serde(flatten)]
+    report: tf_parse::Report,
+}
+
 async fn post_activity_index(
     _grant: Grant<Write<Activity>>,
     State(db): State<Database>,
     Path(query): Path<UserQuery>,
-    file: bytes::Bytes,
+    State(config): State<Config>,
+    Query(params): Query<UploadParams>,
+    headers: HeaderMap,
+    body: Body,
 ) -> Result<impl IntoResponse> {
+    let content_encoding = headers
+        .get(header::CONTENT_ENCODING)
+        .map(|x| x.to_str().map(String::from))
+        .transpose()
+        .map_err(|_| Error::BadRequest)?;
+
+    let summary = upload(db, config, query, params, content_encoding, body).await?;
+
+    Ok(Json(summary))
+}
+
+/// Spools an upload to a temporary file, then decodes and stores it. The
+/// file is only read into memory to be stored, after it has been parsed.
+async fn upload(
+    db: Database,
+    config: Config,
+    query: UserQuery,
+    params: UploadParams,
+    content_encoding: Option<String>,
+    body: Body,
+) -> Result<UploadSummary> {
+    let spooled = spool_body(body, config.max_upload_size as u64).await?;
+
     let task = async move {
         let (send, recv) = tokio::sync::oneshot::channel();
 
         rayon::spawn(move || {
-            let _ = send.send(tf_parse::parse(&file));
+            let result =
+                decompress_file(spooled, content_encoding.as_deref(), config.max_upload_size)
+                    .and_then(|file| ingest::parse_file(file, params.lenient));
+
+            let _ = send.send(result);
         });
 
         recv.await
     };
 
-    let parsed = task.await.unwrap()?;
+    let (parsed, report, file) = task.await??;
 
-    let activity_query = ActivityQuery {
-        user_id: query.user_id,
-        id: parsed.id,
-    };
+    let activity = tokio::task::spawn_blocking(move || {
+        ingest::insert(&db, &query, &parsed, &file, config.dem.as_deref())
+    })
+    .await??;
+
+    Ok(UploadSummary { activity, report })
+}
+
+/// Writes an upload to a temporary file as it arrives, rejecting it as soon
+/// as it grows past the limit instead of after it has been received in full.
+async fn spool_body(mut body: Body, limit: u64) -> Result<std::fs::File> {
+    if body.size_hint().lower() > limit {
+        return Err(Error::PayloadTooLarge);
+    }
+
+    let mut file = tokio::fs::File::from_std(tempfile::tempfile()?);
+    let mut size = 0;
+
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| Error::BadRequest)?;
+        size += chunk.len() as u64;
+
+        if size > limit {
+            return Err(Error::PayloadTooLarge);
+        }
+
+        file.write_all(&chunk).await?;
+    }
+
+    file.flush().await?;
+
+    Ok(file.into_std().await)
+}
+
+/// Starts a bulk import of an export archive, see `import::run`. Progress is
+/// reported through the `importProgress` subscription.
+async fn post_activity_import(
+    _grant: Grant<Write<Activity>>,
+    State(db): State<Database>,
+    State(broker): State<Broker>,
+    State(config): State<Config>,
+    Path(query): Path<UserQuery>,
+    body: Body,
+) -> Result<impl IntoResponse> {
+    let running = import::Running::begin(query.user_id).ok_or(Error::Conflict)?;
+    let archive = spool_body(body, config.max_import_size).await?;
 
     tokio::task::spawn_blocking(move || {
-        let root = db.root::<tf_models::user::User>()?;
-
-        if !root.contains_key(&query)? {
-            root.insert(
-                &query,
-                &tf_models::user::User {
-                    name: query.user_id.as_str().into(),
-                    ..Default::default()
+        import::run(
+            &db,
+            &broker,
+            &query,
+            archive,
+            config.max_upload_size,
+            config.max_import_size,
+            config.dem.as_deref(),
+            running,
+        )
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, Point};
+    use tf_models::{
+        activity::{Load, Record},
+        UserId,
+    };
+
+    fn gpx() -> String {
+        fixtures::gpx("running", &[0, 5, 10].map(Point::moving))
+    }
+
+    #[test]
+    fn reprocess_stored_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open(dir.path()).unwrap();
+        let user = UserQuery {
+            user_id: UserId::new(),
+        };
+
+        let file = File {
+            data: gpx().into_bytes(),
+        };
+        let (activity, _) = ingest::parse(&file, false).unwrap();
+        let stored = ingest::insert(&db, &user, &activity, &file, None).unwrap();
+
+        let root = db.root::<User>().unwrap();
+        root.traverse::<Record>().unwrap().remove(&stored).unwrap();
+        root.traverse::<Load>().unwrap().remove(&stored).unwrap();
+
+        let broken = ActivityQuery {
+            user_id: user.user_id,
+            id: "2022060206000000".parse().unwrap(),
+        };
+        root.traverse::<File>()
+            .unwrap()
+            .insert(
+                &broken,
+                &File {
+                    data: b"not an activity".to_vec(),
                 },
-            )?;
-        }
+                &user,
+            )
+            .unwrap();
 
-        root.traverse::<Session>()?
-            .insert(&activity_query, &parsed.session, &query)?;
+        let summary = reprocess_all(&db, &user, None).unwrap();
 
-        root.traverse::<Record>()?
-            .insert(&activity_query, &parsed.record, &query)?;
+        assert_eq!(summary.reprocessed.len(), 1);
+        assert_eq!(summary.reprocessed[0].id, stored.id);
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.failed[0].activity.id, broken.id);
+        assert_eq!(
+            summary.failed[0].error,
+            "Parse error: Unsupported file format."
+        );
 
-        root.traverse::<Vec<Lap>>()?
-            .insert(&activity_query, &parsed.lap, &query)?;
+        let record = root.traverse::<Record>().unwrap().get(&stored).unwrap();
+        assert_eq!(record.unwrap().duration.len(), 3);
+        assert!(root
+            .traverse::<Load>()
+            .unwrap()
+            .get(&stored)
+            .unwrap()
+            .is_some());
+    }
 
-        if let Some(default_gear) = root.traverse::<DefaultGear>()?.key(&query)? {
-            root.traverse::<Session>()?
-                .traverse::<Gear>(&activity_query)?
-                .link(&activity_query, &default_gear)?;
+    fn config() -> Config {
+        Config {
+            max_upload_size: 1 << 16,
+            max_import_size: 1 << 16,
+            inboxes: Vec::new(),
+            dem: None,
         }
+    }
 
-        Ok::<_, tf_database::error::Error>(())
-    })
-    .await??;
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
 
-    Ok(Json(activity_query))
+    #[tokio::test]
+    async fn upload_compressed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open(dir.path()).unwrap();
+        let user = UserQuery {
+            user_id: UserId::new(),
+        };
+
+        let summary = upload(
+            db.clone(),
+            config(),
+            user,
+            UploadParams { lenient: false },
+            Some("gzip".into()),
+            Body::from(gzip(gpx().as_bytes())),
+        )
+        .await
+        .unwrap();
+
+        let root = db.root::<User>().unwrap();
+        let file = root
+            .traverse::<File>()
+            .unwrap()
+            .get(&summary.activity)
+            .unwrap();
+        assert_eq!(file.unwrap().data, gpx().as_bytes());
+
+        let record = root
+            .traverse::<Record>()
+            .unwrap()
+            .get(&summary.activity)
+            .unwrap();
+        assert_eq!(record.unwrap().duration.len(), 3);
+
+        let bomb = gzip(&vec![b' '; 1 << 20]);
+        let result = upload(
+            db.clone(),
+            config(),
+            user,
+            UploadParams { lenient: false },
+            None,
+            Body::from(bomb),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::PayloadTooLarge)));
+
+        let result = upload(
+            db,
+            config(),
+            user,
+            UploadParams { lenient: false },
+            None,
+            Body::from(vec![0; (1 << 16) + 1]),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::PayloadTooLarge)));
+    }
 }
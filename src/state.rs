@@ -1,4 +1,4 @@
-use crate::{cache::ThumbnailCache, Broker, Schema};
+use crate::{cache::ThumbnailCache, config::Config, Broker, Schema};
 use axum::extract::FromRef;
 use tf_auth::{database::Database as AuthDatabase, State as AuthState};
 use tf_database::Database as AppDatabase;
@@ -24,6 +24,7 @@ impl FromRef<AppState> for AuthDatabase {
 #[derive(Clone, FromRef)]
 pub struct AppState {
     pub broker: Broker,
+    pub config: Config,
     pub cache: ThumbnailCache,
     pub state: AuthState,
     pub schema: Schema,